@@ -1,607 +1,946 @@
-use chrono::prelude::*;
+use std::collections::HashMap;
+use std::io;
+use std::iter;
+use std::ops::Range;
 
-use fake::faker::chrono::en::DateTimeAfter;
-use fake::{Fake, Faker};
 use log::debug;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rand::rngs::StdRng;
 
-use crate::models::cli::ResourceType;
+use crate::bundle_writer::{BundleSink, BundleWriter};
+use crate::extensions::option_ext::OptionExt;
+use crate::models::cli::{BundleSemantics, BundleType, EntryRequest, FullUrl, ResourceType};
 use crate::models::enums::id_type::IdType;
-use crate::models::enums::syst_therapy_type::SystTherapyType;
-use crate::utils::{get_ids, get_min_date_time};
-use crate::{
-    condition_svc, medication_svc, observation_svc, patient_svc, procedure_svc, specimen_svc,
-};
-
-use fhirbolt::model::r4b::resources::{
-    Bundle, BundleEntry, Condition, MedicationStatement, Observation, Patient, Procedure, Specimen,
-};
-use fhirbolt::model::r4b::types::{Code, Id};
-
-pub fn get_bundle() -> Bundle {
+use crate::models::id_allocator::IdAllocator;
+use crate::models::scenario::Scenario;
+use crate::resource_generator::get_patient_record;
+use crate::resource_writer::RecordSink;
+use crate::utils::{self, get_bundle_entry_request, get_ids};
+
+use fhirbolt::model::r4b::resources::{Bundle, BundleEntry, MedicationStatementMedication};
+use fhirbolt::model::r4b::types::{Code, Id, Identifier, Reference, Uri};
+use fhirbolt::model::r4b::Resource;
+
+// the characters of a search parameter value, that are URL-encoded (all but the unreserved ones)
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Generates a bundle containing one patient with a complete clinical record
+pub fn get_bundle(ids: &mut IdAllocator, scenario: &Scenario, rng: &mut StdRng) -> Bundle {
     debug!("get_bundle");
 
-    let i: u16 = Faker.fake();
-    let (bundle_id, _) = get_ids(IdType::Id, ResourceType::Bundle, i);
-    let (patient_id, patient_ref_id) = get_ids(IdType::Id, ResourceType::Patient, i);
-    let (condition_id, condition_ref_id) = get_ids(IdType::Id, ResourceType::Condition, i);
-    let (specimen_id, specimen_ref_id) = get_ids(IdType::Id, ResourceType::Specimen, i);
-    let (obs_hist_id, obs_hist_ref_id) = get_ids(IdType::Id, ResourceType::ObservationHistology, i);
-    let (obs_vital_status_id, obs_vital_status_ref_id) =
-        get_ids(IdType::Id, ResourceType::ObservationVitalStatus, i);
-    let (obs_tnmc_id, obs_tnmc_ref_id) = get_ids(IdType::Id, ResourceType::ObservationTNMc, i);
-    let (proc_rt_id, proc_rt_ref_id) = get_ids(IdType::Id, ResourceType::ProcedureRadiotherapy, i);
-    let (proc_op_id, proc_op_ref_id) = get_ids(IdType::Id, ResourceType::ProcedureOperation, i);
-    let (med_stmt_id, med_stmt_ref_id) = get_ids(
-        IdType::Id,
-        ResourceType::SystemicTherapyMedicationStatement,
-        i,
-    );
-
-    let min_date_time = get_min_date_time();
-    let effective_date: DateTime<Utc> = DateTimeAfter(min_date_time).fake();
-
-    let start_date: DateTime<Utc> = DateTimeAfter(min_date_time).fake();
-    let end_date: DateTime<Utc> = DateTimeAfter(start_date).fake();
-
-    let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-    let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-    // let pt1 = pt.clone();
-    // print_fhir_data(pt1, "patient");
-
-    let s = specimen_svc::get_specimen(specimen_id.as_str(), patient_ref_id.as_str());
-    // let s1 = s.clone();
-    // print_fhir_data(s1, "specimen");
-
-    let c = condition_svc::get_condition(
-        condition_id.as_str(),
-        patient_ref_id.as_str(),
-        "C34.0",
-        "C34.0",
-    );
-    // let c1 = c.clone();
-    // print_fhir_data(c1, "condition");
-
-    let ohist = observation_svc::get_histology(
-        obs_hist_id.as_str(),
-        patient_ref_id.as_str(),
-        condition_ref_id.as_str(),
-        specimen_ref_id.as_str(),
-        effective_date.date_naive(),
-        "8140/3",
-    );
-    // let ohist1 = ohist.clone();
-    // print_fhir_data(ohist1, "observation-histology");
-
-    let ovs = observation_svc::get_vital_status(
-        obs_vital_status_id.as_str(),
-        patient_ref_id.as_str(),
-        effective_date.date_naive(),
-    );
-    // let ovs1 = ovs.clone();
-    // print_fhir_data(ovs1, "observation-vitalstatus");
-
-    let otnmc = observation_svc::get_tnmc(
-        &obs_tnmc_id.as_str(),
-        patient_ref_id.as_str(),
-        effective_date.date_naive(),
-    );
-    // let otnmc1 = otnmc.clone();
-    // print_fhir_data(otnmc1, "observation-tnmc");
-
-    let prt = procedure_svc::get_procedure(
-        proc_rt_id.as_str(),
-        patient_ref_id.as_str(),
-        condition_ref_id.as_str(),
-        start_date.date_naive(),
-        end_date.date_naive(),
-        SystTherapyType::RT,
-    );
-    // let prt1 = prt.clone();
-    // print_fhir_data(prt1, "procedure-radiotherapy");
-
-    let pop = procedure_svc::get_procedure(
-        proc_op_id.as_str(),
-        patient_ref_id.as_str(),
-        condition_ref_id.as_str(),
-        start_date.date_naive(),
-        end_date.date_naive(),
-        SystTherapyType::OP,
-    );
-    // let pop1 = pop.clone();
-    // print_fhir_data(pop1, "procedure-operation");
-
-    let m = medication_svc::get_med_statement(
-        med_stmt_id.as_str(),
-        "medicine",
-        patient_ref_id.as_str(),
-        condition_ref_id.as_str(),
-        start_date.date_naive(),
-        end_date.date_naive(),
-    );
-    // let m1 = m.clone();
-    // print_fhir_data(m1, "medication statement");
-
-    let b = assemble_bundle(
-        bundle_id.as_str(),
-        (pt, patient_ref_id.as_str()),
-        (s, specimen_ref_id.as_str()),
-        (c, condition_ref_id.as_str()),
-        (ohist, obs_hist_ref_id.as_str()),
-        (ovs, obs_vital_status_ref_id.as_str()),
-        (otnmc, obs_tnmc_ref_id.as_str()),
-        (pop, proc_op_ref_id.as_str()),
-        (prt, proc_rt_ref_id.as_str()),
-        (m, med_stmt_ref_id.as_str()),
-    );
-    // let b1 = b.clone();
-    // print_fhir_data(b1, "bundle");
-
-    b
+    let i = ids.next_id();
+    let (bundle_id, _) = get_ids(IdType::Id, ResourceType::Bundle, &i);
+    let entries = get_patient_record(ids, scenario, rng);
+
+    get_transaction_bundle(bundle_id.as_str(), entries)
 }
 
-pub fn get_condition_bundle(
+/// Writes the given number of (distinct) patients, each with their own complete clinical record, to
+/// transaction bundles. The patients are generated and written one after the other, hence the memory
+/// use does not grow with the number of patients.
+///
+/// With a bundle size, a new bundle is started whenever the next patient record would make the bundle
+/// exceed the given number of entries; a patient record is never split, so that the references of the
+/// resources resolve within their bundle. The first bundle gets the given id, the others get a newly
+/// allocated one.
+///
+/// The bundles get the type and the entry requests of the given semantics.
+///
+/// Returns the number of bundles written.
+#[allow(clippy::too_many_arguments)]
+pub fn write_cohort_bundles<S: BundleSink>(
+    sink: &mut S,
     bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    condition_tuple: (Condition, &str),
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
-
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let condition = condition_svc::get_bundle_entry(condition_tuple.0, condition_tuple.1);
-
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: vec![patient, condition],
-        ..Default::default()
+    range: Range<u64>,
+    bundle_size: Option<u64>,
+    semantics: &BundleSemantics,
+    ids: &mut IdAllocator,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) -> io::Result<u64> {
+    debug!("write_cohort_bundles");
+
+    let mut bundle_no = 1;
+    let (format, layout) = (sink.format(), sink.layout());
+    let bundle_type = semantics.bundle_type;
+    let mut bundle_writer = BundleWriter::new(
+        sink.open(bundle_id, bundle_no)?,
+        bundle_id,
+        bundle_type,
+        format,
+        layout,
+    )?;
+    let mut bundle_entries = 0;
+    for _ in range {
+        let mut record = get_patient_record(ids, scenario, rng);
+        set_entry_requests(&mut record, semantics);
+        let record_len = record.len() as u64;
+        if bundle_size.is_some_and(|size| bundle_entries > 0 && bundle_entries + record_len > size)
+        {
+            let entries = bundle_writer.entries();
+            sink.close(bundle_writer.finish()?, entries)?;
+            bundle_no += 1;
+            let (next_bundle_id, _) = get_ids(IdType::Id, ResourceType::Bundle, &ids.next_id());
+            bundle_writer = BundleWriter::new(
+                sink.open(&next_bundle_id, bundle_no)?,
+                &next_bundle_id,
+                bundle_type,
+                format,
+                layout,
+            )?;
+            bundle_entries = 0;
+        }
+        bundle_writer.write_entries(record)?;
+        bundle_entries += record_len;
     }
-}
+    let entries = bundle_writer.entries();
+    sink.close(bundle_writer.finish()?, entries)?;
 
-pub fn get_specimen_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    specimen_tuple: (Specimen, &str),
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
+    Ok(bundle_no)
+}
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let specimen = specimen_svc::get_bundle_entry(specimen_tuple.0, specimen_tuple.1);
+/// Writes the given number of patients, each with their own complete clinical record, record by
+/// record (e.g. as NDJSON or as a file per resource); like [write_cohort_bundles], the records are
+/// streamed.
+pub fn write_cohort_records<S: RecordSink>(
+    sink: &mut S,
+    range: Range<u64>,
+    ids: &mut IdAllocator,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) -> io::Result<()> {
+    debug!("write_cohort_records");
+
+    range
+        .into_iter()
+        .try_for_each(|_| sink.write_record(get_patient_record(ids, scenario, rng)))
+}
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: vec![patient, specimen],
-        ..Default::default()
-    }
+pub fn get_transaction_bundle(bundle_id: &str, entries: Vec<BundleEntry>) -> Bundle {
+    get_typed_bundle(bundle_id, BundleType::Transaction, entries)
 }
 
-pub fn get_observation_bundle(
+pub fn get_typed_bundle(
     bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    observation_tuple: (Observation, &str),
+    bundle_type: BundleType,
+    entries: Vec<BundleEntry>,
 ) -> Bundle {
     let id = Id {
         value: Some(bundle_id.to_string()),
         ..Default::default()
     };
     let code = Code {
-        value: Some("transaction".to_string()),
+        value: Some(bundle_type.as_str().to_string()),
         ..Default::default()
     };
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let observation = observation_svc::get_bundle_entry(observation_tuple.0, observation_tuple.1);
-
     Bundle {
         id: Some(id),
         r#type: code,
-        entry: vec![patient, observation],
+        entry: entries,
         ..Default::default()
     }
 }
 
-pub fn get_observation_histology_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    condition_tuple: (Condition, &str),
-    specimen_tuple: (Specimen, &str),
-    observation_tuple: (Observation, &str),
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
+/// Sets the type of a (transaction) bundle and the fullUrls and requests of its entries, which must
+/// only reference each other (or resources outside of any bundle), see [set_entry_requests]
+pub fn set_semantics(bundle: &mut Bundle, semantics: &BundleSemantics) {
+    bundle.r#type = Code {
+        value: Some(semantics.bundle_type.as_str().to_string()),
         ..Default::default()
     };
+    set_entry_requests(&mut bundle.entry, semantics);
+}
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let condition = condition_svc::get_bundle_entry(condition_tuple.0, condition_tuple.1);
-    let specimen = specimen_svc::get_bundle_entry(specimen_tuple.0, specimen_tuple.1);
-    let observation = observation_svc::get_bundle_entry(observation_tuple.0, observation_tuple.1);
+/// Sets the fullUrls and the requests of the entries of a patient record (or of any other entries,
+/// which only reference each other). The entries are PUT by their id, unless the semantics demand
+/// otherwise. An entry gets the URL of its resource at the server base as its fullUrl, against which
+/// the relative references (e.g. `Patient/Patient-id-1`) resolve. Without a server base, or if it is
+/// POSTed or PUT by a search (and hence has no id yet), it gets a `urn:uuid:` fullUrl instead, and
+/// the references to it are rewritten to that fullUrl, so that the server resolves them to the
+/// resource it stores. The entries of a collection get no request at all.
+pub fn set_entry_requests(entries: &mut [BundleEntry], semantics: &BundleSemantics) {
+    let mut urn_uuids = HashMap::new();
+    for entry in entries.iter_mut() {
+        let Some(resource) = entry.resource.as_mut() else {
+            continue;
+        };
+        let Some(id) = resource.id().and_then(|id| id.value.clone()) else {
+            continue;
+        };
+        let resource_type = utils::get_resource_type(resource);
+        let ref_id = format!("{resource_type}/{id}");
+        if matches!(
+            semantics.entry_request,
+            EntryRequest::ConditionalCreate | EntryRequest::ConditionalUpdate
+        ) {
+            set_id_identifier(resource, &id);
+        }
+        let identifier_query = get_identifier_query(resource);
+        let request = match (semantics.entry_request, identifier_query) {
+            (EntryRequest::Put, _) | (EntryRequest::ConditionalUpdate, None) => None,
+            (EntryRequest::Post, _) | (EntryRequest::ConditionalCreate, None) => {
+                Some(get_bundle_entry_request("POST", resource_type))
+            }
+            (EntryRequest::ConditionalCreate, Some(query)) => {
+                let mut request = get_bundle_entry_request("POST", resource_type);
+                request.if_none_exist = Some(query.into());
+                Some(request)
+            }
+            (EntryRequest::ConditionalUpdate, Some(query)) => Some(get_bundle_entry_request(
+                "PUT",
+                &format!("{resource_type}?{query}"),
+            )),
+        };
+
+        entry.full_url = Some(match (&semantics.full_url, &request) {
+            (FullUrl::Base(base), None) => utils::get_full_url(base, &ref_id),
+            _ => {
+                let urn_uuid = utils::get_urn_uuid(&ref_id);
+                urn_uuids.insert(ref_id, urn_uuid.clone());
+                Uri::from(urn_uuid)
+            }
+        });
+        if let Some(request) = request {
+            // the server assigns (or finds) the id, a conditional update even fails on a different id
+            clear_id(resource);
+            entry.request = request.into_some();
+        }
+    }
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: vec![patient, condition, specimen, observation],
-        ..Default::default()
+    for entry in entries.iter_mut() {
+        if semantics.bundle_type == BundleType::Collection {
+            entry.request = None;
+        }
+        let references = entry
+            .resource
+            .as_mut()
+            .map(get_references_mut)
+            .unwrap_or_default();
+        for reference in references {
+            let urn_uuid = reference
+                .reference
+                .as_ref()
+                .and_then(|r| r.value.as_ref())
+                .and_then(|r| urn_uuids.get(r));
+            if let Some(urn_uuid) = urn_uuid {
+                reference.reference = Some(urn_uuid.as_str().into());
+            }
+        }
     }
 }
 
-pub fn get_procedure_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    condition_tuple: (Condition, &str),
-    procedure_tuple: (Procedure, &str),
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
-
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let condition = condition_svc::get_bundle_entry(condition_tuple.0, condition_tuple.1);
-    let procedure = procedure_svc::get_bundle_entry(procedure_tuple.0, procedure_tuple.1);
+/// Returns the search for the resource by its identifier, with the system and the value URL-encoded
+/// (e.g. `identifier=https%3A%2F%2Fwww.cancercoreeurope.eu%2Ffhir%2Fcore%2Fsid%2Fpatient-id|Patient-src-identifier-1`)
+fn get_identifier_query(resource: &Resource) -> Option<String> {
+    get_identifiers(resource).iter().find_map(|identifier| {
+        let system = identifier.system.as_ref()?.value.as_ref()?;
+        let value = identifier.value.as_ref()?.value.as_ref()?;
+        Some(format!(
+            "identifier={}|{}",
+            utf8_percent_encode(system, QUERY_VALUE),
+            utf8_percent_encode(value, QUERY_VALUE)
+        ))
+    })
+}
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: vec![patient, condition, procedure],
+/// Gives a generated resource without an identifier its id as identifier, so that a conditional
+/// request can search it by that, as the server assigns (or finds) the id
+fn set_id_identifier(resource: &mut Resource, id: &str) {
+    if !get_identifiers(resource).is_empty() {
+        return;
+    }
+    let identifier = Identifier {
+        system: Some(utils::get_identifier_url(utils::get_resource_type(
+            resource,
+        ))),
+        value: Some(id.into()),
         ..Default::default()
+    };
+    match resource {
+        Resource::Condition(r) => r.identifier.push(identifier),
+        Resource::Specimen(r) => r.identifier.push(identifier),
+        Resource::Observation(r) => r.identifier.push(identifier),
+        Resource::Procedure(r) => r.identifier.push(identifier),
+        Resource::MedicationStatement(r) => r.identifier.push(identifier),
+        Resource::Medication(r) => r.identifier.push(identifier),
+        _ => {}
     }
 }
 
-pub fn get_med_stmt_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    condition_tuple: (Condition, &str),
-    med_stmt_tuple: (MedicationStatement, &str),
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
+/// Returns the identifiers of a generated resource
+fn get_identifiers(resource: &Resource) -> &[Identifier] {
+    match resource {
+        Resource::Patient(r) => &r.identifier,
+        Resource::Condition(r) => &r.identifier,
+        Resource::Specimen(r) => &r.identifier,
+        Resource::Observation(r) => &r.identifier,
+        Resource::Procedure(r) => &r.identifier,
+        Resource::MedicationStatement(r) => &r.identifier,
+        Resource::Medication(r) => &r.identifier,
+        _ => &[],
+    }
+}
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let condition = condition_svc::get_bundle_entry(condition_tuple.0, condition_tuple.1);
-    let med_stmt = medication_svc::get_bundle_entry(med_stmt_tuple.0, med_stmt_tuple.1);
+/// Removes the id of a generated resource
+fn clear_id(resource: &mut Resource) {
+    match resource {
+        Resource::Patient(r) => r.id = None,
+        Resource::Condition(r) => r.id = None,
+        Resource::Specimen(r) => r.id = None,
+        Resource::Observation(r) => r.id = None,
+        Resource::Procedure(r) => r.id = None,
+        Resource::MedicationStatement(r) => r.id = None,
+        Resource::Medication(r) => r.id = None,
+        _ => {}
+    }
+}
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: vec![patient, condition, med_stmt],
-        ..Default::default()
+/// Returns the references of a generated resource to other resources
+fn get_references_mut(resource: &mut Resource) -> Vec<&mut Reference> {
+    match resource {
+        Resource::Condition(c) => vec![c.subject.as_mut()],
+        Resource::Specimen(s) => s
+            .subject
+            .as_deref_mut()
+            .into_iter()
+            .chain(s.parent.iter_mut())
+            .collect(),
+        Resource::Observation(o) => o
+            .subject
+            .as_deref_mut()
+            .into_iter()
+            .chain(o.focus.iter_mut())
+            .chain(o.specimen.as_deref_mut())
+            .collect(),
+        Resource::Procedure(p) => iter::once(p.subject.as_mut())
+            .chain(p.reason_reference.iter_mut())
+            .collect(),
+        Resource::MedicationStatement(m) => {
+            let medication = match &mut m.medication {
+                MedicationStatementMedication::Reference(r) => Some(r.as_mut()),
+                _ => None,
+            };
+            medication
+                .into_iter()
+                .chain(iter::once(m.subject.as_mut()))
+                .chain(m.reason_reference.iter_mut())
+                .collect()
+        }
+        _ => vec![],
     }
 }
 
-fn assemble_bundle(
-    id: &str,
-    patient_tuple: (Patient, &str),
-    specimen_tuple: (Specimen, &str),
-    condition_tuple: (Condition, &str),
-    obs_histology_tuple: (Observation, &str),
-    obs_vital_status_tuple: (Observation, &str),
-    obs_tnmc_tuple: (Observation, &str),
-    proc_op_tuple: (Procedure, &str),
-    proc_rt_tuple: (Procedure, &str),
-    med_stmt_tuple: (MedicationStatement, &str),
-) -> Bundle {
-    let id = Id {
-        value: Some(id.to_string()),
-        ..Default::default()
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use fhirbolt::model::r4b::resources::{
+        MedicationStatementEffective, ObservationEffective, Procedure, ProcedurePerformed,
     };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
+    use fhirbolt::model::r4b::Resource;
+    use fhirbolt::serde::xml;
+    use rand::SeedableRng;
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::models::cli::{Format, IdStrategy, Layout};
+    use crate::models::enums::syst_therapy_type::SystTherapyType;
+    use crate::utils::{
+        CLINICAL_STAGE_GROUP_LOINC_CODE, PATHOLOGIC_STAGE_GROUP_LOINC_CODE,
+        TNM_R_SYMBOL_LOINC_CODE, TNM_Y_SYMBOL_LOINC_CODE,
     };
+    use fhirbolt::model::r4b::types::{CodeableConcept, DateTime, Period};
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let specimen = specimen_svc::get_bundle_entry(specimen_tuple.0, specimen_tuple.1);
-    let condition = condition_svc::get_bundle_entry(condition_tuple.0, condition_tuple.1);
+    impl BundleSink for Vec<Vec<u8>> {
+        type Writer = Vec<u8>;
 
-    let observation =
-        observation_svc::get_bundle_entry(obs_histology_tuple.0, obs_histology_tuple.1);
-    let vital_status =
-        observation_svc::get_bundle_entry(obs_vital_status_tuple.0, obs_vital_status_tuple.1);
-    let tnmc = observation_svc::get_bundle_entry(obs_tnmc_tuple.0, obs_tnmc_tuple.1);
+        fn format(&self) -> Format {
+            Format::Xml
+        }
 
-    let procedure = procedure_svc::get_bundle_entry(proc_rt_tuple.0, proc_rt_tuple.1);
-    let operation = procedure_svc::get_bundle_entry(proc_op_tuple.0, proc_op_tuple.1);
+        fn layout(&self) -> Layout {
+            Layout::Plain
+        }
 
-    let med_stmt = medication_svc::get_bundle_entry(med_stmt_tuple.0, med_stmt_tuple.1);
+        fn open(&mut self, _bundle_id: &str, _bundle_no: u64) -> io::Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: vec![
-            patient,
-            specimen,
-            condition,
-            observation,
-            vital_status,
-            tnmc,
-            procedure,
-            operation,
-            med_stmt,
-        ],
-        ..Default::default()
+        fn close(&mut self, writer: Vec<u8>, _entries: u64) -> io::Result<()> {
+            self.push(writer);
+            Ok(())
+        }
     }
-}
-
-pub fn get_patients_bundle(bundle_id: &str, patient_tuples: Vec<(Patient, String)>) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
-
-    let patient_entries: Vec<BundleEntry> = patient_tuples
-        .iter()
-        .map(|pt_tuple| patient_svc::get_bundle_entry(pt_tuple.0.clone(), pt_tuple.1.as_str()))
-        .collect();
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: patient_entries,
-        ..Default::default()
+    fn get_cohort_bundles(number: u64, bundle_size: Option<u64>) -> Vec<Bundle> {
+        write_cohort(number, bundle_size, &Scenario::default())
+            .iter()
+            .map(|xml| xml::from_slice(xml, None).expect("bundle is valid XML"))
+            .collect()
     }
-}
-
-pub fn get_conditions_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    condition_tuples: Vec<(Condition, String)>,
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let condition_entries: Vec<BundleEntry> = condition_tuples
-        .iter()
-        .map(|c_tuple| condition_svc::get_bundle_entry(c_tuple.0.clone(), c_tuple.1.as_str()))
-        .collect();
-
-    let mut entries = vec![patient];
-    entries.extend(condition_entries);
-
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: entries,
-        ..Default::default()
+    /// Writes the cohort (with the seed 1) and returns the serialized bundles
+    fn write_cohort(number: u64, bundle_size: Option<u64>, scenario: &Scenario) -> Vec<Vec<u8>> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut ids =
+            IdAllocator::new(IdStrategy::Sequential, None, 1).expect("allocator is valid");
+        let mut sink: Vec<Vec<u8>> = Vec::new();
+        let bundles = write_cohort_bundles(
+            &mut sink,
+            "Bundle-id-1",
+            0..number,
+            bundle_size,
+            &BundleSemantics {
+                full_url: FullUrl::Base(FHIR_BASE.to_string()),
+                ..Default::default()
+            },
+            &mut ids,
+            scenario,
+            &mut rng,
+        )
+        .expect("bundles are written");
+        assert_eq!(sink.len() as u64, bundles, "not every bundle is closed");
+        sink
     }
-}
 
-pub fn get_specimens_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    specimen_tuples: Vec<(Specimen, String)>,
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
+    fn get_cohort_bundle(number: u64) -> Bundle {
+        let mut bundles = get_cohort_bundles(number, None);
+        assert_eq!(bundles.len(), 1, "number of bundles does not match");
+        bundles.remove(0)
+    }
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let specimen_entries: Vec<BundleEntry> = specimen_tuples
-        .iter()
-        .map(|s_tuple| specimen_svc::get_bundle_entry(s_tuple.0.clone(), s_tuple.1.as_str()))
-        .collect();
+    #[test]
+    fn test_write_cohort_bundles_has_a_complete_record_per_patient() {
+        let bundle = get_cohort_bundle(3);
+
+        let patient_ids: Vec<String> = bundle
+            .entry
+            .iter()
+            .filter_map(|e| match &e.resource {
+                Some(Resource::Patient(pt)) => pt.id.clone().and_then(|id| id.value),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(patient_ids.len(), 3, "number of patients does not match");
+        // a recurrence adds a TNM classification to the record
+        let recurrences = bundle
+            .entry
+            .iter()
+            .filter(|e| {
+                get_tnm_codes(e).is_some_and(|(_, c)| c.contains(&TNM_R_SYMBOL_LOINC_CODE.into()))
+            })
+            .count();
+        // as does the parent of a derivative specimen
+        let parents = bundle
+            .entry
+            .iter()
+            .filter(|e| matches!(&e.resource, Some(Resource::Specimen(s)) if !s.parent.is_empty()))
+            .count();
+        // and a combined therapy has a medication statement per substance
+        let med_stmts = bundle
+            .entry
+            .iter()
+            .filter(|e| matches!(&e.resource, Some(Resource::MedicationStatement(_))))
+            .count();
+        assert_eq!(
+            bundle.entry.len(),
+            3 * 9 + med_stmts + recurrences + parents,
+            "number of entries does not match"
+        );
+
+        let conditions_per_patient = patient_ids.iter().all(|pt_id| {
+            bundle.entry.iter().any(|e| match &e.resource {
+                Some(Resource::Condition(c)) => {
+                    c.subject.reference.clone().and_then(|r| r.value)
+                        == Some(format!("Patient/{pt_id}"))
+                }
+                _ => false,
+            })
+        });
+        assert!(
+            conditions_per_patient,
+            "every patient should have a condition"
+        );
+    }
 
-    let mut entries = vec![patient];
-    entries.extend(specimen_entries);
+    /// The code of a TNM observation and the codes of its components
+    fn get_tnm_codes(entry: &BundleEntry) -> Option<(String, Vec<String>)> {
+        let Some(Resource::Observation(o)) = &entry.resource else {
+            return None;
+        };
+        let get_code = |c: &CodeableConcept| c.coding[0].code.clone().and_then(|c| c.value);
+        // the other observations have no components
+        if o.component.is_empty() {
+            return None;
+        }
+        let components = o
+            .component
+            .iter()
+            .filter_map(|c| get_code(&c.code))
+            .collect();
+        Some((get_code(&o.code)?, components))
+    }
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: entries,
-        ..Default::default()
+    #[test]
+    fn test_get_patient_record_adds_the_plausible_tnm_symbols() {
+        let scenario = Scenario::from_toml("neoadjuvant_rate = 1.0\nrecurrence_rate = 1.0")
+            .expect("scenario is valid");
+        let mut ids =
+            IdAllocator::new(IdStrategy::Sequential, None, 1).expect("allocator is valid");
+        let mut rng = StdRng::seed_from_u64(1);
+        let tnms: Vec<(String, Vec<String>)> = get_patient_record(&mut ids, &scenario, &mut rng)
+            .iter()
+            .filter_map(get_tnm_codes)
+            .collect();
+
+        let has_symbol = |code: &str, symbol: &str| {
+            tnms.iter()
+                .filter(|(c, _)| c == code)
+                .map(|(_, components)| components.contains(&symbol.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            has_symbol(PATHOLOGIC_STAGE_GROUP_LOINC_CODE, TNM_Y_SYMBOL_LOINC_CODE),
+            vec![true],
+            "the pTNM after a neoadjuvant therapy has no y-symbol"
+        );
+        assert_eq!(
+            has_symbol(CLINICAL_STAGE_GROUP_LOINC_CODE, TNM_Y_SYMBOL_LOINC_CODE),
+            vec![false, false],
+            "a cTNM has a y-symbol"
+        );
+        assert_eq!(
+            has_symbol(CLINICAL_STAGE_GROUP_LOINC_CODE, TNM_R_SYMBOL_LOINC_CODE),
+            vec![false, true],
+            "only the recurrence has an r-symbol"
+        );
     }
-}
 
-pub fn get_histologies_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    condition_tuple: (Condition, &str),
-    specimen_tuple: (Specimen, &str),
-    observation_tuples: Vec<(Observation, String)>,
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
+    #[test]
+    fn test_get_patient_record_has_no_y_symbol_after_a_surveillance() {
+        let scenario = Scenario::from_toml(
+            "neoadjuvant_rate = 1.0\n[resources_per_patient]\nradiotherapies = 0\n[weights.syst_therapy_type]\nWW = 1",
+        )
+        .expect("scenario is valid");
+        for seed in 0..20 {
+            let mut ids =
+                IdAllocator::new(IdStrategy::Sequential, None, 1).expect("allocator is valid");
+            let mut rng = StdRng::seed_from_u64(seed);
+            let ptnm = get_patient_record(&mut ids, &scenario, &mut rng)
+                .iter()
+                .filter_map(get_tnm_codes)
+                .find(|(code, _)| code == PATHOLOGIC_STAGE_GROUP_LOINC_CODE)
+                .expect("operated patient has a pTNM");
+            assert!(
+                !ptnm.1.contains(&TNM_Y_SYMBOL_LOINC_CODE.to_string()),
+                "the pTNM after a watchful waiting has a y-symbol"
+            );
+        }
+    }
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let condition = condition_svc::get_bundle_entry(condition_tuple.0, condition_tuple.1);
-    let specimen = specimen_svc::get_bundle_entry(specimen_tuple.0, specimen_tuple.1);
-    // let observation = observation_svc::get_bundle_entry(observation_tuple.0, observation_tuple.1);
-    let hist_entries: Vec<BundleEntry> = observation_tuples
-        .iter()
-        .map(|hist_tuple| {
-            observation_svc::get_bundle_entry(hist_tuple.0.clone(), hist_tuple.1.as_str())
-        })
-        .collect();
+    fn is_operation(procedure: &Procedure) -> bool {
+        procedure
+            .category
+            .as_ref()
+            .and_then(|c| c.coding[0].code.clone())
+            .and_then(|c| c.value)
+            .is_some_and(|code| code == SystTherapyType::OP.to_string())
+    }
 
-    let mut entries = vec![patient, condition, specimen];
-    entries.extend(hist_entries);
+    #[test]
+    fn test_get_patient_record_dates_the_ptnm_at_the_operation() {
+        let mut ids =
+            IdAllocator::new(IdStrategy::Sequential, None, 1).expect("allocator is valid");
+        let mut rng = StdRng::seed_from_u64(1);
+        let record = get_patient_record(&mut ids, &Scenario::default(), &mut rng);
+
+        let operation_date = record.iter().find_map(|e| match &e.resource {
+            Some(Resource::Procedure(p)) if is_operation(p) => match &p.performed {
+                Some(ProcedurePerformed::Period(period)) => {
+                    period.start.clone().and_then(|d| d.value)
+                }
+                _ => None,
+            },
+            _ => None,
+        });
+        let ptnm_date = record
+            .iter()
+            .filter(|e| {
+                get_tnm_codes(e).is_some_and(|(code, _)| code == PATHOLOGIC_STAGE_GROUP_LOINC_CODE)
+            })
+            .find_map(|e| match &e.resource {
+                Some(Resource::Observation(o)) => match &o.effective {
+                    Some(ObservationEffective::DateTime(d)) => d.value.clone(),
+                    _ => None,
+                },
+                _ => None,
+            });
+        assert!(operation_date.is_some(), "record has no operation");
+        assert_eq!(
+            ptnm_date, operation_date,
+            "pTNM is not dated at the operation"
+        );
+    }
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: entries,
-        ..Default::default()
+    /// The start and end dates of the (radio or systemic) therapies of the record, i.e. of the
+    /// procedures other than operations and of the medication statements of medications taken
+    fn get_therapy_periods(record: &[BundleEntry]) -> Vec<(String, String)> {
+        let get_dates = |period: &Period| {
+            let get_date =
+                |d: &Option<DateTime>| d.clone().and_then(|d| d.value).unwrap_or_default();
+            (get_date(&period.start), get_date(&period.end))
+        };
+        record
+            .iter()
+            .filter_map(|e| match &e.resource {
+                Some(Resource::Procedure(p)) if !is_operation(p) => match &p.performed {
+                    Some(ProcedurePerformed::Period(period)) => Some(get_dates(period)),
+                    _ => None,
+                },
+                Some(Resource::MedicationStatement(m))
+                    if m.status.value.as_deref() == Some("completed") =>
+                {
+                    match &m.effective {
+                        Some(MedicationStatementEffective::Period(period)) => {
+                            Some(get_dates(period))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect()
     }
-}
 
-pub fn get_vital_statuses_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    obs_vs_tuples: Vec<(Observation, String)>,
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
+    #[test]
+    fn test_get_patient_record_dates_the_therapy_by_the_y_symbol() {
+        let scenario = Scenario::from_toml("neoadjuvant_rate = 0.5").expect("scenario is valid");
+        let (mut neoadjuvant, mut adjuvant) = (0, 0);
+        for seed in 0..50 {
+            let mut ids =
+                IdAllocator::new(IdStrategy::Sequential, None, 1).expect("allocator is valid");
+            let mut rng = StdRng::seed_from_u64(seed);
+            let record = get_patient_record(&mut ids, &scenario, &mut rng);
+
+            let operation_date = record
+                .iter()
+                .find_map(|e| match &e.resource {
+                    Some(Resource::Procedure(p)) if is_operation(p) => match &p.performed {
+                        Some(ProcedurePerformed::Period(period)) => {
+                            period.start.clone().and_then(|d| d.value)
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .expect("record has an operation");
+            let y = record
+                .iter()
+                .filter_map(get_tnm_codes)
+                .find(|(code, _)| code == PATHOLOGIC_STAGE_GROUP_LOINC_CODE)
+                .expect("operated patient has a pTNM")
+                .1
+                .contains(&TNM_Y_SYMBOL_LOINC_CODE.to_string());
+            let therapies = get_therapy_periods(&record);
+            assert!(!therapies.is_empty(), "record has no therapy");
+
+            for (start, end) in therapies {
+                if y {
+                    assert!(end < operation_date, "neoadjuvant therapy ends at {end}, after the operation at {operation_date}");
+                } else {
+                    assert!(start > operation_date, "adjuvant therapy starts at {start}, before the operation at {operation_date}");
+                }
+            }
+            if y {
+                neoadjuvant += 1;
+            } else {
+                adjuvant += 1;
+            }
+        }
+        assert!(
+            neoadjuvant > 0 && adjuvant > 0,
+            "not both therapy orders are generated"
+        );
+    }
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let vs_entries: Vec<BundleEntry> = obs_vs_tuples
-        .iter()
-        .map(|vs_tuple| observation_svc::get_bundle_entry(vs_tuple.0.clone(), vs_tuple.1.as_str()))
-        .collect();
+    #[test]
+    fn test_write_cohort_bundles_is_the_same_on_any_day_if_canonical() {
+        let write_on = |today: NaiveDate, canonical: bool| {
+            let mut scenario = Scenario::default();
+            scenario.pin_max_date(today, canonical);
+            write_cohort(5, None, &scenario)
+        };
+        let today = NaiveDate::from_ymd_opt(2026, 10, 18).expect("date is valid");
+        let next_year = NaiveDate::from_ymd_opt(2027, 10, 18).expect("date is valid");
+
+        assert_eq!(
+            write_on(today, true),
+            write_on(next_year, true),
+            "canonical data depends on the day"
+        );
+        assert_ne!(
+            write_on(today, false),
+            write_on(next_year, false),
+            "data does not depend on the day"
+        );
+    }
 
-    let mut entries = vec![patient];
-    entries.extend(vs_entries);
+    #[test]
+    fn test_write_cohort_bundles_has_unique_ids() {
+        let bundle = get_cohort_bundle(200);
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: entries,
-        ..Default::default()
+        let full_urls: HashSet<String> = bundle
+            .entry
+            .iter()
+            .filter_map(|e| e.full_url.clone().and_then(|url| url.value))
+            .collect();
+        assert_eq!(full_urls.len(), bundle.entry.len(), "ids are not unique");
     }
-}
 
-pub fn get_tnmcs_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    obs_tnmc_tuples: Vec<(Observation, String)>,
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
+    #[test]
+    fn test_get_patient_record_references_resolve_within_the_record() {
+        let scenario = Scenario::from_toml(
+            "recurrence_rate = 0.5\n[resources_per_patient]\nspecimens = 2\ntnmcs = 2\nradiotherapies = 2\noperations = 2\nmedication_statements = 2",
+        )
+        .expect("scenario is valid");
+        for seed in 0..20 {
+            let mut ids =
+                IdAllocator::new(IdStrategy::Sequential, None, 1).expect("allocator is valid");
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut record = get_patient_record(&mut ids, &scenario, &mut rng);
+
+            let urls: HashSet<String> = record.iter().map(|e| get_request(e).1).collect();
+            assert_eq!(urls.len(), record.len(), "ids are not unique");
+            for entry in &mut record {
+                let resource = entry.resource.as_mut().expect("entry has a resource");
+                for reference in get_references_mut(resource) {
+                    let reference = reference.reference.clone().and_then(|r| r.value);
+                    assert!(
+                        reference.as_ref().is_some_and(|r| urls.contains(r)),
+                        "{reference:?} does not resolve within the record"
+                    );
+                }
+            }
+        }
+    }
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let vs_entries: Vec<BundleEntry> = obs_tnmc_tuples
-        .iter()
-        .map(|tnmc_tuple| {
-            observation_svc::get_bundle_entry(tnmc_tuple.0.clone(), tnmc_tuple.1.as_str())
-        })
-        .collect();
+    #[test]
+    fn test_write_cohort_bundles_keeps_patient_records_together() {
+        // a patient record has 10 entries (11 with a recurrence or a derivative specimen, 12 with both),
+        // hence 2 records fit into a bundle of 24 entries
+        let bundles = get_cohort_bundles(5, Some(24));
+        assert_eq!(bundles.len(), 3, "number of bundles does not match");
+
+        let bundle_ids: HashSet<String> = bundles
+            .iter()
+            .filter_map(|b| b.id.clone().and_then(|id| id.value))
+            .collect();
+        assert_eq!(bundle_ids.len(), 3, "bundle ids are not unique");
+
+        for bundle in bundles {
+            assert!(bundle.entry.len() <= 24, "bundle is too large");
+            let full_urls: Vec<String> = bundle
+                .entry
+                .iter()
+                .filter_map(|e| e.full_url.clone().and_then(|url| url.value))
+                .collect();
+            for e in &bundle.entry {
+                if let Some(Resource::Condition(c)) = &e.resource {
+                    let subject = c
+                        .subject
+                        .reference
+                        .clone()
+                        .and_then(|r| r.value)
+                        .expect("condition has a subject");
+                    let patient_id = subject.trim_start_matches("Patient/");
+                    assert!(
+                        full_urls
+                            .iter()
+                            .any(|url| url.ends_with(&format!("/{patient_id}"))),
+                        "{subject} is not in the bundle of its condition"
+                    );
+                }
+            }
+        }
+    }
 
-    let mut entries = vec![patient];
-    entries.extend(vs_entries);
+    const FHIR_BASE: &str = "http://localhost:8080/fhir";
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: entries,
-        ..Default::default()
+    fn get_record(semantics: BundleSemantics) -> Vec<BundleEntry> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut ids =
+            IdAllocator::new(IdStrategy::Sequential, None, 1).expect("allocator is valid");
+        let mut record = get_patient_record(&mut ids, &Scenario::default(), &mut rng);
+        set_entry_requests(&mut record, &semantics);
+        record
     }
-}
-
-pub fn get_procedures_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    condition_tuple: (Condition, &str),
-    procedure_tuples: Vec<(Procedure, String)>,
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let condition = condition_svc::get_bundle_entry(condition_tuple.0, condition_tuple.1);
-    let proc_entries: Vec<BundleEntry> = procedure_tuples
-        .iter()
-        .map(|proc_tuple| {
-            procedure_svc::get_bundle_entry(proc_tuple.0.clone(), proc_tuple.1.as_str())
-        })
-        .collect();
+    fn get_full_url(entry: &BundleEntry) -> String {
+        entry
+            .full_url
+            .clone()
+            .and_then(|url| url.value)
+            .unwrap_or_default()
+    }
 
-    let mut entries = vec![patient, condition];
-    entries.extend(proc_entries);
+    fn get_subject(entry: &BundleEntry) -> Option<String> {
+        match &entry.resource {
+            Some(Resource::Condition(c)) => c.subject.reference.clone().and_then(|r| r.value),
+            _ => None,
+        }
+    }
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: entries,
-        ..Default::default()
+    fn get_request(entry: &BundleEntry) -> (String, String) {
+        let request = entry.request.as_ref().expect("entry has a request");
+        (
+            request.method.value.clone().unwrap_or_default(),
+            request.url.value.clone().unwrap_or_default(),
+        )
     }
-}
 
-pub fn get_med_stmts_bundle(
-    bundle_id: &str,
-    patient_tuple: (Patient, &str),
-    condition_tuple: (Condition, &str),
-    med_stmt_tuples: Vec<(MedicationStatement, String)>,
-) -> Bundle {
-    let id = Id {
-        value: Some(bundle_id.to_string()),
-        ..Default::default()
-    };
-    let code = Code {
-        value: Some("transaction".to_string()),
-        ..Default::default()
-    };
+    #[test]
+    fn test_set_entry_requests_creates_the_resources_if_none_exist() {
+        let record = get_record(BundleSemantics {
+            bundle_type: BundleType::Transaction,
+            entry_request: EntryRequest::ConditionalCreate,
+            ..Default::default()
+        });
+
+        for entry in &record {
+            let request = entry.request.as_ref().expect("entry has a request");
+            assert_eq!(
+                request.method.value.as_deref(),
+                Some("POST"),
+                "method does not match"
+            );
+            let query = request
+                .if_none_exist
+                .as_ref()
+                .and_then(|q| q.value.clone())
+                .expect("entry has an ifNoneExist");
+            assert!(
+                query.starts_with("identifier=https%3A%2F%2F"),
+                "query is not URL-encoded: {query}"
+            );
+        }
+    }
 
-    let patient = patient_svc::get_bundle_entry(patient_tuple.0, patient_tuple.1);
-    let condition = condition_svc::get_bundle_entry(condition_tuple.0, condition_tuple.1);
-    let ms_entries: Vec<BundleEntry> = med_stmt_tuples
-        .iter()
-        .map(|ms_tuple| medication_svc::get_bundle_entry(ms_tuple.0.clone(), ms_tuple.1.as_str()))
-        .collect();
+    #[test]
+    fn test_set_entry_requests_builds_the_full_urls() {
+        let record = get_record(BundleSemantics {
+            full_url: FullUrl::Base(format!("{FHIR_BASE}/")),
+            ..Default::default()
+        });
+        assert_eq!(
+            get_full_url(&record[0]),
+            format!("{FHIR_BASE}/Patient/Patient-id-1"),
+            "patient fullUrl does not match"
+        );
+        let subjects: Vec<String> = record.iter().filter_map(get_subject).collect();
+        assert_eq!(
+            subjects,
+            vec!["Patient/Patient-id-1"],
+            "condition reference does not match"
+        );
+
+        // without a server base, the references point to the urn:uuid of the patient entry
+        let record = get_record(BundleSemantics::default());
+        let patient_url = get_full_url(&record[0]);
+        assert!(
+            patient_url.starts_with("urn:uuid:"),
+            "patient has no urn:uuid: {patient_url}"
+        );
+        assert_eq!(
+            get_request(&record[0]),
+            ("PUT".to_string(), "Patient/Patient-id-1".to_string()),
+            "patient request does not match"
+        );
+        let subjects: Vec<String> = record.iter().filter_map(get_subject).collect();
+        assert_eq!(
+            subjects,
+            vec![patient_url],
+            "condition reference does not match"
+        );
+    }
 
-    let mut entries = vec![patient, condition];
-    entries.extend(ms_entries);
+    #[test]
+    fn test_set_entry_requests_posts_with_urn_uuids() {
+        let record = get_record(BundleSemantics {
+            bundle_type: BundleType::Transaction,
+            entry_request: EntryRequest::Post,
+            ..Default::default()
+        });
+
+        let patient_url = record[0].full_url.clone().and_then(|url| url.value);
+        assert!(
+            patient_url
+                .as_ref()
+                .is_some_and(|url| url.starts_with("urn:uuid:")),
+            "patient has no urn:uuid: {patient_url:?}"
+        );
+        assert_eq!(
+            get_request(&record[0]),
+            ("POST".to_string(), "Patient".to_string()),
+            "patient request does not match"
+        );
+        for entry in &record {
+            assert!(
+                entry.resource.as_ref().and_then(|r| r.id()).is_none(),
+                "a POSTed resource has an id"
+            );
+            if let Some(Resource::Condition(c)) = &entry.resource {
+                assert_eq!(
+                    c.subject.reference.clone().and_then(|r| r.value),
+                    patient_url,
+                    "condition does not reference the patient entry"
+                );
+            }
+        }
+    }
 
-    Bundle {
-        id: Some(id),
-        r#type: code,
-        entry: entries,
-        ..Default::default()
+    #[test]
+    fn test_set_entry_requests_updates_the_resources_by_identifier() {
+        let record = get_record(BundleSemantics {
+            bundle_type: BundleType::Transaction,
+            entry_request: EntryRequest::ConditionalUpdate,
+            ..Default::default()
+        });
+
+        let (method, url) = get_request(&record[0]);
+        assert_eq!(method, "PUT", "patient method does not match");
+        assert_eq!(
+            url,
+            "Patient?identifier=https%3A%2F%2Fwww.cancercoreeurope.eu%2Ffhir%2Fcore%2Fsid%2Fpatient-id|Patient-src-identifier-1",
+            "patient url does not match"
+        );
+        // the other resources are PUT by their id, which is kept as their identifier
+        let (method, url) = get_request(&record[1]);
+        assert_eq!(method, "PUT", "specimen method does not match");
+        assert!(
+            url.starts_with("Specimen?identifier=https%3A%2F%2Fwww.cancercoreeurope.eu%2Ffhir%2Fcore%2Fsid%2Fspecimen-id|Specimen-id-"),
+            "specimen url does not match: {url}"
+        );
+        for entry in &record {
+            let resource = entry.resource.as_ref().expect("entry has a resource");
+            assert_eq!(
+                get_identifiers(resource).len(),
+                1,
+                "identifiers do not match"
+            );
+            assert!(resource.id().is_none(), "a conditional resource has an id");
+        }
+
+        let collection = get_record(BundleSemantics {
+            bundle_type: BundleType::Collection,
+            ..Default::default()
+        });
+        assert!(
+            collection.iter().all(|e| e.request.is_none()),
+            "entries of a collection have requests"
+        );
     }
 }
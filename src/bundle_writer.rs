@@ -0,0 +1,214 @@
+use std::io::{self, Write};
+
+use fhirbolt::model::r4b::resources::{Bundle, BundleEntry};
+use fhirbolt::serde::{json, xml};
+
+use crate::bundle_svc;
+use crate::models::cli::{BundleType, Format, Layout};
+
+const XML_BUNDLE_END: &str = "</Bundle>";
+const JSON_BUNDLE_END: &str = "]}";
+const PRETTY_JSON_BUNDLE_END: &str = "\n  ]\n}";
+
+/// The destination of the bundles written by [crate::bundle_svc::write_cohort_bundles]. It opens a
+/// writer per bundle and gets it back, once the bundle is complete.
+pub trait BundleSink {
+    type Writer: Write;
+
+    /// The format the bundles are written in
+    fn format(&self) -> Format;
+
+    /// The layout the bundles are written in
+    fn layout(&self) -> Layout;
+
+    /// Opens the writer for the bundle with the given id and (1-based) number
+    fn open(&mut self, bundle_id: &str, bundle_no: u64) -> io::Result<Self::Writer>;
+
+    /// Gets the writer of a complete bundle back, along with the number of its entries
+    fn close(&mut self, writer: Self::Writer, entries: u64) -> io::Result<()>;
+}
+
+/// Writes a bundle entry by entry, so that a bundle with (hundreds of) thousands of
+/// entries never has to be held in memory. The written XML (or JSON) is the same as the one of the
+/// complete bundle serialized at once (followed by a line break).
+pub struct BundleWriter<W: Write> {
+    writer: W,
+    bundle_id: String,
+    bundle_type: BundleType,
+    format: Format,
+    pretty: bool,
+    // the serialized bundle up to (excluding) the first entry
+    header: String,
+    entries: u64,
+}
+
+impl<W: Write> BundleWriter<W> {
+    /// Creates the writer and writes the start of the bundle (up to the first entry)
+    pub fn new(
+        mut writer: W,
+        bundle_id: &str,
+        bundle_type: BundleType,
+        format: Format,
+        layout: Layout,
+    ) -> io::Result<Self> {
+        let pretty = layout.is_pretty();
+        let bundle = get_bundle(bundle_id, bundle_type, vec![], format, pretty)?;
+        let header = match format {
+            Format::Xml => bundle.strip_suffix(XML_BUNDLE_END).map(String::from),
+            // a bundle without entries has no entry array, hence it is opened here
+            Format::Json if pretty => bundle
+                .strip_suffix("\n}")
+                .map(|start| format!("{start},\n  \"entry\": [\n")),
+            Format::Json => bundle
+                .strip_suffix('}')
+                .map(|start| format!("{start},\"entry\":[")),
+        }
+        .ok_or_else(|| invalid_data("bundle has no end"))?;
+        writer.write_all(header.as_bytes())?;
+
+        Ok(Self {
+            writer,
+            bundle_id: bundle_id.to_string(),
+            bundle_type,
+            format,
+            pretty,
+            header,
+            entries: 0,
+        })
+    }
+
+    pub fn write_entry(&mut self, entry: BundleEntry) -> io::Result<()> {
+        // the entry is serialized as part of a bundle (the serializer only accepts resources), the
+        // start and the end of the bundle are then cut off
+        let bundle = get_bundle(
+            &self.bundle_id,
+            self.bundle_type,
+            vec![entry],
+            self.format,
+            self.pretty,
+        )?;
+        let entry_data = bundle
+            .strip_prefix(self.header.as_str())
+            .and_then(|data| data.strip_suffix(self.get_bundle_end()))
+            .ok_or_else(|| invalid_data("entry is not enclosed by the bundle"))?;
+        if self.format == Format::Json && self.entries > 0 {
+            let separator: &[u8] = if self.pretty { b",\n" } else { b"," };
+            self.writer.write_all(separator)?;
+        }
+        self.entries += 1;
+        self.writer.write_all(entry_data.as_bytes())
+    }
+
+    pub fn write_entries<I>(&mut self, entries: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = BundleEntry>,
+    {
+        entries
+            .into_iter()
+            .try_for_each(|entry| self.write_entry(entry))
+    }
+
+    /// The number of entries written so far
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// Writes the end of the bundle and returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(self.get_bundle_end().as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Returns what follows the last entry of the bundle
+    fn get_bundle_end(&self) -> &'static str {
+        match self.format {
+            Format::Xml => XML_BUNDLE_END,
+            Format::Json if self.pretty => PRETTY_JSON_BUNDLE_END,
+            Format::Json => JSON_BUNDLE_END,
+        }
+    }
+}
+
+fn get_bundle(
+    bundle_id: &str,
+    bundle_type: BundleType,
+    entries: Vec<BundleEntry>,
+    format: Format,
+    pretty: bool,
+) -> io::Result<String> {
+    let bundle: Bundle = bundle_svc::get_typed_bundle(bundle_id, bundle_type, entries);
+    let data = match format {
+        Format::Xml => xml::to_string(&bundle, None).map_err(|e| invalid_data(&e.to_string())),
+        Format::Json if pretty => {
+            json::to_string_pretty(&bundle, None).map_err(|e| invalid_data(&e.to_string()))
+        }
+        Format::Json => json::to_string(&bundle, None).map_err(|e| invalid_data(&e.to_string())),
+    }?;
+    Ok(data.trim_end().to_string())
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Cannot serialize bundle. Reason: {msg}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use fhirbolt::model::r4b::resources::Patient;
+    use fhirbolt::model::r4b::types::Id;
+
+    use super::*;
+    use crate::patient_svc;
+
+    fn get_entry(i: u64) -> BundleEntry {
+        let patient = Patient {
+            id: Some(Id {
+                value: Some(format!("Patient-id-{i}")),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        patient_svc::get_bundle_entry(patient, format!("Patient/Patient-id-{i}").as_str())
+    }
+
+    #[test]
+    fn test_streamed_bundle_matches_the_serialized_bundle() {
+        for (format, layout) in [
+            (Format::Xml, Layout::Plain),
+            (Format::Json, Layout::Plain),
+            (Format::Json, Layout::Pretty),
+        ] {
+            let mut bundle_writer = BundleWriter::new(
+                Vec::new(),
+                "Bundle-id-1",
+                BundleType::Transaction,
+                format,
+                layout,
+            )
+            .expect("header is written");
+            bundle_writer
+                .write_entries((1..=3).map(get_entry))
+                .expect("entries are written");
+            let streamed = bundle_writer.finish().expect("bundle is finished");
+
+            let serialized = get_bundle(
+                "Bundle-id-1",
+                BundleType::Transaction,
+                (1..=3).map(get_entry).collect(),
+                format,
+                layout.is_pretty(),
+            )
+            .expect("bundle is serialized");
+
+            assert_eq!(
+                String::from_utf8(streamed).expect("bundle is utf-8"),
+                format!("{serialized}\n"),
+                "streamed {format:?} ({layout:?}) bundle does not match"
+            );
+        }
+    }
+}
@@ -1,30 +1,27 @@
-use std::ops::Range;
-
-use chrono::prelude::*;
-use fake::faker::chrono::en::DateTimeAfter;
-use fake::{Fake, Faker};
+use chrono::NaiveDate;
 use fhirbolt::model::r4b::resources::{BundleEntry, Condition, ConditionOnset};
 use fhirbolt::model::r4b::types::{Code, CodeableConcept, Coding, DateTime, Id, Reference, Uri};
 use fhirbolt::model::r4b::Resource;
+use rand::rngs::StdRng;
 
 use crate::extensions::option_ext::OptionExt;
 use crate::models::cli::ResourceType;
 use crate::models::enums::id_type::IdType;
-use crate::models::enums::tumor_site_location::TumorSiteLocation;
-use crate::utils::{
-    get_bundle_entry_request, get_full_url, get_ids, get_min_date_time_millenial,
-    get_site_location_url,
-};
+use crate::models::id_allocator::IdAllocator;
+use crate::models::scenario::Scenario;
+use crate::models::tumor_entity::Diagnosis;
+use crate::resource_generator::{GeneratorContext, ResourceGenerator};
+use crate::utils::{get_bundle_entry_request, get_ids, get_site_location_url};
 
 pub fn get_condition(
     id: &str,
     subject_ref: &str,
-    code_value: &str,
-    bs_code_value1: &str,
+    diagnosis: &Diagnosis,
+    onset_date: NaiveDate,
+    scenario: &Scenario,
+    rng: &mut StdRng,
 ) -> Condition {
-    let bs_code_value2: TumorSiteLocation = Faker.fake();
-    let min_date_time: chrono::DateTime<Utc> = get_min_date_time_millenial();
-    let effective_date: chrono::DateTime<Utc> = DateTimeAfter(min_date_time).fake();
+    let bs_code_value2 = scenario.get_tumor_site_location(rng);
 
     let cid = Id {
         value: Some(id.to_string()),
@@ -35,13 +32,13 @@ pub fn get_condition(
         ..Default::default()
     };
     let effective = DateTime {
-        value: Some(effective_date.date_naive().to_string()),
+        value: Some(onset_date.to_string()),
         ..Default::default()
     };
     let coding = Coding {
         system: Some(Uri::from("http://fhir.de/CodeSystem/bfarm/icd-10-gm")),
         version: Some("2019".into()),
-        code: Some(Code::from(code_value)),
+        code: Some(Code::from(diagnosis.icd10_code)),
         ..Default::default()
     };
     let cod_concept = CodeableConcept {
@@ -51,7 +48,7 @@ pub fn get_condition(
     let body_site_coding1 = Coding {
         system: Some(Uri::from("urn:oid:2.16.840.1.113883.6.43.1")),
         version: Some("31".into()),
-        code: Some(Code::from(bs_code_value1)),
+        code: Some(Code::from(diagnosis.topography)),
         ..Default::default()
     };
     let body_site_coding2 = Coding {
@@ -78,34 +75,41 @@ pub fn get_condition(
 
 pub fn get_bundle_entry(condition: Condition, condition_ref_id: &str) -> BundleEntry {
     BundleEntry {
-        full_url: Some(get_full_url(
-            condition.clone().id.unwrap().value.unwrap().as_str(),
-        )),
         resource: Some(Resource::Condition(Box::new(condition.clone()))),
         request: get_bundle_entry_request("PUT", condition_ref_id).into_some(),
         ..Default::default()
     }
 }
 
-pub fn get_conditions(
-    subject_ref: &str,
-    code_value: &str,
-    bs_code_value1: &str,
-    range: Range<u8>,
-) -> Vec<(Condition, String)> {
-    range
-        .map(|_| {
-            let i: u16 = Faker.fake();
-            let (condition_id, condition_ref_id) = get_ids(IdType::Id, ResourceType::Condition, i);
-            (
-                get_condition(
-                    condition_id.as_str(),
-                    subject_ref,
-                    code_value,
-                    bs_code_value1,
-                ),
-                condition_ref_id,
-            )
-        })
-        .collect()
+/// Generates a condition of the patient
+pub struct ConditionGenerator;
+
+impl ResourceGenerator for ConditionGenerator {
+    fn resource_type(&self) -> ResourceType {
+        ResourceType::Condition
+    }
+
+    fn dependencies(&self) -> &'static [ResourceType] {
+        &[ResourceType::Patient]
+    }
+
+    fn generate(
+        &self,
+        i: &str,
+        context: &mut GeneratorContext,
+        _ids: &mut IdAllocator,
+        scenario: &Scenario,
+        rng: &mut StdRng,
+    ) -> Vec<BundleEntry> {
+        let (condition_id, condition_ref_id) = get_ids(IdType::Id, ResourceType::Condition, i);
+        let condition = get_condition(
+            condition_id.as_str(),
+            context.get_ref_id(ResourceType::Patient).as_str(),
+            &context.diagnosis,
+            context.timeline.diagnosis_date,
+            scenario,
+            rng,
+        );
+        vec![get_bundle_entry(condition, condition_ref_id.as_str())]
+    }
 }
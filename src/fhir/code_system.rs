@@ -0,0 +1,114 @@
+use fhirbolt::model::r4b::resources::{Bundle, BundleEntry, CodeSystem, CodeSystemConcept};
+use fhirbolt::model::r4b::types::Code;
+use fhirbolt::model::r4b::Resource;
+
+use crate::bundle_svc;
+use crate::fhir::traits::{CodeSystemAdapter, CodeSystemConceptAdapter}; // Bring the trait into scope
+use crate::models::cli::BundleType;
+use crate::models::enums::syst_therapy_type::SystTherapyType;
+use crate::models::enums::tnmm_symbol::TnmmSymbol;
+use crate::models::enums::tnmr_symbol::TnmrSymbol;
+use crate::models::enums::tnmy_symbol::TnmySymbol;
+use crate::models::enums::vital_status::VitalStatus;
+use crate::utils::{CCE, FHIR_COMPLETION_STATUS, FHIR_RESOURCE_STATUS, FHIR_RESOURCE_VERSION};
+
+use super::globals::get_contact_details;
+
+/// Generates the CodeSystems of all the enums, which are not defined by an external terminology
+pub fn get_code_systems() -> Vec<CodeSystem> {
+    vec![
+        get_code_system::<VitalStatus>(),
+        get_code_system::<SystTherapyType>(),
+        get_code_system::<TnmySymbol>(),
+        get_code_system::<TnmrSymbol>(),
+        get_code_system::<TnmmSymbol>(),
+    ]
+}
+
+/// Puts the CodeSystems into a collection bundle, so that they are a single valid FHIR document; the
+/// entries are identified by the canonical URLs of their CodeSystems
+pub fn get_code_system_bundle() -> Bundle {
+    let entries = get_code_systems()
+        .into_iter()
+        .map(|code_system| BundleEntry {
+            full_url: code_system.url.clone(),
+            resource: Some(Resource::CodeSystem(Box::new(code_system))),
+            ..Default::default()
+        })
+        .collect();
+    bundle_svc::get_typed_bundle("CodeSystems", BundleType::Collection, entries)
+}
+
+pub fn get_code_system<T: CodeSystemAdapter + CodeSystemConceptAdapter>() -> CodeSystem {
+    let status_code = Code {
+        value: Some(FHIR_RESOURCE_STATUS.to_string()),
+        ..Default::default()
+    };
+    let content_code = Code {
+        value: Some(FHIR_COMPLETION_STATUS.to_string()),
+        ..Default::default()
+    };
+    let concepts = get_code_system_concepts::<T>();
+
+    CodeSystem {
+        text: Some(Box::new(T::get_narrative())),
+        url: Some(T::get_url().into()),
+        version: Some(FHIR_RESOURCE_VERSION.to_string().into()),
+        name: Some(T::get_name().into()),
+        title: Some(T::get_title().into()),
+        status: status_code,
+        publisher: Some(CCE.to_string().into()),
+        contact: get_contact_details(),
+        description: Some(T::get_description().into()),
+        case_sensitive: Some(true.into()),
+        compositional: Some(false.into()),
+        content: content_code,
+        count: Some((concepts.len() as u32).into()),
+        concept: concepts,
+        ..Default::default()
+    }
+}
+
+fn get_code_system_concepts<T: CodeSystemConceptAdapter>() -> Vec<CodeSystemConcept> {
+    T::get_concepts()
+        .into_iter()
+        .map(|concept| CodeSystemConcept {
+            code: Code {
+                value: Some(concept.code),
+                ..Default::default()
+            },
+            display: Some(concept.display.into()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_code_system_bundle_has_an_entry_per_code_system() {
+        let bundle = get_code_system_bundle();
+
+        assert_eq!(
+            bundle.r#type.value.as_deref(),
+            Some("collection"),
+            "bundle type does not match"
+        );
+        assert_eq!(
+            bundle.entry.len(),
+            get_code_systems().len(),
+            "number of entries does not match"
+        );
+        for entry in &bundle.entry {
+            let Some(Resource::CodeSystem(code_system)) = &entry.resource else {
+                panic!("entry is no CodeSystem");
+            };
+            assert_eq!(
+                entry.full_url, code_system.url,
+                "fullUrl is not the canonical URL"
+            );
+        }
+    }
+}
@@ -1,3 +1,3 @@
+pub mod code_system;
 pub mod globals;
 pub mod traits;
-pub mod vital_status_code_system;
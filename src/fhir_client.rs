@@ -0,0 +1,722 @@
+use std::error::Error;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use fhirbolt::model::r4b::resources::{
+    Bundle, BundleEntry, OperationOutcome, OperationOutcomeIssue,
+};
+use fhirbolt::model::r4b::Resource;
+use fhirbolt::serde::{json, xml, DeserializeResource};
+use log::{debug, warn};
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::header::{ACCEPT, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Proxy, StatusCode};
+
+use crate::models::cli::{EntryRequest, FhirServerArgs, Format};
+
+// large transaction bundles take a while to be processed by the server
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+// delay before the first retry, it doubles with every further retry
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+// the longest delay before a retry, also if the server asks for a longer one (`Retry-After`)
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+enum Auth {
+    None,
+    Basic(String, Option<String>),
+    Bearer(String),
+}
+
+/// Uploads the generated transaction bundles to a FHIR server
+pub struct FhirClient {
+    client: Client,
+    url: String,
+    auth: Auth,
+    format: Format,
+    // whether the bundles can be uploaded again without duplicating resources
+    idempotent: bool,
+    max_retries: u32,
+    retry_delay: Duration,
+    max_retry_delay: Duration,
+}
+
+/// Why an upload failed; transient failures (e.g. the server is overloaded) are retried
+enum UploadFailure {
+    Transient(String, Option<Duration>),
+    Permanent(String),
+}
+
+/// The number of resources created and updated by the uploaded bundle(s)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UploadSummary {
+    pub created: u64,
+    pub updated: u64,
+}
+
+impl UploadSummary {
+    pub fn add(&mut self, other: UploadSummary) {
+        self.created += other.created;
+        self.updated += other.updated;
+    }
+}
+
+impl FhirClient {
+    /// Creates the client for the server, which the bundles are uploaded to in the given format; only
+    /// idempotent bundles (see [EntryRequest::is_idempotent]) are uploaded again, if the connection is
+    /// lost or the upload times out
+    pub fn new(
+        args: &FhirServerArgs,
+        format: Format,
+        entry_request: EntryRequest,
+    ) -> Result<Self, String> {
+        let url = args
+            .fhir_url
+            .clone()
+            .ok_or("--fhir-url is required to upload the data to a FHIR server")?;
+
+        let mut builder = Client::builder().timeout(UPLOAD_TIMEOUT);
+        if let Some(proxy_url) = &args.proxy_url {
+            let proxy =
+                Proxy::all(proxy_url).map_err(|e| format!("invalid proxy url {proxy_url}: {e}"))?;
+            builder = builder.proxy(proxy);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| format!("failed to create the HTTP client: {e}"))?;
+
+        let auth = match (&args.fhir_user, &args.fhir_token) {
+            (Some(user), _) => Auth::Basic(user.clone(), args.fhir_password.clone()),
+            (None, Some(token)) => Auth::Bearer(token.clone()),
+            (None, None) => Auth::None,
+        };
+
+        Ok(Self {
+            client,
+            url,
+            auth,
+            format,
+            idempotent: entry_request.is_idempotent(),
+            max_retries: args.max_retries,
+            retry_delay: RETRY_DELAY,
+            max_retry_delay: MAX_RETRY_DELAY,
+        })
+    }
+
+    /// POSTs the transaction bundle (XML or JSON) to the server and counts the created and updated resources
+    /// in its transaction-response. Transient failures (429, 503 and, if the bundle is idempotent, lost
+    /// connections and timeouts) are retried with an exponential backoff (or after the server's
+    /// `Retry-After`), which is capped at [MAX_RETRY_DELAY]; if the server rejects the
+    /// bundle, the issues of its OperationOutcome are returned along with the fullUrl of the entry they
+    /// refer to. A batch, whose entries are processed independently, fails if any of its entries
+    /// failed; the failed entries are returned with their status and the issues of their outcome.
+    pub fn upload_bundle(&self, bundle: Vec<u8>) -> Result<UploadSummary, String> {
+        debug!("upload_bundle to {}", self.url);
+
+        let mut retries = 0;
+        loop {
+            match self.post_bundle(&bundle) {
+                Ok(summary) => return Ok(summary),
+                Err(UploadFailure::Transient(e, retry_after)) if retries < self.max_retries => {
+                    let delay = self.get_retry_delay(retries, retry_after);
+                    retries += 1;
+                    warn!(
+                        "{e}, retrying in {delay:?} ({retries}/{})",
+                        self.max_retries
+                    );
+                    thread::sleep(delay);
+                }
+                Err(UploadFailure::Transient(e, _)) | Err(UploadFailure::Permanent(e)) => {
+                    return Err(e)
+                }
+            }
+        }
+    }
+
+    /// The delay before the given retry: the server's `Retry-After` or the exponential backoff, but not
+    /// longer than the maximum delay
+    fn get_retry_delay(&self, retries: u32, retry_after: Option<Duration>) -> Duration {
+        retry_after
+            .unwrap_or_else(|| {
+                self.retry_delay
+                    .saturating_mul(2u32.saturating_pow(retries))
+            })
+            .min(self.max_retry_delay)
+    }
+
+    fn post_bundle(&self, bundle: &[u8]) -> Result<UploadSummary, UploadFailure> {
+        let request = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, self.format.mime_type())
+            .header(ACCEPT, self.format.mime_type())
+            .body(bundle.to_vec());
+        let response = self.authorize(request).send().map_err(|e| {
+            let msg = format!("failed to call {}: {e}", self.url);
+            match is_connection_lost(&e) {
+                true if self.idempotent => UploadFailure::Transient(msg, None),
+                // the server might have stored the POSTed resources, a retry would duplicate them
+                true => UploadFailure::Permanent(format!(
+                    "{msg} (not retried, as the resources might have been created already)"
+                )),
+                false => UploadFailure::Permanent(msg),
+            }
+        })?;
+
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs);
+        let body = response.text().map_err(|e| {
+            UploadFailure::Permanent(format!("failed to read the response of {}: {e}", self.url))
+        })?;
+
+        if !status.is_success() {
+            let msg = self.get_rejection_message(status, &body, bundle);
+            return match status {
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                    Err(UploadFailure::Transient(msg, retry_after))
+                }
+                _ => Err(UploadFailure::Permanent(msg)),
+            };
+        }
+
+        let response_bundle: Bundle = self.deserialize(body.as_bytes()).map_err(|e| {
+            UploadFailure::Permanent(format!(
+                "invalid transaction-response from {}: {e}",
+                self.url
+            ))
+        })?;
+        let summary = get_upload_summary(&response_bundle);
+
+        // the entries of a batch are processed independently, hence some of them may have failed
+        if response_bundle.entry.iter().any(is_failed_entry) {
+            let failures = get_entry_failures(&response_bundle, &self.get_full_urls(bundle));
+            return Err(UploadFailure::Permanent(format!(
+                "{} failed to process {} of {} entries ({} created, {} updated):\n{}",
+                self.url,
+                failures.len(),
+                response_bundle.entry.len(),
+                summary.created,
+                summary.updated,
+                failures.join("\n")
+            )));
+        }
+        Ok(summary)
+    }
+
+    /// Lists the issues of the OperationOutcome returned by the server (or the plain response, if it
+    /// is not an OperationOutcome)
+    fn get_rejection_message(&self, status: StatusCode, body: &str, bundle: &[u8]) -> String {
+        match self.deserialize::<OperationOutcome>(body.as_bytes()) {
+            Ok(outcome) => {
+                let full_urls = self.get_full_urls(bundle);
+                let issues: Vec<String> = outcome
+                    .issue
+                    .iter()
+                    .map(|issue| format!("  {}", get_issue_description(issue, &full_urls)))
+                    .collect();
+                format!("{} returned {status}:\n{}", self.url, issues.join("\n"))
+            }
+            Err(_) if body.trim().is_empty() => format!("{} returned {status}", self.url),
+            Err(_) => format!("{} returned {status}: {body}", self.url),
+        }
+    }
+
+    fn deserialize<'a, T: DeserializeResource<'a>>(&self, data: &'a [u8]) -> Result<T, String> {
+        match self.format {
+            Format::Xml => xml::from_slice(data, None).map_err(|e| e.to_string()),
+            Format::Json => json::from_slice(data, None).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Returns the fullUrls of the entries of the (uploaded) bundle, in the order of the entries
+    fn get_full_urls(&self, bundle: &[u8]) -> Vec<String> {
+        self.deserialize::<Bundle>(bundle)
+            .map(|bundle| {
+                bundle
+                    .entry
+                    .into_iter()
+                    .map(|e| e.full_url.and_then(|url| url.value).unwrap_or_default())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.auth {
+            Auth::None => request,
+            Auth::Basic(user, password) => request.basic_auth(user, password.as_ref()),
+            Auth::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+}
+
+/// Whether the connection to the server could not be established, was lost or timed out, so that the
+/// upload might succeed on a retry
+fn is_connection_lost(e: &reqwest::Error) -> bool {
+    if e.is_connect() || e.is_timeout() {
+        return true;
+    }
+    let mut source = e.source();
+    while let Some(err) = source {
+        if let Some(io_err) = err.downcast_ref::<io::Error>() {
+            if matches!(
+                io_err.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+            ) {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Describes the issue by its severity, its message and the fullUrl of the bundle entry it refers to
+/// (as given by its expression or location, e.g. `Bundle.entry[3].resource.subject`)
+fn get_issue_description(issue: &OperationOutcomeIssue, full_urls: &[String]) -> String {
+    let severity = issue.severity.value.clone().unwrap_or_default();
+    let code = issue.code.value.clone().unwrap_or_default();
+    let message = issue
+        .diagnostics
+        .as_ref()
+        .and_then(|d| d.value.clone())
+        .or_else(|| {
+            issue
+                .details
+                .as_ref()
+                .and_then(|d| d.text.as_ref())
+                .and_then(|t| t.value.clone())
+        })
+        .unwrap_or_default();
+    let full_url = issue
+        .expression
+        .iter()
+        .chain(issue.location.iter())
+        .filter_map(|path| path.value.as_deref())
+        .find_map(get_entry_index)
+        .and_then(|i| full_urls.get(i));
+
+    match full_url {
+        Some(url) => format!("{severity} ({code}): {message} [{url}]"),
+        None => format!("{severity} ({code}): {message}"),
+    }
+}
+
+/// Returns the index of the bundle entry in a FHIRPath, e.g. 3 for `Bundle.entry[3].resource`
+fn get_entry_index(path: &str) -> Option<usize> {
+    let start = path.find("entry[")? + "entry[".len();
+    let len = path[start..].find(']')?;
+    path[start..start + len].parse().ok()
+}
+
+/// Returns the status of the entry of a transaction- or batch-response, e.g. `201 Created`
+fn get_entry_status(entry: &BundleEntry) -> String {
+    entry
+        .response
+        .as_ref()
+        .and_then(|r| r.status.value.clone())
+        .unwrap_or_default()
+}
+
+/// Whether the entry of a batch-response failed, i.e. has a 4xx or 5xx status
+fn is_failed_entry(entry: &BundleEntry) -> bool {
+    get_entry_status(entry).starts_with(['4', '5'])
+}
+
+/// Describes the failed entries of the batch-response by the fullUrl of the uploaded entry (the
+/// entries of the response are in the order of the uploaded ones), their status and the issues of
+/// their OperationOutcome
+fn get_entry_failures(response_bundle: &Bundle, full_urls: &[String]) -> Vec<String> {
+    response_bundle
+        .entry
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| is_failed_entry(entry))
+        .map(|(i, entry)| {
+            let full_url = full_urls.get(i).map_or("", String::as_str);
+            let mut failure = format!("  [{full_url}] returned {}", get_entry_status(entry));
+            if let Some(Resource::OperationOutcome(outcome)) =
+                entry.response.as_ref().and_then(|r| r.outcome.as_ref())
+            {
+                for issue in &outcome.issue {
+                    failure.push_str(&format!(
+                        "\n    {}",
+                        get_issue_description(issue, full_urls)
+                    ));
+                }
+            }
+            failure
+        })
+        .collect()
+}
+
+/// Counts the entries of the transaction-response by their status, `201 Created` for created and
+/// `200 OK` for updated resources
+fn get_upload_summary(response_bundle: &Bundle) -> UploadSummary {
+    let mut summary = UploadSummary::default();
+    for entry in &response_bundle.entry {
+        let status = get_entry_status(entry);
+        if status.starts_with("201") {
+            summary.created += 1;
+        } else if status.starts_with("200") {
+            summary.updated += 1;
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    use super::*;
+
+    const CLIENT_TIMEOUT: Duration = Duration::from_millis(500);
+
+    const TRANSACTION_RESPONSE: &str = r#"<Bundle xmlns="http://hl7.org/fhir">
+  <type value="transaction-response"/>
+  <entry><response><status value="201 Created"/></response></entry>
+  <entry><response><status value="201 Created"/></response></entry>
+  <entry><response><status value="200 OK"/></response></entry>
+</Bundle>"#;
+
+    const OPERATION_OUTCOME: &str = r#"<OperationOutcome xmlns="http://hl7.org/fhir">
+  <issue>
+    <severity value="error"/>
+    <code value="invalid"/>
+    <diagnostics value="unknown gender"/>
+    <expression value="Bundle.entry[1].resource.gender"/>
+  </issue>
+</OperationOutcome>"#;
+
+    const BATCH_RESPONSE: &str = r#"<Bundle xmlns="http://hl7.org/fhir">
+  <type value="batch-response"/>
+  <entry><response><status value="201 Created"/></response></entry>
+  <entry>
+    <response>
+      <status value="422 Unprocessable Entity"/>
+      <outcome>
+        <OperationOutcome xmlns="http://hl7.org/fhir">
+          <issue>
+            <severity value="error"/>
+            <code value="invalid"/>
+            <diagnostics value="unknown gender"/>
+            <expression value="Patient.gender"/>
+          </issue>
+        </OperationOutcome>
+      </outcome>
+    </response>
+  </entry>
+</Bundle>"#;
+
+    const BUNDLE: &str = r#"<Bundle xmlns="http://hl7.org/fhir">
+  <type value="transaction"/>
+  <entry><fullUrl value="https://example.org/Patient-id-1"/></entry>
+  <entry><fullUrl value="https://example.org/Patient-id-2"/></entry>
+</Bundle>"#;
+
+    /// Serves one request per given response (status and body) and returns the received requests
+    /// (head and body); an empty status leaves the request unanswered, so that it times out. The status
+    /// may be followed by further header lines (e.g. `503 Service Unavailable\r\nRetry-After: 1`).
+    fn serve(
+        responses: &'static [(&'static str, &'static str)],
+    ) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("stub server binds");
+        let url = format!(
+            "http://{}/fhir",
+            listener.local_addr().expect("has an address")
+        );
+
+        let handle = thread::spawn(move || {
+            let mut unanswered = vec![];
+            let requests = responses
+                .iter()
+                .map(|(status, body)| {
+                    let (stream, _) = listener.accept().expect("request is received");
+                    let mut reader = BufReader::new(stream);
+                    let mut request = String::new();
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).expect("header is read");
+                        if let Some(len) = line.to_lowercase().strip_prefix("content-length:") {
+                            content_length =
+                                len.trim().parse().expect("content length is a number");
+                        }
+                        request.push_str(&line);
+                        if line == "\r\n" {
+                            break;
+                        }
+                    }
+                    let mut request_body = vec![0; content_length];
+                    reader.read_exact(&mut request_body).expect("body is read");
+                    request.push_str(&String::from_utf8_lossy(&request_body));
+                    if status.is_empty() {
+                        // the connection is kept open (without a response), until the client timed out
+                        unanswered.push(reader);
+                        return request;
+                    }
+
+                    let response = format!(
+                        "HTTP/1.1 {status}\r\nContent-Type: application/fhir+xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    reader
+                        .get_mut()
+                        .write_all(response.as_bytes())
+                        .expect("response is written");
+                    request
+                })
+                .collect();
+            if !unanswered.is_empty() {
+                thread::sleep(CLIENT_TIMEOUT * 2);
+            }
+            requests
+        });
+
+        (url, handle)
+    }
+
+    fn get_args(url: String) -> FhirServerArgs {
+        FhirServerArgs {
+            fhir_url: Some(url),
+            ..Default::default()
+        }
+    }
+
+    fn get_client(args: &FhirServerArgs) -> FhirClient {
+        get_client_for(args, EntryRequest::Put)
+    }
+
+    fn get_client_for(args: &FhirServerArgs, entry_request: EntryRequest) -> FhirClient {
+        let mut client =
+            FhirClient::new(args, Format::Xml, entry_request).expect("client is created");
+        client.retry_delay = Duration::from_millis(1);
+        client.client = Client::builder()
+            .timeout(CLIENT_TIMEOUT)
+            .build()
+            .expect("client is built");
+        client
+    }
+
+    #[test]
+    fn test_upload_bundle_counts_created_and_updated_resources() {
+        let (url, server) = serve(&[("200 OK", TRANSACTION_RESPONSE)]);
+        let args = FhirServerArgs {
+            fhir_token: Some("secret".to_string()),
+            ..get_args(url)
+        };
+        let client = get_client(&args);
+
+        let summary = client
+            .upload_bundle(b"<Bundle/>".to_vec())
+            .expect("bundle is uploaded");
+        assert_eq!(
+            summary,
+            UploadSummary {
+                created: 2,
+                updated: 1
+            },
+            "summary does not match"
+        );
+
+        let request = &server.join().expect("stub server does not panic")[0];
+        assert!(request.starts_with("POST /fhir "), "request is not a POST");
+        assert!(
+            request
+                .to_lowercase()
+                .contains("authorization: bearer secret"),
+            "bearer token is not sent"
+        );
+        assert!(request.ends_with("<Bundle/>"), "bundle is not sent");
+    }
+
+    #[test]
+    fn test_upload_bundle_fails_on_error_status() {
+        let (url, server) = serve(&[("400 Bad Request", OPERATION_OUTCOME)]);
+        let args = FhirServerArgs {
+            max_retries: 3,
+            ..get_args(url)
+        };
+        let client = get_client(&args);
+
+        let e = client
+            .upload_bundle(BUNDLE.as_bytes().to_vec())
+            .expect_err("error status is not reported");
+        assert!(
+            e.contains("error (invalid): unknown gender [https://example.org/Patient-id-2]"),
+            "issue is not described: {e}"
+        );
+        // a rejected bundle is not retried
+        assert_eq!(
+            server.join().expect("stub server does not panic").len(),
+            1,
+            "number of requests does not match"
+        );
+    }
+
+    #[test]
+    fn test_upload_bundle_fails_on_failed_batch_entries() {
+        let (url, server) = serve(&[("200 OK", BATCH_RESPONSE)]);
+        let client = get_client(&get_args(url));
+
+        let e = client
+            .upload_bundle(BUNDLE.as_bytes().to_vec())
+            .expect_err("failed entry is not reported");
+        assert!(
+            e.contains("failed to process 1 of 2 entries (1 created, 0 updated)"),
+            "failures are not counted: {e}"
+        );
+        assert!(
+            e.contains("[https://example.org/Patient-id-2] returned 422 Unprocessable Entity"),
+            "failed entry is not described: {e}"
+        );
+        assert!(
+            e.contains("error (invalid): unknown gender"),
+            "issue is not described: {e}"
+        );
+        assert!(
+            !e.contains("Patient-id-1"),
+            "successful entry is reported: {e}"
+        );
+        server.join().expect("stub server does not panic");
+    }
+
+    #[test]
+    fn test_upload_bundle_retries_transient_failures() {
+        let (url, server) = serve(&[
+            ("503 Service Unavailable", ""),
+            ("429 Too Many Requests", ""),
+            ("200 OK", TRANSACTION_RESPONSE),
+        ]);
+        let args = FhirServerArgs {
+            max_retries: 2,
+            ..get_args(url)
+        };
+        let client = get_client(&args);
+
+        let summary = client
+            .upload_bundle(BUNDLE.as_bytes().to_vec())
+            .expect("bundle is uploaded on the last retry");
+        assert_eq!(summary.created, 2, "summary does not match");
+        assert_eq!(
+            server.join().expect("stub server does not panic").len(),
+            3,
+            "number of requests does not match"
+        );
+    }
+
+    #[test]
+    fn test_upload_bundle_gives_up_after_the_retries() {
+        let (url, server) = serve(&[
+            ("503 Service Unavailable", ""),
+            ("503 Service Unavailable", ""),
+        ]);
+        let args = FhirServerArgs {
+            max_retries: 1,
+            ..get_args(url)
+        };
+
+        assert!(
+            get_client(&args)
+                .upload_bundle(BUNDLE.as_bytes().to_vec())
+                .is_err(),
+            "failure is not reported"
+        );
+        server.join().expect("stub server does not panic");
+    }
+
+    #[test]
+    fn test_upload_bundle_caps_the_retry_after_delay() {
+        let (url, server) = serve(&[
+            ("503 Service Unavailable\r\nRetry-After: 86400", ""),
+            ("200 OK", TRANSACTION_RESPONSE),
+        ]);
+        let args = FhirServerArgs {
+            max_retries: 1,
+            ..get_args(url)
+        };
+        let mut client = get_client(&args);
+        client.max_retry_delay = Duration::from_millis(10);
+
+        let summary = client
+            .upload_bundle(BUNDLE.as_bytes().to_vec())
+            .expect("bundle is uploaded on the retry");
+        assert_eq!(summary.created, 2, "summary does not match");
+        server.join().expect("stub server does not panic");
+
+        let client = get_client_for(&get_args(String::new()), EntryRequest::Put);
+        assert_eq!(
+            client.get_retry_delay(0, Some(Duration::from_secs(86400))),
+            MAX_RETRY_DELAY,
+            "retry-after is not capped"
+        );
+        assert_eq!(
+            client.get_retry_delay(40, None),
+            MAX_RETRY_DELAY,
+            "backoff is not capped"
+        );
+        assert_eq!(
+            client.get_retry_delay(2, Some(Duration::from_secs(5))),
+            Duration::from_secs(5),
+            "retry-after does not match"
+        );
+    }
+
+    #[test]
+    fn test_upload_bundle_retries_a_timed_out_idempotent_bundle() {
+        let (url, server) = serve(&[("", ""), ("200 OK", TRANSACTION_RESPONSE)]);
+        let args = FhirServerArgs {
+            max_retries: 1,
+            ..get_args(url)
+        };
+
+        let summary = get_client(&args)
+            .upload_bundle(BUNDLE.as_bytes().to_vec())
+            .expect("bundle is uploaded on the retry");
+        assert_eq!(summary.created, 2, "summary does not match");
+        server.join().expect("stub server does not panic");
+    }
+
+    #[test]
+    fn test_upload_bundle_does_not_retry_a_timed_out_post() {
+        let (url, server) = serve(&[("", "")]);
+        let args = FhirServerArgs {
+            max_retries: 1,
+            ..get_args(url)
+        };
+
+        let e = get_client_for(&args, EntryRequest::Post)
+            .upload_bundle(BUNDLE.as_bytes().to_vec())
+            .expect_err("timeout is not reported");
+        assert!(e.contains("not retried"), "retry is not explained: {e}");
+        assert_eq!(
+            server.join().expect("stub server does not panic").len(),
+            1,
+            "number of requests does not match"
+        );
+    }
+
+    #[test]
+    fn test_get_entry_index() {
+        assert_eq!(get_entry_index("Bundle.entry[3].resource.subject"), Some(3));
+        assert_eq!(get_entry_index("Bundle.entry"), None);
+    }
+
+    #[test]
+    fn test_new_requires_the_fhir_url() {
+        assert!(
+            FhirClient::new(&FhirServerArgs::default(), Format::Xml, EntryRequest::Put).is_err(),
+            "missing url is not reported"
+        );
+    }
+}
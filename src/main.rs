@@ -1,37 +1,51 @@
 mod bundle_svc;
+mod bundle_writer;
 mod condition_svc;
 mod extensions;
 mod fhir;
+mod fhir_client;
 mod lens;
 mod medication_svc;
 mod models;
+mod ndjson_writer;
 mod observation_svc;
+mod output_dir;
 mod patient_svc;
 mod procedure_svc;
+mod resource_generator;
+mod resource_writer;
 mod showcase;
 mod specimen_svc;
 mod utils;
 
-use chrono::prelude::*;
 use clap::Parser;
-use fake::faker::chrono::en::DateTimeAfter;
-use fake::{Fake, Faker};
-use fhir::vital_status_code_system::get_vital_status_code_system;
+use fhir::code_system::get_code_system_bundle;
 use fhirbolt::model::r4b::resources::{Observation, Patient, Specimen};
-use fhirbolt::serde::xml;
 use lens::catalogue::Catalogue;
 use lens::traits::CategoryConverter;
-use log::info;
-use models::cli::{CliArgs, Commands, OutputMode, ResourceType};
+use log::{error, info, warn};
+use models::cli::{
+    BundleSemantics, BundleType, CliArgs, Commands, EntryRequest, FileLayout, Format, FullUrl,
+    OutputMode, ResourceType,
+};
 use models::enums::id_type::IdType;
 use models::enums::syst_therapy_type::SystTherapyType;
-use showcase::showcase_data;
-use utils::get_ids;
-
-use crate::utils::get_min_date_time;
-
-const DATA_FOLDER: &str = "generated-data";
-// const PROXY_URL: &str = "";
+use models::id_allocator::IdAllocator;
+use models::scenario::Scenario;
+use ndjson_writer::{NdjsonWriter, EXPORT_MANIFEST};
+use output_dir::{OutputDir, MANIFEST};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use resource_writer::ResourceWriter;
+use showcase::{showcase_data, DataSink};
+use std::env;
+use std::process;
+use utils::{get_ids, get_max_date_time};
+
+// the seed of the canonical data, if no seed is given
+const CANONICAL_SEED: u64 = 0;
+// the maximum number of entries per uploaded bundle, if no --bundle-size is given
+const UPLOAD_BUNDLE_SIZE: u64 = 1000;
 
 // TODO: Update README as per the new CLI options
 // TODO: Refactor CLI options and pull out output_mode in the main options
@@ -42,42 +56,184 @@ fn main() {
     colored_logger.init();
 
     let cli = CliArgs::parse();
-    match cli.cmd {
+    let mut out_dir = OutputDir::new(cli.out_dir.clone(), cli.overwrite_policy(), cli.archive);
+    let seed = match cli.cmd {
         Commands::SyntheticData {
             number,
             resource_type,
             output_mode,
+            ref scenario,
+            id_strategy,
+            ref id_namespace,
+            bundle_size,
+            ndjson,
+            ref ndjson_url,
+            file_layout,
+            bundle_type,
+            entry_request,
+            ref full_url_base,
+            urn_uuid,
+            ref fhir_server,
         } => {
-            let file_msg = format!("write to a file in /{}", DATA_FOLDER);
+            let file_msg = format!("write to files in {}", out_dir.path().display());
             let storage = match output_mode {
                 OutputMode::Screen => "show on terminal",
                 OutputMode::File => file_msg.as_str(),
-                OutputMode::ApiCall => "call API endpoint (WIP)",
+                OutputMode::ApiCall => "upload to the FHIR server",
             };
 
             println!(
                 "Generating {} {:?} and {}...",
                 number, resource_type, storage
             );
-            println!("");
+            println!();
 
-            if number > 1 {
-                info!(
-                    "generating a single bundle containing multiple {:?}...",
-                    resource_type
+            // every generator draws from this single rng, so that the same seed (and the same
+            // arguments) always produce the same data
+            // canonical data must not depend on a random seed
+            let seed = match cli.seed {
+                Some(seed) => seed,
+                None if cli.canonical => CANONICAL_SEED,
+                None => rand::random(),
+            };
+            info!("using seed {seed} (pass --seed {seed} to reproduce this run)");
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            let mut scenario = match scenario {
+                Some(path) => Scenario::from_file(path).unwrap_or_else(|e| {
+                    error!("{e}");
+                    process::exit(1);
+                }),
+                None => Scenario::default(),
+            };
+            // canonical data must not depend on the day it is generated either
+            scenario.pin_max_date(get_max_date_time().date_naive(), cli.canonical);
+            let mut ids =
+                IdAllocator::new(id_strategy, id_namespace.clone(), seed).unwrap_or_else(|e| {
+                    error!("{e}");
+                    process::exit(1);
+                });
+
+            if bundle_size.is_some()
+                && (ndjson
+                    || file_layout != FileLayout::Flat
+                    || !(number > 1 && resource_type == ResourceType::Bundle))
+            {
+                warn!("--bundle-size only splits a cohort (-n greater than 1 and -r bundle), it is ignored");
+            }
+
+            // every bundle is held in memory until it is POSTed, hence a cohort is uploaded in chunks
+            let bundle_size = match bundle_size {
+                None if output_mode == OutputMode::ApiCall
+                    && number > 1
+                    && resource_type == ResourceType::Bundle =>
+                {
+                    info!("uploading the cohort in bundles of at most {UPLOAD_BUNDLE_SIZE} entries (see --bundle-size)");
+                    Some(UPLOAD_BUNDLE_SIZE)
+                }
+                bundle_size => bundle_size,
+            };
+
+            // the (relative) references resolve against the server, the bundles are uploaded to
+            let full_url = match full_url_base.as_ref().or(fhir_server.fhir_url.as_ref()) {
+                Some(base) if !urn_uuid => FullUrl::Base(base.clone()),
+                _ => FullUrl::UrnUuid,
+            };
+            if bundle_type == BundleType::Batch
+                && (entry_request != EntryRequest::Put || full_url == FullUrl::UrnUuid)
+            {
+                error!("the entries of a batch cannot reference each other, hence it needs --entry-request put and a --full-url-base (or --fhir-url)");
+                process::exit(1);
+            }
+            if bundle_type == BundleType::Collection && output_mode == OutputMode::ApiCall {
+                error!("a collection cannot be uploaded, -o api-call needs a transaction or batch bundle");
+                process::exit(1);
+            }
+            if (bundle_type != BundleType::Transaction || entry_request != EntryRequest::Put)
+                && (ndjson || file_layout != FileLayout::Flat)
+            {
+                warn!("--bundle-type and --entry-request only apply to bundles, they are ignored");
+            }
+            let semantics = BundleSemantics {
+                bundle_type,
+                entry_request,
+                full_url,
+            };
+
+            if ndjson {
+                if resource_type != ResourceType::Bundle || output_mode != OutputMode::File {
+                    error!("--ndjson only writes a cohort to files (-r bundle and -o file)");
+                    process::exit(1);
+                }
+                info!("generating {number} patients as NDJSON, each with all resource types...");
+                // the (kick-off) request of the export is addressed to the server the data is meant for
+                let base = full_url_base
+                    .clone()
+                    .or(fhir_server.fhir_url.clone())
+                    .unwrap_or_else(utils::get_fhir_url);
+                let base_url = match ndjson_url {
+                    Some(url) => url.clone(),
+                    None => ndjson_writer::get_file_url(&cli.out_dir).unwrap_or_else(|e| {
+                        error!("failed to resolve the output directory: {e}");
+                        process::exit(1);
+                    }),
+                };
+                generate_ndjson_export(
+                    number,
+                    &base,
+                    &base_url,
+                    cli.canonical,
+                    &mut out_dir,
+                    &mut ids,
+                    &scenario,
+                    &mut rng,
+                );
+            } else if file_layout != FileLayout::Flat {
+                if resource_type != ResourceType::Bundle || output_mode != OutputMode::File {
+                    error!("--file-layout {file_layout:?} only writes patients to files (-r bundle and -o file)");
+                    process::exit(1);
+                }
+                info!("generating {number} patients, each with all resource types, as a file per resource...");
+                generate_resource_files(
+                    cli,
+                    number,
+                    file_layout,
+                    &mut out_dir,
+                    &mut ids,
+                    &scenario,
+                    &mut rng,
                 );
-                generate_fhir_bundles(cli, number, resource_type);
             } else {
-                if resource_type == ResourceType::Bundle {
-                    info!("generating a single bundle containing all resource types...");
-                } else {
-                    info!(
+                match (number > 1, resource_type) {
+                    (true, ResourceType::Bundle) => info!(
+                        "generating a single bundle containing {} patients, each with all resource types...",
+                        number
+                    ),
+                    (true, _) => info!(
+                        "generating a single bundle containing multiple {:?}...",
+                        resource_type
+                    ),
+                    (false, ResourceType::Bundle) => {
+                        info!("generating a single bundle containing all resource types...")
+                    }
+                    (false, _) => info!(
                         "generating a single bundle containing a {:?}...",
                         resource_type
-                    );
+                    ),
                 }
-                generate_fhir_bundle(cli, resource_type);
+                generate_fhir_bundle(
+                    cli,
+                    number,
+                    bundle_size,
+                    &semantics,
+                    resource_type,
+                    &mut out_dir,
+                    &mut ids,
+                    &scenario,
+                    &mut rng,
+                );
             }
+            Some(seed)
         }
 
         Commands::Catalogue { .. } => {
@@ -94,464 +250,185 @@ fn main() {
 
             let json = serde_json::to_string_pretty(&catalogue)
                 .expect("Failed to serialize categories to JSON");
-            showcase_data(json, None, cli.cmd);
+            showcase_data(json, None, Format::Json, None, cli.cmd, &mut out_dir);
+            None
         }
 
         Commands::FhirProfiles => {
-            let vs_res = utils::get_xml(get_vital_status_code_system(), "vital-status CodeSystem");
-            showcase_data(vs_res, None, cli.cmd);
-        }
-    }
-}
-
-fn generate_fhir_bundle(cli: CliArgs, resource_type: ResourceType) {
-    info!("generate_fhir_bundle");
-
-    let i: u16 = Faker.fake();
-
-    let (bundle_id, _) = get_ids(IdType::Id, ResourceType::Bundle, i);
-    let (patient_id, patient_ref_id) = get_ids(IdType::Id, ResourceType::Patient, i);
-    let (condition_id, condition_ref_id) = get_ids(IdType::Id, ResourceType::Condition, i);
-    let (specimen_id, specimen_ref_id) = get_ids(IdType::Id, ResourceType::Specimen, i);
-    let (obs_hist_id, obs_hist_ref_id) = get_ids(IdType::Id, ResourceType::ObservationHistology, i);
-    let (obs_vital_status_id, obs_vital_status_ref_id) =
-        get_ids(IdType::Id, ResourceType::ObservationVitalStatus, i);
-    let (obs_tnmc_id, obs_tnmc_ref_id) = get_ids(IdType::Id, ResourceType::ObservationTNMc, i);
-    let (proc_rt_id, proc_rt_ref_id) = get_ids(IdType::Id, ResourceType::ProcedureRadiotherapy, i);
-    let (proc_op_id, proc_op_ref_id) = get_ids(IdType::Id, ResourceType::ProcedureOperation, i);
-    let (med_stmt_id, med_stmt_ref_id) = get_ids(
-        IdType::Id,
-        ResourceType::SystemicTherapyMedicationStatement,
-        i,
-    );
-
-    let min_date_time = get_min_date_time();
-    let effective_date: DateTime<Utc> = DateTimeAfter(min_date_time).fake();
-
-    let start_date: DateTime<Utc> = DateTimeAfter(min_date_time).fake();
-    let end_date: DateTime<Utc> = DateTimeAfter(start_date).fake();
-
-    let (xml_data, file_name) = match resource_type {
-        ResourceType::Patient => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-
-            let b = bundle_svc::get_patients_bundle(&bundle_id, vec![(pt, patient_ref_id)]);
-            (utils::get_xml(b, "patient (bundle)"), patient_id)
-        }
-
-        ResourceType::Condition => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-
-            let c = condition_svc::get_condition(
-                condition_id.as_str(),
-                patient_ref_id.as_str(),
-                "C34.0",
-                "C34.0",
-            );
-            let b = bundle_svc::get_condition_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (c, condition_ref_id.as_str()),
-            );
-            (utils::get_xml(b, "condition (bundle)"), condition_id)
-        }
-
-        ResourceType::Specimen => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-
-            let s = specimen_svc::get_specimen(specimen_id.as_str(), patient_ref_id.as_str());
-            let b = bundle_svc::get_specimen_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (s, specimen_ref_id.as_str()),
-            );
-            (utils::get_xml(b, "specimen (bundle)"), specimen_id)
-        }
-
-        ResourceType::ObservationHistology => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-            let c = condition_svc::get_condition(
-                condition_id.as_str(),
-                patient_ref_id.as_str(),
-                "C34.0",
-                "C34.0",
-            );
-            let s = specimen_svc::get_specimen(specimen_id.as_str(), patient_ref_id.as_str());
-            let ohist = observation_svc::get_histology(
-                obs_hist_id.as_str(),
-                patient_ref_id.as_str(),
-                condition_ref_id.as_str(),
-                specimen_ref_id.as_str(),
-                effective_date.date_naive(),
-                "8140/3",
-            );
-            let b = bundle_svc::get_observation_histology_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (c, condition_ref_id.as_str()),
-                (s, specimen_ref_id.as_str()),
-                (ohist, obs_hist_ref_id.as_str()),
-            );
-            (
-                utils::get_xml(b, "observation histology (bundle)"),
-                obs_hist_id,
-            )
-        }
-
-        ResourceType::ObservationVitalStatus => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-
-            let ovs = observation_svc::get_vital_status(
-                obs_vital_status_id.as_str(),
-                patient_ref_id.as_str(),
-                effective_date.date_naive(),
-            );
-            let b = bundle_svc::get_observation_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (ovs, obs_vital_status_ref_id.as_str()),
-            );
-            (
-                utils::get_xml(b, "observation vital-status (bundle)"),
-                obs_vital_status_id,
-            )
-        }
-
-        ResourceType::ObservationTNMc => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-
-            let otnmc = observation_svc::get_tnmc(
-                &obs_tnmc_id.as_str(),
-                patient_ref_id.as_str(),
-                effective_date.date_naive(),
-            );
-            let b = bundle_svc::get_observation_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (otnmc, obs_tnmc_ref_id.as_str()),
-            );
-            (utils::get_xml(b, "observation tnmc (bundle)"), obs_tnmc_id)
-        }
-
-        ResourceType::ProcedureRadiotherapy => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-            let c = condition_svc::get_condition(
-                condition_id.as_str(),
-                patient_ref_id.as_str(),
-                "C34.0",
-                "C34.0",
-            );
-            let prt = procedure_svc::get_procedure(
-                proc_rt_id.as_str(),
-                patient_ref_id.as_str(),
-                condition_ref_id.as_str(),
-                start_date.date_naive(),
-                end_date.date_naive(),
-                SystTherapyType::RT,
-            );
-            let b = bundle_svc::get_procedure_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (c, condition_ref_id.as_str()),
-                (prt, proc_rt_ref_id.as_str()),
-            );
-            (
-                utils::get_xml(b, "procedure radiotherapy (bundle)"),
-                proc_rt_id,
-            )
-        }
-
-        ResourceType::ProcedureOperation => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-            let c = condition_svc::get_condition(
-                condition_id.as_str(),
-                patient_ref_id.as_str(),
-                "C34.0",
-                "C34.0",
-            );
-            let pop = procedure_svc::get_procedure(
-                proc_op_id.as_str(),
-                patient_ref_id.as_str(),
-                condition_ref_id.as_str(),
-                start_date.date_naive(),
-                end_date.date_naive(),
-                SystTherapyType::OP,
-            );
-            let b = bundle_svc::get_procedure_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (c, condition_ref_id.as_str()),
-                (pop, proc_op_ref_id.as_str()),
-            );
-            (
-                utils::get_xml(b, "procedure operation (bundle)"),
-                proc_op_id,
-            )
-        }
-
-        ResourceType::SystemicTherapyMedicationStatement => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-            let c = condition_svc::get_condition(
-                condition_id.as_str(),
-                patient_ref_id.as_str(),
-                "C34.0",
-                "C34.0",
-            );
-            let m = medication_svc::get_med_statement(
-                med_stmt_id.as_str(),
-                "medicine",
-                patient_ref_id.as_str(),
-                condition_ref_id.as_str(),
-                start_date.date_naive(),
-                end_date.date_naive(),
-            );
-            let b = bundle_svc::get_med_stmt_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (c, condition_ref_id.as_str()),
-                (m, med_stmt_ref_id.as_str()),
-            );
-            (utils::get_xml(b, "medication stmt (bundle)"), med_stmt_id)
-        }
-
-        ResourceType::Bundle => {
-            let b = bundle_svc::get_bundle();
-            (
-                xml::to_string(&b, None).unwrap_or("Cannot serialize bundle to XML.".to_string()),
-                bundle_id,
-            )
+            let code_systems = utils::serialize(
+                get_code_system_bundle(),
+                cli.format,
+                cli.layout(),
+                "CodeSystem (bundle)",
+            );
+            showcase_data(code_systems, None, cli.format, None, cli.cmd, &mut out_dir);
+            None
         }
     };
 
-    showcase_data(xml_data, Some(file_name), cli.cmd);
+    write_manifest(out_dir, seed);
 }
 
-fn generate_fhir_bundles(cli: CliArgs, number: u8, resource_type: ResourceType) {
-    info!("generate_fhir_bundles");
-
-    let range = 0..number;
-    let i: u16 = Faker.fake();
-
-    let (bundle_id, _) = get_ids(IdType::Id, ResourceType::Bundle, i);
-    let (patient_id, patient_ref_id) = get_ids(IdType::Id, ResourceType::Patient, i);
-    let (condition_id, condition_ref_id) = get_ids(IdType::Id, ResourceType::Condition, i);
-    let (specimen_id, specimen_ref_id) = get_ids(IdType::Id, ResourceType::Specimen, i);
-    let (obs_hist_id, _) = get_ids(IdType::Id, ResourceType::ObservationHistology, i);
-    let (obs_vital_status_id, _) = get_ids(IdType::Id, ResourceType::ObservationVitalStatus, i);
-    let (obs_tnmc_id, _) = get_ids(IdType::Id, ResourceType::ObservationTNMc, i);
-    let (proc_rt_id, _) = get_ids(IdType::Id, ResourceType::ProcedureRadiotherapy, i);
-    let (proc_op_id, _) = get_ids(IdType::Id, ResourceType::ProcedureOperation, i);
-    let (med_stmt_id, _) = get_ids(
-        IdType::Id,
-        ResourceType::SystemicTherapyMedicationStatement,
-        i,
-    );
-
-    let min_date_time = get_min_date_time();
-    let effective_date: DateTime<Utc> = DateTimeAfter(min_date_time).fake();
-
-    let start_date: DateTime<Utc> = DateTimeAfter(min_date_time).fake();
-    let end_date: DateTime<Utc> = DateTimeAfter(start_date).fake();
-
-    let (bundle, file_name) = match resource_type {
-        ResourceType::Patient => {
-            let patient_tuples = patient_svc::get_patients(range);
-            let b = bundle_svc::get_patients_bundle(bundle_id.as_str(), patient_tuples);
-            (b, patient_id)
-        }
-
-        ResourceType::Condition => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-
-            let condition_tuples =
-                condition_svc::get_conditions(patient_ref_id.as_str(), "C34.0", "C34.0", range);
-            let b = bundle_svc::get_conditions_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                condition_tuples,
-            );
-            (b, condition_id)
-        }
-
-        ResourceType::Specimen => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-
-            let specimen_tuples = specimen_svc::get_specimens(patient_ref_id.as_str(), range);
-            let b = bundle_svc::get_specimens_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                specimen_tuples,
-            );
-            (b, specimen_id)
-        }
-
-        ResourceType::ObservationHistology => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-            let c = condition_svc::get_condition(
-                condition_id.as_str(),
-                patient_ref_id.as_str(),
-                "C34.0",
-                "C34.0",
-            );
-            let s = specimen_svc::get_specimen(specimen_id.as_str(), patient_ref_id.as_str());
-            let hist_tuples = observation_svc::get_histologies(
-                patient_ref_id.as_str(),
-                condition_ref_id.as_str(),
-                specimen_ref_id.as_str(),
-                effective_date.date_naive(),
-                "8140/3",
-                range,
-            );
-            let b = bundle_svc::get_histologies_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (c, condition_ref_id.as_str()),
-                (s, specimen_ref_id.as_str()),
-                hist_tuples,
-            );
-            (b, obs_hist_id)
+/// Writes the manifest of the files written in this run (if any)
+fn write_manifest(out_dir: OutputDir, seed: Option<u64>) {
+    let path = out_dir.path().to_path_buf();
+    match out_dir.finish(env::args().skip(1).collect(), seed) {
+        Ok(Some(manifest)) => info!(
+            "wrote {MANIFEST} listing {} file(s) to {}",
+            manifest.files.len(),
+            path.display()
+        ),
+        Ok(None) => {}
+        Err(e) => {
+            error!("failed to write the manifest: {e}");
+            process::exit(1);
         }
+    }
+}
 
-        ResourceType::ObservationVitalStatus => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-
-            let vital_status_tuples = observation_svc::get_vital_statuses(
-                patient_ref_id.as_str(),
-                effective_date.date_naive(),
-                range,
-            );
-            let b = bundle_svc::get_vital_statuses_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                vital_status_tuples,
-            );
-            (b, obs_vital_status_id)
+#[allow(clippy::too_many_arguments)]
+fn generate_ndjson_export(
+    number: u64,
+    base: &str,
+    base_url: &str,
+    canonical: bool,
+    out_dir: &mut OutputDir,
+    ids: &mut IdAllocator,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) {
+    info!("generate_ndjson_export");
+
+    let mut writer = NdjsonWriter::new(out_dir);
+    let request = format!("{}/$export", base.trim_end_matches('/'));
+    let manifest = bundle_svc::write_cohort_records(&mut writer, 0..number, ids, scenario, rng)
+        .and_then(|_| {
+            // the export is complete now; canonical data is exported at the end of its (pinned) max
+            // date instead, so that its manifest does not depend on the time it is generated
+            let transaction_time = match canonical {
+                true => scenario
+                    .get_max_date()
+                    .and_hms_opt(23, 59, 59)
+                    .expect("time is valid")
+                    .and_utc(),
+                false => get_max_date_time(),
+            };
+            writer.finish(&request, base_url, transaction_time)
+        });
+    match manifest {
+        Ok(manifest) => {
+            for file in manifest.output {
+                info!(
+                    "wrote {} {} resource(s) to {}",
+                    file.count, file.r#type, file.url
+                );
+            }
+            info!("wrote {EXPORT_MANIFEST}");
         }
-
-        ResourceType::ObservationTNMc => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-
-            let tnmc_tuples = observation_svc::get_tnmcs(
-                patient_ref_id.as_str(),
-                effective_date.date_naive(),
-                range,
-            );
-            let b = bundle_svc::get_tnmcs_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                tnmc_tuples,
-            );
-            (b, obs_tnmc_id)
+        Err(e) => {
+            error!("failed to write the NDJSON files: {e}");
+            out_dir.discard();
+            process::exit(1);
         }
+    }
+}
 
-        ResourceType::ProcedureRadiotherapy => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-            let c = condition_svc::get_condition(
-                condition_id.as_str(),
-                patient_ref_id.as_str(),
-                "C34.0",
-                "C34.0",
-            );
-            let prt_tuples = procedure_svc::get_proc_radio_therapies(
-                patient_ref_id.as_str(),
-                condition_ref_id.as_str(),
-                start_date.date_naive(),
-                end_date.date_naive(),
-                range,
-            );
-            let b = bundle_svc::get_procedures_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (c, condition_ref_id.as_str()),
-                prt_tuples,
-            );
-            (b, proc_rt_id)
+fn generate_resource_files(
+    cli: CliArgs,
+    number: u64,
+    file_layout: FileLayout,
+    out_dir: &mut OutputDir,
+    ids: &mut IdAllocator,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) {
+    info!("generate_resource_files");
+
+    let mut writer = ResourceWriter::new(out_dir, file_layout, cli.format, cli.layout());
+    match bundle_svc::write_cohort_records(&mut writer, 0..number, ids, scenario, rng) {
+        Ok(()) => info!("wrote {} file(s)", writer.files()),
+        Err(e) => {
+            error!("failed to write the resources: {e}");
+            out_dir.discard();
+            process::exit(1);
         }
+    }
+}
 
-        ResourceType::ProcedureOperation => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-            let c = condition_svc::get_condition(
-                condition_id.as_str(),
-                patient_ref_id.as_str(),
-                "C34.0",
-                "C34.0",
-            );
-            let pop_tuples = procedure_svc::get_proc_operations(
-                patient_ref_id.as_str(),
-                condition_ref_id.as_str(),
-                start_date.date_naive(),
-                end_date.date_naive(),
-                range,
-            );
-            let b = bundle_svc::get_procedures_bundle(
-                &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (c, condition_ref_id.as_str()),
-                pop_tuples,
-            );
-            (b, proc_op_id)
-        }
+#[allow(clippy::too_many_arguments)]
+fn generate_fhir_bundle(
+    cli: CliArgs,
+    number: u64,
+    bundle_size: Option<u64>,
+    semantics: &BundleSemantics,
+    resource_type: ResourceType,
+    out_dir: &mut OutputDir,
+    ids: &mut IdAllocator,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) {
+    info!("generate_fhir_bundle");
 
-        ResourceType::SystemicTherapyMedicationStatement => {
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            let pt = patient_svc::get_patient(patient_id.as_str(), patient_src_id.as_str());
-            let c = condition_svc::get_condition(
-                condition_id.as_str(),
-                patient_ref_id.as_str(),
-                "C34.0",
-                "C34.0",
-            );
-            let med_stmt_tuples = medication_svc::get_med_statements(
-                patient_ref_id.as_str(),
-                condition_ref_id.as_str(),
-                start_date.date_naive(),
-                end_date.date_naive(),
-                range,
-            );
-            let b = bundle_svc::get_med_stmts_bundle(
+    let (mut bundle, file_name) = match resource_generator::get_generator(resource_type) {
+        Some(generator) => resource_generator::get_bundle(generator, number, ids, scenario, rng),
+
+        None if number > 1 => {
+            // a cohort can have (hundreds of) thousands of patients, hence it is streamed
+            let (bundle_id, _) = get_ids(IdType::Id, ResourceType::Bundle, &ids.next_id());
+            let mut sink = DataSink::new(
+                &cli.cmd,
+                cli.format,
+                cli.layout(),
+                bundle_size.is_some(),
+                out_dir,
+            );
+            let result = bundle_svc::write_cohort_bundles(
+                &mut sink,
                 &bundle_id,
-                (pt, patient_ref_id.as_str()),
-                (c, condition_ref_id.as_str()),
-                med_stmt_tuples,
-            );
-            (b, med_stmt_id)
+                0..number,
+                bundle_size,
+                semantics,
+                ids,
+                scenario,
+                rng,
+            );
+            match result {
+                Ok(bundles) => info!("wrote {bundles} bundle(s)"),
+                Err(e) => {
+                    error!("failed to write the bundle: {e}");
+                    drop(sink);
+                    out_dir.discard();
+                    process::exit(1);
+                }
+            }
+            if let Err(e) = sink.finish() {
+                error!("{e}");
+                process::exit(1);
+            }
+            return;
         }
 
-        ResourceType::Bundle => {
-            todo!()
-            // let b = bundle_svc::get_bundle();
-            // (
-            //     xml::to_string(&b, None).unwrap_or("Cannot serialize bundle to XML.".to_string()),
-            //     bundle_id,
-            // )
+        None => {
+            let bundle = bundle_svc::get_bundle(ids, scenario, rng);
+            let file_name = bundle
+                .id
+                .as_ref()
+                .and_then(|id| id.value.clone())
+                .unwrap_or_default();
+            (bundle, file_name)
         }
     };
 
-    let bundle_xml = utils::get_xml(bundle, "bundle");
-    showcase_data(bundle_xml, Some(file_name), cli.cmd);
+    bundle_svc::set_semantics(&mut bundle, semantics);
+    let resources = bundle.entry.len() as u64;
+    let error_infix = format!("{} (bundle)", resource_type.as_str());
+    let data = utils::serialize(bundle, cli.format, cli.layout(), &error_infix);
+    showcase_data(
+        data,
+        Some(file_name),
+        cli.format,
+        Some(resources),
+        cli.cmd,
+        out_dir,
+    );
 }
-
-// fn get_client() -> Client {
-//     let proxy = get_proxy();
-//     Client::builder().proxy(proxy).build().unwrap()
-// }
-
-// fn get_proxy() -> Proxy {
-//     Proxy::all(PROXY_URL).unwrap()
-// }
@@ -1,42 +1,73 @@
-use std::ops::Range;
-
 use chrono::NaiveDate;
-use fake::{Fake, Faker};
 use fhirbolt::model::r4b::resources::{
     BundleEntry, MedicationStatement, MedicationStatementEffective, MedicationStatementMedication,
 };
 use fhirbolt::model::r4b::types::{Code, CodeableConcept, Coding, DateTime, Id, Period, Reference};
 use fhirbolt::model::r4b::Resource;
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
 
 use crate::extensions::option_ext::OptionExt;
 use crate::models::cli::ResourceType;
 use crate::models::enums::id_type::IdType;
+use crate::models::enums::substance::Substance;
 use crate::models::enums::syst_therapy_type::SystTherapyType;
-use crate::utils::{get_bundle_entry_request, get_full_url, get_ids, get_syst_therapy_type_url};
+use crate::models::id_allocator::IdAllocator;
+use crate::models::scenario::Scenario;
+use crate::models::tumor_entity::Diagnosis;
+use crate::resource_generator::{GeneratorContext, ResourceGenerator};
+use crate::utils::{get_atc_url, get_bundle_entry_request, get_ids, get_syst_therapy_type_url};
 
 pub fn get_med_statement(
     id: &str,
-    med_ref: &str,
     subject_ref: &str,
     reason_ref: &str,
+    therapy_type: SystTherapyType,
+    substance: Option<Substance>,
     start_date: NaiveDate,
     end_date: NaiveDate,
 ) -> MedicationStatement {
-    let therapy_type: SystTherapyType = Faker.fake();
-
     let pid = Id {
         value: Some(id.to_string()),
         ..Default::default()
     };
-    let status = Code {
-        id: Some("ongoing".to_string()),
-        ..Default::default()
-    };
-    let med_rfrnc = Reference {
-        reference: Some(med_ref.into()),
-        ..Default::default()
+    // under watchful waiting or (active) surveillance the tumour is only monitored, hence the patient
+    // takes no medication
+    let (status, medication) = if therapy_type.is_surveillance() {
+        let no_medication = CodeableConcept {
+            text: Some("No medication".into()),
+            ..Default::default()
+        };
+        ("not-taken", no_medication)
+    } else {
+        let medication = match substance {
+            Some(substance) => CodeableConcept {
+                coding: vec![Coding {
+                    system: Some(get_atc_url()),
+                    code: Some(Code::from(substance.as_str())),
+                    display: Some(substance.to_string().into()),
+                    ..Default::default()
+                }],
+                text: Some(substance.to_string().into()),
+                ..Default::default()
+            },
+            // e.g. a stem cell therapy, which has no substance in the substance table, hence the
+            // medication is coded by the therapy type
+            None => CodeableConcept {
+                coding: vec![Coding {
+                    system: Some(get_syst_therapy_type_url()),
+                    code: Some(Code::from(therapy_type.to_string())),
+                    display: Some(therapy_type.as_str().into()),
+                    ..Default::default()
+                }],
+                text: Some(therapy_type.as_str().into()),
+                ..Default::default()
+            },
+        };
+        ("completed", medication)
     };
-    let medication = MedicationStatementMedication::Reference(Box::new(med_rfrnc));
+    let status = Code::from(status);
+    let medication = MedicationStatementMedication::CodeableConcept(Box::new(medication));
     let subject_rfrnc = Reference {
         reference: Some(subject_ref.into()),
         ..Default::default()
@@ -79,43 +110,263 @@ pub fn get_med_statement(
     }
 }
 
-pub fn get_bundle_entry(patient: MedicationStatement, patient_ref_id: &str) -> BundleEntry {
-    BundleEntry {
-        full_url: Some(get_full_url(
-            patient.clone().id.unwrap().value.unwrap().as_str(),
-        )),
-        resource: Some(Resource::MedicationStatement(Box::new(patient.clone()))),
-        request: get_bundle_entry_request("PUT", patient_ref_id).into_some(),
-        ..Default::default()
-    }
-}
-
-pub fn get_med_statements(
-    src_id: &str,
+/// Generates the medication statements of a systemic therapy of the given type, one per substance; a
+/// combined therapy (e.g. a chemoimmunotherapy) gives one substance of each of its drug classes, the
+/// further statements get a new id. Only the substances plausible for the diagnosis are drawn.
+#[allow(clippy::too_many_arguments)]
+pub fn get_therapy_med_statements(
+    i: &str,
+    subject_ref: &str,
     reason_ref: &str,
+    therapy_type: SystTherapyType,
+    diagnosis: &Diagnosis,
     start_date: NaiveDate,
     end_date: NaiveDate,
-    range: Range<u8>,
+    ids: &mut IdAllocator,
+    rng: &mut StdRng,
 ) -> Vec<(MedicationStatement, String)> {
-    range
-        .map(|_| {
-            let i: u16 = Faker.fake();
+    let substances: Vec<Option<Substance>> = if therapy_type.get_drug_classes().is_empty() {
+        vec![None]
+    } else {
+        therapy_type
+            .get_drug_classes()
+            .iter()
+            .map(|drug_class| {
+                let substances: Vec<Substance> = drug_class
+                    .get_substances()
+                    .into_iter()
+                    .filter(|substance| substance.is_plausible_for(diagnosis))
+                    .collect();
+                substances.choose(rng).copied()
+            })
+            .collect()
+    };
+
+    substances
+        .into_iter()
+        .enumerate()
+        .map(|(n, substance)| {
+            let i = if n == 0 { i.to_string() } else { ids.next_id() };
             let (med_stmt_id, med_stmt_ref_id) = get_ids(
                 IdType::Id,
                 ResourceType::SystemicTherapyMedicationStatement,
-                i,
+                &i,
+            );
+            let med_stmt = get_med_statement(
+                med_stmt_id.as_str(),
+                subject_ref,
+                reason_ref,
+                therapy_type,
+                substance,
+                start_date,
+                end_date,
             );
-            (
-                get_med_statement(
-                    med_stmt_id.as_str(),
-                    "medicine",
-                    src_id,
-                    reason_ref,
-                    start_date,
-                    end_date,
-                ),
-                med_stmt_ref_id,
-            )
+            (med_stmt, med_stmt_ref_id)
         })
         .collect()
 }
+
+pub fn get_bundle_entry(patient: MedicationStatement, patient_ref_id: &str) -> BundleEntry {
+    BundleEntry {
+        resource: Some(Resource::MedicationStatement(Box::new(patient.clone()))),
+        request: get_bundle_entry_request("PUT", patient_ref_id).into_some(),
+        ..Default::default()
+    }
+}
+
+/// Generates the medication statements of a systemic therapy of the patient's condition
+pub struct MedStatementGenerator;
+
+impl ResourceGenerator for MedStatementGenerator {
+    fn resource_type(&self) -> ResourceType {
+        ResourceType::SystemicTherapyMedicationStatement
+    }
+
+    fn dependencies(&self) -> &'static [ResourceType] {
+        &[ResourceType::Patient, ResourceType::Condition]
+    }
+
+    fn count_per_patient(&self, scenario: &Scenario) -> u8 {
+        scenario.resources_per_patient.medication_statements
+    }
+
+    fn generate(
+        &self,
+        i: &str,
+        context: &mut GeneratorContext,
+        ids: &mut IdAllocator,
+        scenario: &Scenario,
+        rng: &mut StdRng,
+    ) -> Vec<BundleEntry> {
+        let therapy_type = scenario.get_syst_therapy_type(rng);
+        context.therapy_types.push(therapy_type);
+        get_therapy_med_statements(
+            i,
+            context.get_ref_id(ResourceType::Patient).as_str(),
+            context.get_ref_id(ResourceType::Condition).as_str(),
+            therapy_type,
+            &context.diagnosis,
+            context.timeline.therapy_start_date,
+            context.timeline.therapy_end_date,
+            ids,
+            rng,
+        )
+        .into_iter()
+        .map(|(med_stmt, med_stmt_ref_id)| get_bundle_entry(med_stmt, med_stmt_ref_id.as_str()))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::SeedableRng;
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::models::cli::IdStrategy;
+    use crate::models::enums::drug_class::DrugClass;
+    use crate::models::staging::StagingScheme;
+
+    fn get_diagnosis(icd10_code: &'static str) -> Diagnosis {
+        Diagnosis {
+            icd10_code,
+            topography: "C80.9",
+            morphology: "8140/3",
+            staging_scheme: StagingScheme::Generic,
+        }
+    }
+
+    fn get_scenario_med_statements(toml: &str) -> Vec<(MedicationStatement, String)> {
+        get_diagnosis_med_statements(toml, &get_diagnosis("C34.1"), 1)
+    }
+
+    fn get_diagnosis_med_statements(
+        toml: &str,
+        diagnosis: &Diagnosis,
+        seed: u64,
+    ) -> Vec<(MedicationStatement, String)> {
+        let scenario = Scenario::from_toml(toml).expect("scenario is valid");
+        let mut ids = IdAllocator::new(IdStrategy::Sequential, None, 1).expect("ids are allocated");
+        let mut rng = StdRng::seed_from_u64(seed);
+        let date = NaiveDate::from_ymd_opt(2020, 1, 1).expect("date is valid");
+
+        get_therapy_med_statements(
+            &ids.next_id(),
+            "Patient/Patient-id-1",
+            "Condition/Condition-id-1",
+            scenario.get_syst_therapy_type(&mut rng),
+            diagnosis,
+            date,
+            date,
+            &mut ids,
+            &mut rng,
+        )
+    }
+
+    fn get_atc_code(med_stmt: &MedicationStatement) -> Option<String> {
+        match &med_stmt.medication {
+            MedicationStatementMedication::CodeableConcept(c) => {
+                c.coding.first()?.code.clone()?.value
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_get_therapy_med_statements_gives_a_substance_per_drug_class() {
+        let med_stmts = get_scenario_med_statements("[weights.syst_therapy_type]\nCIT = 1");
+
+        let drug_classes: Vec<DrugClass> = med_stmts
+            .iter()
+            .map(|(med_stmt, _)| {
+                let code = get_atc_code(med_stmt).expect("the substance is coded");
+                Substance::iter()
+                    .find(|s| s.as_str() == code)
+                    .expect("the substance is in the substance table")
+                    .get_drug_class()
+            })
+            .collect();
+        assert_eq!(
+            drug_classes,
+            vec![
+                DrugClass::Chemotherapy,
+                DrugClass::Immunotherapy,
+                DrugClass::TargetedTherapy
+            ],
+            "drug classes of a chemoimmunotherapy with targeted substances do not match"
+        );
+        let ref_ids: HashSet<&String> = med_stmts.iter().map(|(_, ref_id)| ref_id).collect();
+        assert_eq!(ref_ids.len(), 3, "the medication statements share an id");
+    }
+
+    #[test]
+    fn test_get_therapy_med_statements_codes_a_therapy_without_substance_by_its_type() {
+        let med_stmts = get_scenario_med_statements("[weights.syst_therapy_type]\nSC = 1");
+
+        let [(med_stmt, _)] = med_stmts.as_slice() else {
+            panic!("number of medication statements does not match");
+        };
+        let MedicationStatementMedication::CodeableConcept(medication) = &med_stmt.medication
+        else {
+            panic!("medication is not a CodeableConcept");
+        };
+        let coding = &medication.coding[0];
+        assert_eq!(
+            coding.system,
+            Some(get_syst_therapy_type_url()),
+            "system does not match"
+        );
+        assert_eq!(
+            coding.code.clone().and_then(|c| c.value).as_deref(),
+            Some("SC"),
+            "code does not match"
+        );
+        assert_eq!(
+            coding.display.clone().and_then(|d| d.value).as_deref(),
+            Some("Stem cell therapy"),
+            "display does not match"
+        );
+    }
+
+    #[test]
+    fn test_get_therapy_med_statements_takes_no_medication_under_surveillance() {
+        let med_stmts = get_scenario_med_statements("[weights.syst_therapy_type]\nAS = 1");
+
+        assert_eq!(
+            med_stmts.len(),
+            1,
+            "number of medication statements does not match"
+        );
+        let med_stmt = &med_stmts[0].0;
+        assert_eq!(med_stmt.status.value.as_deref(), Some("not-taken"));
+        assert_eq!(
+            get_atc_code(med_stmt),
+            None,
+            "a substance is taken under surveillance"
+        );
+    }
+
+    #[test]
+    fn test_get_therapy_med_statements_draws_the_hormone_therapy_of_the_entity() {
+        for (icd10_code, implausible) in [
+            ("C61", [Substance::Tamoxifen, Substance::Letrozole]),
+            ("C50.4", [Substance::Enzalutamide, Substance::Abiraterone]),
+        ] {
+            let diagnosis = get_diagnosis(icd10_code);
+            for seed in 0..50 {
+                let med_stmts = get_diagnosis_med_statements(
+                    "[weights.syst_therapy_type]\nHO = 1",
+                    &diagnosis,
+                    seed,
+                );
+                let code = get_atc_code(&med_stmts[0].0).expect("the substance is coded");
+                assert!(
+                    implausible.iter().all(|s| s.as_str() != code),
+                    "{code} is drawn for {icd10_code}"
+                );
+            }
+        }
+    }
+}
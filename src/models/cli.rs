@@ -1,4 +1,8 @@
-use clap::{command, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::utils::DATA_FOLDER;
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum OutputMode {
@@ -9,10 +13,188 @@ pub enum OutputMode {
     /// Store the generated XML in a file
     File,
 
-    /// Call the given API endpoint (WIP)
+    /// Upload the generated XML to the FHIR server given by --fhir-url
     ApiCall,
 }
 
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Format {
+    /// FHIR XML
+    #[default]
+    Xml,
+
+    /// FHIR JSON
+    Json,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Xml => "xml",
+            Format::Json => "json",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Format::Xml => "application/fhir+xml",
+            Format::Json => "application/fhir+json",
+        }
+    }
+}
+
+/// How the serialized FHIR resources are laid out (see `--pretty` and `--canonical`)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// As the serializer writes them, i.e. indented XML and single line JSON
+    #[default]
+    Plain,
+
+    /// Indented XML and JSON
+    Pretty,
+
+    /// Indented, with normalized whitespace (no trailing spaces, line feeds only and a final line
+    /// break), so that the files can be checked in and diffed
+    Canonical,
+}
+
+impl Layout {
+    pub fn is_pretty(&self) -> bool {
+        *self != Layout::Plain
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum FileLayout {
+    /// Write the bundles to the output directory
+    #[default]
+    Flat,
+
+    /// Write every resource to its own file, in a directory per patient
+    PerPatient,
+
+    /// Write every resource to its own file, in a directory per resource type
+    PerResourceType,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum BundleType {
+    /// The server processes all entries as a single unit, that succeeds or fails as a whole
+    #[default]
+    Transaction,
+
+    /// The server processes every entry on its own (the entries cannot reference each other)
+    Batch,
+
+    /// A plain collection of resources without requests, e.g. as input for ETL tests
+    Collection,
+}
+
+impl BundleType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BundleType::Transaction => "transaction",
+            BundleType::Batch => "batch",
+            BundleType::Collection => "collection",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum EntryRequest {
+    /// PUT every resource by its id, e.g. `Patient/Patient-id-1`
+    #[default]
+    Put,
+
+    /// POST every resource, the server assigns the ids; the entries get `urn:uuid:` fullUrls, which
+    /// the references within the bundle point to
+    Post,
+
+    /// Like post, but a resource is only created, if none with the same identifier exists
+    /// (`ifNoneExist`); the resources other than the patients get their id as identifier
+    ConditionalCreate,
+
+    /// PUT every resource by its identifier (e.g. `Patient?identifier=system|value`); the resources
+    /// other than the patients get their id as identifier
+    ConditionalUpdate,
+}
+
+impl EntryRequest {
+    /// Whether uploading the same bundle twice stores its resources only once, so that a failed upload
+    /// can safely be retried, even if the server might have processed it
+    pub fn is_idempotent(&self) -> bool {
+        *self != EntryRequest::Post
+    }
+}
+
+/// How the fullUrls of the bundle entries are built (see `--full-url-base` and `--urn-uuid`)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum FullUrl {
+    /// `urn:uuid:` values, which the references within the bundle point to
+    #[default]
+    UrnUuid,
+
+    /// The URL of the resource at the given server base (`<base>/<Type>/<id>`), against which the
+    /// relative references resolve
+    Base(String),
+}
+
+/// The type of the generated bundles, and the fullUrls and requests of their entries (see
+/// `--bundle-type`, `--entry-request` and `--full-url-base`)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BundleSemantics {
+    pub bundle_type: BundleType,
+    pub entry_request: EntryRequest,
+    pub full_url: FullUrl,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum ArchiveFormat {
+    /// A gzip compressed tar archive
+    #[value(name = "tar.gz")]
+    TarGz,
+
+    /// A zip archive
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+/// What happens to a file, which already exists in the output directory (see `--overwrite` and
+/// `--no-clobber`)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Replace the file and log a warning
+    #[default]
+    Warn,
+
+    /// Replace the file
+    Overwrite,
+
+    /// Keep the file and fail
+    NoClobber,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum IdStrategy {
+    /// Number the resources 1, 2, 3, ...
+    #[default]
+    Sequential,
+
+    /// Use random (version 4) UUIDs
+    Uuid,
+
+    /// Use a hash of the seed and a counter
+    SeedHash,
+}
+
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum ResourceType {
     /// Generate whole Bundle
@@ -37,6 +219,9 @@ pub enum ResourceType {
     /// Generate Observation TNMc
     ObservationTNMc,
 
+    /// Generate Observation TNMp
+    ObservationTNMp,
+
     /// Generate Procedure Radiotherapy
     ProcedureRadiotherapy,
 
@@ -57,6 +242,7 @@ impl ResourceType {
             ResourceType::ObservationHistology => "Histology",
             ResourceType::ObservationVitalStatus => "VitalStatus",
             ResourceType::ObservationTNMc => "TNMc",
+            ResourceType::ObservationTNMp => "TNMp",
             ResourceType::ProcedureRadiotherapy => "Radiotherapy",
             ResourceType::ProcedureOperation => "Operation",
             ResourceType::SystemicTherapyMedicationStatement => "SystemicTherapy",
@@ -72,6 +258,7 @@ impl ResourceType {
             ResourceType::ObservationHistology => "Observation",
             ResourceType::ObservationVitalStatus => "Observation",
             ResourceType::ObservationTNMc => "Observation",
+            ResourceType::ObservationTNMp => "Observation",
             ResourceType::ProcedureRadiotherapy => "Procedure",
             ResourceType::ProcedureOperation => "Procedure",
             ResourceType::SystemicTherapyMedicationStatement => "MedicationStatement",
@@ -86,6 +273,95 @@ impl ResourceType {
 pub struct CliArgs {
     #[command(subcommand)]
     pub cmd: Commands,
+
+    /// Seed for the random number generator; the same seed (and the same arguments) always generates
+    /// the same data on the same day, or on any day with a max_date in the scenario. A random seed is
+    /// used (and logged) if none is given
+    #[arg(short, long, global = true)]
+    pub seed: Option<u64>,
+
+    /// Format of the generated FHIR resources (synthetic data and FHIR profiles)
+    #[arg(long, global = true, value_enum, default_value_t = Format::Xml)]
+    pub format: Format,
+
+    /// Indent the generated FHIR resources (XML is always indented)
+    #[arg(long, global = true)]
+    pub pretty: bool,
+
+    /// Write canonical FHIR resources to check in and diff: indented, with normalized whitespace and
+    /// generated with a fixed seed (unless --seed is given) and the fixed max date 2025-12-31 (unless
+    /// the scenario has a max_date), so that the same arguments always generate the same output
+    #[arg(long, global = true)]
+    pub canonical: bool,
+
+    /// Directory the generated files are written to (with `-o file`)
+    #[arg(long, global = true, default_value = DATA_FOLDER)]
+    pub out_dir: PathBuf,
+
+    /// Replace the existing files in the output directory without a warning
+    #[arg(long, global = true)]
+    pub overwrite: bool,
+
+    /// Fail instead of replacing an existing file in the output directory
+    #[arg(long, global = true, conflicts_with = "overwrite")]
+    pub no_clobber: bool,
+
+    /// Pack the generated files (and the manifest) into a single compressed archive, named after the
+    /// output directory (e.g. `generated-data.tar.gz`), instead of writing them to the directory
+    #[arg(long, global = true, value_enum)]
+    pub archive: Option<ArchiveFormat>,
+}
+
+impl CliArgs {
+    pub fn layout(&self) -> Layout {
+        if self.canonical {
+            Layout::Canonical
+        } else if self.pretty {
+            Layout::Pretty
+        } else {
+            Layout::Plain
+        }
+    }
+
+    pub fn overwrite_policy(&self) -> OverwritePolicy {
+        if self.no_clobber {
+            OverwritePolicy::NoClobber
+        } else if self.overwrite {
+            OverwritePolicy::Overwrite
+        } else {
+            OverwritePolicy::Warn
+        }
+    }
+}
+
+/// The FHIR server the generated bundles are uploaded to (with `-o api-call`)
+#[derive(Args, Debug, Clone, Default)]
+pub struct FhirServerArgs {
+    /// Base URL of the FHIR server, the bundles are uploaded to (with `-o api-call`)
+    #[arg(long)]
+    pub fhir_url: Option<String>,
+
+    /// User name for the basic auth at the FHIR server
+    #[arg(long, requires = "fhir_url")]
+    pub fhir_user: Option<String>,
+
+    /// Password for the basic auth at the FHIR server
+    #[arg(long, env = "FHIR_PASSWORD", hide_env_values = true)]
+    pub fhir_password: Option<String>,
+
+    /// Bearer token for the FHIR server (ignored, if a user is given)
+    #[arg(long, env = "FHIR_TOKEN", hide_env_values = true)]
+    pub fhir_token: Option<String>,
+
+    /// HTTP(S) proxy to reach the FHIR server through
+    #[arg(long, requires = "fhir_url")]
+    pub proxy_url: Option<String>,
+
+    /// How often an upload is retried, if the server is unavailable (429, 503) or the connection is
+    /// lost or times out (unless the entries are POSTed); the delay doubles with every retry, starting
+    /// at 1 s, and is at most 60 s
+    #[arg(long, default_value_t = 3)]
+    pub max_retries: u32,
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -94,7 +370,7 @@ pub enum Commands {
     SyntheticData {
         /// Number of resources to generate
         #[arg(short, long, default_value_t = 1)]
-        number: u8,
+        number: u64,
 
         /// Type of resource to generate
         #[arg(short, long, value_enum, default_value_t=ResourceType::Bundle)]
@@ -103,6 +379,65 @@ pub enum Commands {
         /// Where to store the resources
         #[arg(short, long, value_enum, default_value_t=OutputMode::Screen)]
         output_mode: OutputMode,
+
+        /// TOML file with the distributions (weights, age at diagnosis, deceased rate, gender ratio,
+        /// neoadjuvant, recurrence and multiple tumours rates and resources per patient) to draw the
+        /// data from, and the max date of the data
+        #[arg(long)]
+        scenario: Option<PathBuf>,
+
+        /// How to build the (unique) ids of the resources
+        #[arg(long, value_enum, default_value_t=IdStrategy::Sequential)]
+        id_strategy: IdStrategy,
+
+        /// Prefix for the ids, to keep them unique across runs (up to 8 alphanumeric characters)
+        #[arg(long)]
+        id_namespace: Option<String>,
+
+        /// Maximum number of entries per bundle; the patients (with all of their resources) are split
+        /// into several bundles (only with `-r bundle`). An uploaded cohort is split into bundles of
+        /// 1000 entries by default, as every bundle is held in memory until it is uploaded
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        bundle_size: Option<u64>,
+
+        /// Write the cohort as FHIR Bulk Data NDJSON files (one per resource type) with an
+        /// `$export`-style manifest, instead of transaction bundles (only with `-r bundle -o file`)
+        #[arg(long)]
+        ndjson: bool,
+
+        /// URL the NDJSON files are to be downloaded from, the export manifest lists them as
+        /// `<url>/<Type>.ndjson`; defaults to the `file://` URL of the output directory
+        #[arg(long, requires = "ndjson")]
+        ndjson_url: Option<String>,
+
+        /// How the generated files are laid out in the output directory (with `-o file`); with a
+        /// directory per patient or resource type, every resource is written to its own file (only
+        /// with `-r bundle`)
+        #[arg(long, value_enum, default_value_t=FileLayout::Flat, conflicts_with = "ndjson")]
+        file_layout: FileLayout,
+
+        /// Type of the generated bundles
+        #[arg(long, value_enum, default_value_t=BundleType::Transaction)]
+        bundle_type: BundleType,
+
+        /// How the server is requested to store the resources of the bundle entries; the entries of a
+        /// collection get the same fullUrls (and references), but no requests
+        #[arg(long, value_enum, default_value_t=EntryRequest::Put)]
+        entry_request: EntryRequest,
+
+        /// Server base the fullUrls of the bundle entries are built from (`<base>/<Type>/<id>`);
+        /// defaults to the --fhir-url. Without a base, the entries get `urn:uuid:` fullUrls, which the
+        /// references within the bundle point to
+        #[arg(long)]
+        full_url_base: Option<String>,
+
+        /// Give the bundle entries `urn:uuid:` fullUrls (and point the references within the bundle to
+        /// them), even if a server base is known
+        #[arg(long, conflicts_with = "full_url_base")]
+        urn_uuid: bool,
+
+        #[command(flatten)]
+        fhir_server: Box<FhirServerArgs>,
     },
 
     #[command(about = "Create catalogue JSON for the CCE explorer (UI)")]
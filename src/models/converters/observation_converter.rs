@@ -3,12 +3,15 @@ use fhirbolt::model::r4b::resources::Observation;
 use crate::{
     fhir::traits::CodeSystemAdapter,
     models::enums::{
-        tnmm_category::TnmmCategory, tnmn_category::TnmnCategory, tnmt_category::TnmtCategory,
+        tnmm_category::TnmmCategory, tnmm_symbol::TnmmSymbol, tnmn_category::TnmnCategory,
+        tnmr_symbol::TnmrSymbol, tnmt_category::TnmtCategory, tnmy_symbol::TnmySymbol,
         tumor_site_location::TumorSiteLocation, uicc_stage::UiccStage,
     },
     utils::{
         CLINICAL_METASTASES_LOINC_CODE, CLINICAL_NODES_LOINC_CODE, CLINICAL_STAGE_GROUP_LOINC_CODE,
-        CLINICAL_TUMOR_LOINC_CODE,
+        CLINICAL_TUMOR_LOINC_CODE, PATHOLOGIC_METASTASES_LOINC_CODE, PATHOLOGIC_NODES_LOINC_CODE,
+        PATHOLOGIC_TUMOR_LOINC_CODE, TNM_M_SYMBOL_LOINC_CODE, TNM_R_SYMBOL_LOINC_CODE,
+        TNM_Y_SYMBOL_LOINC_CODE,
     },
 };
 
@@ -58,11 +61,67 @@ impl CategoryConverter for Observation {
                 Category::SingleSelect(tnmm),
             ],
         );
+        let ptnmt = SingleSelectCategory::new(
+            PATHOLOGIC_TUMOR_LOINC_CODE,
+            "pTNM-T",
+            "",
+            TnmtCategory::get_criteria(),
+        );
+        let ptnmn = SingleSelectCategory::new(
+            PATHOLOGIC_NODES_LOINC_CODE,
+            "pTNM-N",
+            "",
+            TnmnCategory::get_criteria(),
+        );
+        let ptnmm = SingleSelectCategory::new(
+            PATHOLOGIC_METASTASES_LOINC_CODE,
+            "pTNM-M",
+            "",
+            TnmmCategory::get_criteria(),
+        );
+        let ptnm_group = CategoryGroup::new(
+            "ptnm",
+            "TNM(p)",
+            vec![
+                Category::SingleSelect(ptnmt),
+                Category::SingleSelect(ptnmn),
+                Category::SingleSelect(ptnmm),
+            ],
+        );
+        let tnmy = SingleSelectCategory::new(
+            TNM_Y_SYMBOL_LOINC_CODE,
+            "y-Symbol",
+            TnmySymbol::get_url().as_str(),
+            TnmySymbol::get_criteria(),
+        );
+        let tnmr = SingleSelectCategory::new(
+            TNM_R_SYMBOL_LOINC_CODE,
+            "r-Symbol",
+            TnmrSymbol::get_url().as_str(),
+            TnmrSymbol::get_criteria(),
+        );
+        let tnmm_symbol = SingleSelectCategory::new(
+            TNM_M_SYMBOL_LOINC_CODE,
+            "m-Symbol",
+            TnmmSymbol::get_url().as_str(),
+            TnmmSymbol::get_criteria(),
+        );
+        let tnm_symbols_group = CategoryGroup::new(
+            "tnm_symbols",
+            "TNM symbols",
+            vec![
+                Category::SingleSelect(tnmy),
+                Category::SingleSelect(tnmr),
+                Category::SingleSelect(tnmm_symbol),
+            ],
+        );
 
         let child_categories = vec![
             Category::SingleSelect(site_location),
             Category::SingleSelect(uicc_stage),
             Category::Group(tnm_group),
+            Category::Group(ptnm_group),
+            Category::Group(tnm_symbols_group),
         ];
         let category_group = CategoryGroup::new(
             "tumor_classification",
@@ -34,4 +34,34 @@ mod test_specimen_converter {
             .expect("Failed to serialize categories to JSON");
         println!("Catalog of specimen categories:\n{json}");
     }
+
+    #[test]
+    fn test_get_criteria_nests_the_subtypes() {
+        let criteria = SampleMaterialType::get_criteria();
+        let keys: Vec<&str> = criteria.iter().map(|c| c.key.as_str()).collect();
+        assert!(
+            !keys.contains(&"tumor-tissue-ffpe"),
+            "a subtype is listed at the top level"
+        );
+
+        let tissue_ffpe = criteria
+            .iter()
+            .find(|c| c.key == "tissue-ffpe")
+            .expect("tissue-ffpe is listed");
+        let subgroup: Vec<&str> = tissue_ffpe
+            .subgroup
+            .iter()
+            .flatten()
+            .map(|c| c.key.as_str())
+            .collect();
+        assert_eq!(
+            subgroup,
+            vec![
+                "tumor-tissue-ffpe",
+                "normal-tissue-ffpe",
+                "other-tissue-ffpe"
+            ],
+            "subgroup of tissue-ffpe does not match"
+        );
+    }
 }
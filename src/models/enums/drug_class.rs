@@ -0,0 +1,20 @@
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+use crate::models::enums::substance::Substance;
+
+#[derive(Clone, Copy, Debug, Display, EnumIter, PartialEq)]
+pub enum DrugClass {
+    Chemotherapy,
+    HormoneTherapy,
+    Immunotherapy,
+    TargetedTherapy,
+}
+
+impl DrugClass {
+    /// The substances of this drug class in the substance table
+    pub fn get_substances(&self) -> Vec<Substance> {
+        Substance::iter()
+            .filter(|substance| substance.get_drug_class() == *self)
+            .collect()
+    }
+}
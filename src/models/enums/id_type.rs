@@ -18,7 +18,7 @@ impl IdType {
         }
     }
 
-    pub fn get_id(&self, i: u16) -> String {
+    pub fn get_id(&self, i: &str) -> String {
         match self {
             IdType::Id => format!("{}-{}", self.as_str(), i),
             IdType::Identifier => format!("src-{}-{}", self.as_str(), i),
@@ -2,7 +2,8 @@ use fake::Dummy;
 
 use crate::utils::{
     CLINICAL_METASTASES_LOINC_CODE, CLINICAL_NODES_LOINC_CODE, CLINICAL_STAGE_GROUP_LOINC_CODE,
-    CLINICAL_TUMOR_LOINC_CODE,
+    CLINICAL_TUMOR_LOINC_CODE, PATHOLOGIC_METASTASES_LOINC_CODE, PATHOLOGIC_NODES_LOINC_CODE,
+    PATHOLOGIC_STAGE_GROUP_LOINC_CODE, PATHOLOGIC_TUMOR_LOINC_CODE,
 };
 
 #[derive(Debug, Dummy)]
@@ -14,7 +15,7 @@ pub enum TnmClassification {
 impl TnmClassification {
     pub fn as_str(&self) -> &'static str {
         match self {
-            TnmClassification::Pathologic => "21902-2",
+            TnmClassification::Pathologic => PATHOLOGIC_STAGE_GROUP_LOINC_CODE,
             // TODO: ... check if this is correct
             TnmClassification::Clinical => CLINICAL_STAGE_GROUP_LOINC_CODE,
         }
@@ -30,7 +31,7 @@ pub enum TnmtClassification {
 impl TnmtClassification {
     pub fn as_str(&self) -> &'static str {
         match self {
-            TnmtClassification::Pathologic => "21899-0",
+            TnmtClassification::Pathologic => PATHOLOGIC_TUMOR_LOINC_CODE,
             TnmtClassification::Clinical => CLINICAL_TUMOR_LOINC_CODE,
         }
     }
@@ -45,7 +46,7 @@ pub enum TnmnClassification {
 impl TnmnClassification {
     pub fn as_str(&self) -> &'static str {
         match self {
-            TnmnClassification::Pathologic => "21900-6",
+            TnmnClassification::Pathologic => PATHOLOGIC_NODES_LOINC_CODE,
             TnmnClassification::Clinical => CLINICAL_NODES_LOINC_CODE,
         }
     }
@@ -60,7 +61,7 @@ pub enum TnmmClassification {
 impl TnmmClassification {
     pub fn as_str(&self) -> &'static str {
         match self {
-            TnmmClassification::Pathologic => "21901-4",
+            TnmmClassification::Pathologic => PATHOLOGIC_METASTASES_LOINC_CODE,
             TnmmClassification::Clinical => CLINICAL_METASTASES_LOINC_CODE,
         }
     }
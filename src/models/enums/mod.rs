@@ -1,9 +1,12 @@
+pub mod drug_class;
 pub mod gender;
 pub mod id_type;
 pub mod loinc_codes;
 pub mod sample_material_type;
+pub mod substance;
 pub mod syst_therapy_type;
 pub mod tnmm_category;
+pub mod tnmm_symbol;
 pub mod tnmn_category;
 pub mod tnmr_symbol;
 pub mod tnmt_category;
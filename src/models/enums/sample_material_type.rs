@@ -1,48 +1,46 @@
-use std::vec;
-
 use fake::Dummy;
-use strum::Display;
+use strum::{Display, EnumIter, IntoEnumIterator};
 
 use crate::{
     fhir::traits::CodeSystemAdapter,
     lens::{catalogue::Criteria, traits::CriteriaConverter},
 };
 
-#[derive(Debug, Display, Dummy)]
+#[derive(Clone, Copy, Debug, Display, Dummy, EnumIter, PartialEq)]
 pub enum SampleMaterialType {
     WholeBlood,
     BoneMarrow,
-    // BuffyCoat,
-    // DriedWholeBlood,
-    // PeripheralBloodMononuclearCells,
+    BuffyCoat,
+    DriedWholeBlood,
+    PeripheralBloodMononuclearCells,
     BloodPlasma,
-    // PlasmaEdta,
-    // PlasmaCitrat,
-    // PlasmaHeparin,
-    // PlasmaCellFree,
-    // PlasmaOther,
+    PlasmaEdta,
+    PlasmaCitrat,
+    PlasmaHeparin,
+    PlasmaCellFree,
+    PlasmaOther,
     BloodSerum,
-    // Ascites,
+    Ascites,
     CsfLiquor,
-    // Saliva,
+    Saliva,
     StoolFaeces,
     Urine,
-    // Swab,
-    // OtherLiquid,
-    // TissueFfpe,
+    Swab,
+    OtherLiquid,
+    TissueFfpe,
     TumorTissueFfpe,
     NormalTissueFfpe,
-    // OtherTissueFfpe,
-    // TissueFrozen,
+    OtherTissueFfpe,
+    TissueFrozen,
     TumorTissueFrozen,
     NormalTissueFrozen,
-    // OtherTissueFrozen,
-    // OtherTissueStorage,
+    OtherTissueFrozen,
+    OtherTissueStorage,
     Dna,
-    // CfDna,
-    // GDna,
+    CfDna,
+    GDna,
     Rna,
-    // OtherDerivative,
+    OtherDerivative,
 }
 
 impl SampleMaterialType {
@@ -50,37 +48,141 @@ impl SampleMaterialType {
         match self {
             SampleMaterialType::WholeBlood => "whole-blood",
             SampleMaterialType::BoneMarrow => "bone-marrow",
-            // SampleMaterialType::BuffyCoat => "buffy-coat",
-            // SampleMaterialType::DriedWholeBlood => "dried-whole-blood",
-            // SampleMaterialType::PeripheralBloodMononuclearCells => "peripheral-blood-cells-vital",
+            SampleMaterialType::BuffyCoat => "buffy-coat",
+            SampleMaterialType::DriedWholeBlood => "dried-whole-blood",
+            SampleMaterialType::PeripheralBloodMononuclearCells => "peripheral-blood-cells-vital",
             SampleMaterialType::BloodPlasma => "blood-plasma",
-            // SampleMaterialType::PlasmaEdta => "plasma-edta",
-            // SampleMaterialType::PlasmaCitrat => "plasma-citrat",
-            // SampleMaterialType::PlasmaHeparin => "plasma-heparin",
-            // SampleMaterialType::PlasmaCellFree => "plasma-cell-free",
-            // SampleMaterialType::PlasmaOther => "plasma-other",
+            SampleMaterialType::PlasmaEdta => "plasma-edta",
+            SampleMaterialType::PlasmaCitrat => "plasma-citrat",
+            SampleMaterialType::PlasmaHeparin => "plasma-heparin",
+            SampleMaterialType::PlasmaCellFree => "plasma-cell-free",
+            SampleMaterialType::PlasmaOther => "plasma-other",
             SampleMaterialType::BloodSerum => "blood-serum",
-            // SampleMaterialType::Ascites => "ascites",
+            SampleMaterialType::Ascites => "ascites",
             SampleMaterialType::CsfLiquor => "csf-liquor",
-            // SampleMaterialType::Saliva => "saliva",
+            SampleMaterialType::Saliva => "saliva",
             SampleMaterialType::StoolFaeces => "stool-faeces",
             SampleMaterialType::Urine => "urine",
-            // SampleMaterialType::Swab => "swab",
-            // SampleMaterialType::OtherLiquid => "liquid-other",
-            // SampleMaterialType::TissueFfpe => "tissue-ffpe",
+            SampleMaterialType::Swab => "swab",
+            SampleMaterialType::OtherLiquid => "liquid-other",
+            SampleMaterialType::TissueFfpe => "tissue-ffpe",
             SampleMaterialType::TumorTissueFfpe => "tumor-tissue-ffpe",
             SampleMaterialType::NormalTissueFfpe => "normal-tissue-ffpe",
-            // SampleMaterialType::OtherTissueFfpe => "other-tissue-ffpe",
-            // SampleMaterialType::TissueFrozen => "tissue-frozen",
+            SampleMaterialType::OtherTissueFfpe => "other-tissue-ffpe",
+            SampleMaterialType::TissueFrozen => "tissue-frozen",
             SampleMaterialType::TumorTissueFrozen => "tumor-tissue-frozen",
             SampleMaterialType::NormalTissueFrozen => "normal-tissue-frozen",
-            // SampleMaterialType::OtherTissueFrozen => "other-tissue-frozen",
-            // SampleMaterialType::OtherTissueStorage => "tissue-other",
+            SampleMaterialType::OtherTissueFrozen => "other-tissue-frozen",
+            SampleMaterialType::OtherTissueStorage => "tissue-other",
             SampleMaterialType::Dna => "dna",
-            // SampleMaterialType::CfDna => "cf-dna",
-            // SampleMaterialType::GDna => "g-dna",
+            SampleMaterialType::CfDna => "cf-dna",
+            SampleMaterialType::GDna => "g-dna",
             SampleMaterialType::Rna => "rna",
-            // SampleMaterialType::OtherDerivative => "derivative-other",
+            SampleMaterialType::OtherDerivative => "derivative-other",
+        }
+    }
+
+    /// The user-facing name of the sample material type
+    pub fn name(&self) -> &'static str {
+        match self {
+            SampleMaterialType::WholeBlood => "Whole Blood",
+            SampleMaterialType::BoneMarrow => "Bone Marrow",
+            SampleMaterialType::BuffyCoat => "Buffy Coat",
+            SampleMaterialType::DriedWholeBlood => "Dried Whole Blood",
+            SampleMaterialType::PeripheralBloodMononuclearCells => {
+                "Peripheral Blood Mononuclear Cells (PBMC)"
+            }
+            SampleMaterialType::BloodPlasma => "Plasma",
+            SampleMaterialType::PlasmaEdta => "Plasma (EDTA)",
+            SampleMaterialType::PlasmaCitrat => "Plasma (Citrat)",
+            SampleMaterialType::PlasmaHeparin => "Plasma (Heparin)",
+            SampleMaterialType::PlasmaCellFree => "Plasma (Cell-free)",
+            SampleMaterialType::PlasmaOther => "Plasma (Other)",
+            SampleMaterialType::BloodSerum => "Serum",
+            SampleMaterialType::Ascites => "Ascites",
+            SampleMaterialType::CsfLiquor => "Liquor/CSF",
+            SampleMaterialType::Saliva => "Saliva",
+            SampleMaterialType::StoolFaeces => "Stool/Faeces",
+            SampleMaterialType::Urine => "Urine",
+            SampleMaterialType::Swab => "Swab",
+            SampleMaterialType::OtherLiquid => "Other Liquid",
+            SampleMaterialType::TissueFfpe => "Tissue (FFPE)",
+            SampleMaterialType::TumorTissueFfpe => "Tumor Tissue (FFPE)",
+            SampleMaterialType::NormalTissueFfpe => "Normal Tissue (FFPE)",
+            SampleMaterialType::OtherTissueFfpe => "Other Tissue (FFPE)",
+            SampleMaterialType::TissueFrozen => "Tissue (Frozen)",
+            SampleMaterialType::TumorTissueFrozen => "Tumor Tissue (Frozen)",
+            SampleMaterialType::NormalTissueFrozen => "Normal Tissue (Frozen)",
+            SampleMaterialType::OtherTissueFrozen => "Other Tissue (Frozen)",
+            SampleMaterialType::OtherTissueStorage => "Other Tissue Storage",
+            SampleMaterialType::Dna => "DNA",
+            SampleMaterialType::CfDna => "cfDNA",
+            SampleMaterialType::GDna => "gDNA",
+            SampleMaterialType::Rna => "RNA",
+            SampleMaterialType::OtherDerivative => "Other Derivative",
+        }
+    }
+
+    /// The more specific types of this sample material type, e.g. tumour, normal and other tissue of
+    /// FFPE tissue
+    pub fn get_subtypes(&self) -> &'static [SampleMaterialType] {
+        use SampleMaterialType as S;
+
+        match self {
+            S::BloodPlasma => &[
+                S::PlasmaEdta,
+                S::PlasmaCitrat,
+                S::PlasmaHeparin,
+                S::PlasmaCellFree,
+                S::PlasmaOther,
+            ],
+            S::TissueFfpe => &[S::TumorTissueFfpe, S::NormalTissueFfpe, S::OtherTissueFfpe],
+            S::TissueFrozen => &[
+                S::TumorTissueFrozen,
+                S::NormalTissueFrozen,
+                S::OtherTissueFrozen,
+            ],
+            S::Dna => &[S::CfDna, S::GDna],
+            _ => &[],
+        }
+    }
+
+    /// Whether the sample material is (FFPE, frozen or otherwise stored) tissue, which is taken from
+    /// a body site, unlike the liquids and the derivatives
+    pub fn is_tissue(&self) -> bool {
+        use SampleMaterialType as S;
+
+        matches!(
+            self,
+            S::TissueFfpe
+                | S::TumorTissueFfpe
+                | S::NormalTissueFfpe
+                | S::OtherTissueFfpe
+                | S::TissueFrozen
+                | S::TumorTissueFrozen
+                | S::NormalTissueFrozen
+                | S::OtherTissueFrozen
+                | S::OtherTissueStorage
+        )
+    }
+
+    /// The sample material types, which a derivative (DNA, RNA, ...) can be extracted from; a sample
+    /// material, which is not a derivative, has none
+    pub fn get_source_types(&self) -> &'static [SampleMaterialType] {
+        use SampleMaterialType as S;
+
+        match self {
+            // cell-free DNA circulates in the blood plasma
+            S::CfDna => &[S::PlasmaCellFree, S::PlasmaEdta],
+            S::Dna | S::GDna | S::Rna | S::OtherDerivative => &[
+                S::WholeBlood,
+                S::BuffyCoat,
+                S::TumorTissueFfpe,
+                S::NormalTissueFfpe,
+                S::TumorTissueFrozen,
+                S::NormalTissueFrozen,
+            ],
+            _ => &[],
         }
     }
 }
@@ -108,79 +210,34 @@ impl CodeSystemAdapter for SampleMaterialType {
 }
 
 impl CriteriaConverter for SampleMaterialType {
+    /// The criteria of the sample material types, the more specific types are in the subgroup of
+    /// their (more general) type
     fn get_criteria() -> Vec<Criteria> {
-        let whole_blood = Criteria::new_with_description(
-            SampleMaterialType::WholeBlood.as_str(),
-            "Whole Blood",
-            "Whole Blood",
-        );
-        let bone_marrow = Criteria::new_with_description(
-            SampleMaterialType::BoneMarrow.as_str(),
-            "Bone Marrow",
-            "Bone Marrow",
-        );
-        let plasma = Criteria::new_with_description(
-            SampleMaterialType::BloodPlasma.as_str(),
-            "Plasma",
-            "Plasma",
-        );
-        let serum = Criteria::new_with_description(
-            SampleMaterialType::BloodSerum.as_str(),
-            "Serum",
-            "Serum",
-        );
-        let csf_liquor = Criteria::new_with_description(
-            SampleMaterialType::CsfLiquor.as_str(),
-            "Liquor/CSF",
-            "Liquor/CSF",
-        );
-        let stool_faeces = Criteria::new_with_description(
-            SampleMaterialType::StoolFaeces.as_str(),
-            "Stool/Faeces",
-            "Stool/Faeces",
-        );
-        let urine = Criteria::new_with_description(
-            SampleMaterialType::Urine.as_str(),
-            SampleMaterialType::Urine.to_string().as_str(),
-            "Urine",
-        );
-        let tumor_tissue_ffpe = Criteria::new_with_description(
-            SampleMaterialType::TumorTissueFfpe.as_str(),
-            "Tumor Tissue (FFPE)",
-            "Tumor Tissue (FFPE)",
-        );
-        let normal_tissue_ffpe = Criteria::new_with_description(
-            SampleMaterialType::NormalTissueFfpe.as_str(),
-            SampleMaterialType::NormalTissueFfpe.to_string().as_str(),
-            "Normal Tissue (FFPE)",
-        );
-        let tumor_tissue_frozen = Criteria::new_with_description(
-            SampleMaterialType::TumorTissueFrozen.as_str(),
-            "Tumor Tissue (Frozen)",
-            "Tumor Tissue (Frozen)",
-        );
-        let normal_tissue_frozen = Criteria::new_with_description(
-            SampleMaterialType::NormalTissueFrozen.as_str(),
-            "Normal Tissue (Frozen)",
-            "Normal Tissue (Frozen)",
-        );
-        let dna = Criteria::new_with_description(SampleMaterialType::Dna.as_str(), "DNA", "DNA");
-        let rna = Criteria::new_with_description(SampleMaterialType::Rna.as_str(), "RNA", "RNA");
-
-        vec![
-            tumor_tissue_ffpe,
-            tumor_tissue_frozen,
-            normal_tissue_ffpe,
-            normal_tissue_frozen,
-            whole_blood,
-            serum,
-            plasma,
-            urine,
-            csf_liquor,
-            stool_faeces,
-            bone_marrow,
-            dna,
-            rna,
-        ]
+        let subtypes: Vec<SampleMaterialType> = SampleMaterialType::iter()
+            .flat_map(|smt| smt.get_subtypes().iter().copied())
+            .collect();
+
+        SampleMaterialType::iter()
+            .filter(|smt| !subtypes.contains(smt))
+            .map(|smt| {
+                let mut criteria = get_criteria(smt);
+                let subgroup: Vec<Criteria> = smt
+                    .get_subtypes()
+                    .iter()
+                    .copied()
+                    .map(get_criteria)
+                    .collect();
+                criteria.subgroup = (!subgroup.is_empty()).then_some(subgroup);
+                criteria
+            })
+            .collect()
     }
 }
+
+fn get_criteria(sample_material_type: SampleMaterialType) -> Criteria {
+    Criteria::new_with_description(
+        sample_material_type.as_str(),
+        sample_material_type.name(),
+        sample_material_type.name(),
+    )
+}
@@ -0,0 +1,249 @@
+use strum::{Display, EnumIter};
+
+use crate::models::enums::drug_class::DrugClass;
+use crate::models::tumor_entity::Diagnosis;
+
+/// The substance table of common oncology drugs, coded by ATC
+#[derive(Clone, Copy, Debug, Display, EnumIter, PartialEq)]
+pub enum Substance {
+    // cytostatics
+    Cisplatin,
+    Carboplatin,
+    Oxaliplatin,
+    Cyclophosphamide,
+    Pemetrexed,
+    Fluorouracil,
+    Gemcitabine,
+    Capecitabine,
+    Paclitaxel,
+    Docetaxel,
+    Doxorubicin,
+    // hormone antagonists and analogues
+    Leuprorelin,
+    Goserelin,
+    Tamoxifen,
+    Enzalutamide,
+    Anastrozole,
+    Letrozole,
+    Exemestane,
+    Abiraterone,
+    // immune checkpoint inhibitors
+    Nivolumab,
+    Pembrolizumab,
+    Atezolizumab,
+    Ipilimumab,
+    // monoclonal antibodies against a target of the tumour (CD20, HER2, EGFR, VEGF)
+    Rituximab,
+    Trastuzumab,
+    Cetuximab,
+    Bevacizumab,
+    // small molecule (e.g. kinase) inhibitors
+    Imatinib,
+    Erlotinib,
+    Osimertinib,
+    Dabrafenib,
+    Trametinib,
+    Palbociclib,
+    Sunitinib,
+    Sorafenib,
+    Olaparib,
+}
+
+impl Substance {
+    /// The ATC code of the substance
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Substance::Cisplatin => "L01XA01",
+            Substance::Carboplatin => "L01XA02",
+            Substance::Oxaliplatin => "L01XA03",
+            Substance::Cyclophosphamide => "L01AA01",
+            Substance::Pemetrexed => "L01BA04",
+            Substance::Fluorouracil => "L01BC02",
+            Substance::Gemcitabine => "L01BC05",
+            Substance::Capecitabine => "L01BC06",
+            Substance::Paclitaxel => "L01CD01",
+            Substance::Docetaxel => "L01CD02",
+            Substance::Doxorubicin => "L01DB01",
+            Substance::Leuprorelin => "L02AE02",
+            Substance::Goserelin => "L02AE03",
+            Substance::Tamoxifen => "L02BA01",
+            Substance::Enzalutamide => "L02BB04",
+            Substance::Anastrozole => "L02BG03",
+            Substance::Letrozole => "L02BG04",
+            Substance::Exemestane => "L02BG06",
+            Substance::Abiraterone => "L02BX03",
+            Substance::Nivolumab => "L01FF01",
+            Substance::Pembrolizumab => "L01FF02",
+            Substance::Atezolizumab => "L01FF05",
+            Substance::Ipilimumab => "L01FX04",
+            Substance::Rituximab => "L01FA01",
+            Substance::Trastuzumab => "L01FD01",
+            Substance::Cetuximab => "L01FE01",
+            Substance::Bevacizumab => "L01FG01",
+            Substance::Imatinib => "L01EA01",
+            Substance::Erlotinib => "L01EB02",
+            Substance::Osimertinib => "L01EB04",
+            Substance::Dabrafenib => "L01EC02",
+            Substance::Trametinib => "L01EE01",
+            Substance::Palbociclib => "L01EF01",
+            Substance::Sunitinib => "L01EX01",
+            Substance::Sorafenib => "L01EX02",
+            Substance::Olaparib => "L01XK01",
+        }
+    }
+
+    pub fn get_drug_class(&self) -> DrugClass {
+        match self {
+            Substance::Cisplatin
+            | Substance::Carboplatin
+            | Substance::Oxaliplatin
+            | Substance::Cyclophosphamide
+            | Substance::Pemetrexed
+            | Substance::Fluorouracil
+            | Substance::Gemcitabine
+            | Substance::Capecitabine
+            | Substance::Paclitaxel
+            | Substance::Docetaxel
+            | Substance::Doxorubicin => DrugClass::Chemotherapy,
+            Substance::Leuprorelin
+            | Substance::Goserelin
+            | Substance::Tamoxifen
+            | Substance::Enzalutamide
+            | Substance::Anastrozole
+            | Substance::Letrozole
+            | Substance::Exemestane
+            | Substance::Abiraterone => DrugClass::HormoneTherapy,
+            Substance::Nivolumab
+            | Substance::Pembrolizumab
+            | Substance::Atezolizumab
+            | Substance::Ipilimumab => DrugClass::Immunotherapy,
+            Substance::Rituximab
+            | Substance::Trastuzumab
+            | Substance::Cetuximab
+            | Substance::Bevacizumab
+            | Substance::Imatinib
+            | Substance::Erlotinib
+            | Substance::Osimertinib
+            | Substance::Dabrafenib
+            | Substance::Trametinib
+            | Substance::Palbociclib
+            | Substance::Sunitinib
+            | Substance::Sorafenib
+            | Substance::Olaparib => DrugClass::TargetedTherapy,
+        }
+    }
+
+    /// The ICD-10-GM categories of the entities the substance is given for, if it is restricted to some
+    fn get_icd10_categories(&self) -> Option<&'static [&'static str]> {
+        match self {
+            // the antioestrogen and the aromatase inhibitors treat the hormone receptor positive breast,
+            // endometrial and ovarian cancers
+            Substance::Tamoxifen
+            | Substance::Anastrozole
+            | Substance::Letrozole
+            | Substance::Exemestane => Some(&["C50", "C54", "C56"]),
+            // the androgen receptor and synthesis inhibitors only treat the prostate cancer
+            Substance::Enzalutamide | Substance::Abiraterone => Some(&["C61"]),
+            // NOTE: the GnRH analogues (of the breast and the prostate cancer) are not restricted, so that
+            // a hormone therapy of every entity has a substance
+            _ => None,
+        }
+    }
+
+    /// Whether the substance is given for the tumour entity of the diagnosis, which also rules out the
+    /// substances of the other sex (e.g. an aromatase inhibitor for a prostate cancer)
+    pub fn is_plausible_for(&self, diagnosis: &Diagnosis) -> bool {
+        self.get_icd10_categories().is_none_or(|categories| {
+            categories
+                .iter()
+                .any(|category| diagnosis.icd10_code.starts_with(category))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::models::staging::StagingScheme;
+
+    fn get_diagnosis(icd10_code: &'static str) -> Diagnosis {
+        Diagnosis {
+            icd10_code,
+            topography: "C80.9",
+            morphology: "8140/3",
+            staging_scheme: StagingScheme::Generic,
+        }
+    }
+
+    #[test]
+    fn test_as_str_is_an_atc_code_of_the_antineoplastic_agents() {
+        for substance in Substance::iter() {
+            let code = substance.as_str();
+            // L01: antineoplastic agents, L02: endocrine therapy
+            assert!(
+                code.len() == 7 && (code.starts_with("L01") || code.starts_with("L02")),
+                "{substance} has no ATC code of a 5th level antineoplastic agent: {code}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_drug_class_of_the_immunotherapy_is_a_checkpoint_inhibitor() {
+        assert_eq!(
+            DrugClass::Immunotherapy.get_substances(),
+            vec![
+                Substance::Nivolumab,
+                Substance::Pembrolizumab,
+                Substance::Atezolizumab,
+                Substance::Ipilimumab
+            ],
+            "immunotherapy substances do not match"
+        );
+        assert_eq!(
+            Substance::Trastuzumab.get_drug_class(),
+            DrugClass::TargetedTherapy,
+            "drug class of an antibody against a tumour target does not match"
+        );
+    }
+
+    #[test]
+    fn test_is_plausible_for_rules_out_the_hormone_therapy_of_the_other_sex() {
+        let prostate = get_diagnosis("C61");
+        let breast = get_diagnosis("C50.4");
+        let lung = get_diagnosis("C34.1");
+
+        for substance in [Substance::Tamoxifen, Substance::Letrozole] {
+            assert!(
+                substance.is_plausible_for(&breast),
+                "{substance} for breast"
+            );
+            assert!(
+                !substance.is_plausible_for(&prostate),
+                "{substance} for prostate"
+            );
+        }
+        for substance in [Substance::Enzalutamide, Substance::Abiraterone] {
+            assert!(
+                substance.is_plausible_for(&prostate),
+                "{substance} for prostate"
+            );
+            assert!(
+                !substance.is_plausible_for(&breast),
+                "{substance} for breast"
+            );
+        }
+        assert!(
+            DrugClass::HormoneTherapy
+                .get_substances()
+                .iter()
+                .any(|s| s.is_plausible_for(&lung)),
+            "no hormone therapy substance for lung"
+        );
+        assert!(
+            Substance::Cisplatin.is_plausible_for(&prostate),
+            "an unrestricted substance is not plausible"
+        );
+    }
+}
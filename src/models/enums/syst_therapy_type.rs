@@ -1,29 +1,37 @@
 use fake::Dummy;
-use strum::Display;
+use fhirbolt::model::r4b::types::Narrative;
+use strum::{Display, EnumIter, IntoEnumIterator};
 
+use crate::fhir::{
+    globals::get_generated_narrative,
+    traits::{CodeSystemAdapter, CodeSystemConceptAdapter, CodeSystemConceptHelper},
+};
 use crate::lens::{
     catalogue::{Category, CategoryGroup, Criteria, SingleSelectCategory},
     traits::CategoryConverter,
 };
+use crate::models::enums::drug_class::DrugClass;
 
-#[derive(Clone, Debug, Display, Dummy)]
+// NOTE: the variant names are the codes of the oBDS, not acronyms
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, Display, Dummy, EnumIter, PartialEq)]
 pub enum SystTherapyType {
     CH,
     HO,
     IM,
-    // BM,
-    // WS,
-    // AS,
-    // TS,
-    // MI,
+    BM,
+    WS,
+    AS,
+    TS,
+    MI,
     RT,
     OP,
-    // CI,
-    // CT,
-    // CIT,
-    // IT,
+    CI,
+    CT,
+    CIT,
+    IT,
     SC,
-    // WW,
+    WW,
 }
 
 impl SystTherapyType {
@@ -32,63 +40,135 @@ impl SystTherapyType {
             SystTherapyType::CH => "Chemotherapy",
             SystTherapyType::HO => "Hormone therapy",
             SystTherapyType::IM => "Immunotherapy",
-            // SystTherapyType::BM => "Bone marrow transplantation",
-            // SystTherapyType::WS => "Wait and see",
-            // SystTherapyType::AS => "Active Surveillance",
-            // SystTherapyType::TS => "Targeted substances",
-            // SystTherapyType::MI => "Miscellaneous",
+            SystTherapyType::BM => "Bone marrow transplantation",
+            SystTherapyType::WS => "Wait and see",
+            SystTherapyType::AS => "Active Surveillance",
+            SystTherapyType::TS => "Targeted substances",
+            SystTherapyType::MI => "Miscellaneous",
             SystTherapyType::RT => "Radiotherapy",
             SystTherapyType::OP => "Operation",
-            // SystTherapyType::CI => "Chemo- + Immuno-/Antibody therapy",
-            // SystTherapyType::CT => "Chemotherapy + Targeted substances",
-            // SystTherapyType::CIT => "Chemo- + Immuno-/Antibody therapy + Targeted substances",
-            // SystTherapyType::IT => "Immuno-/Antibody therapy + Targeted substances",
+            SystTherapyType::CI => "Chemo- + Immuno-/Antibody therapy",
+            SystTherapyType::CT => "Chemotherapy + Targeted substances",
+            SystTherapyType::CIT => "Chemo- + Immuno-/Antibody therapy + Targeted substances",
+            SystTherapyType::IT => "Immuno-/Antibody therapy + Targeted substances",
             SystTherapyType::SC => "Stem cell therapy",
-            // SystTherapyType::WW => "Watchful Waiting",
+            SystTherapyType::WW => "Watchful Waiting",
+        }
+    }
+
+    /// An operation or a radiotherapy is a procedure, the other therapies are (systemic) medication
+    /// statements
+    pub fn is_procedure(&self) -> bool {
+        matches!(self, SystTherapyType::OP | SystTherapyType::RT)
+    }
+
+    /// The classes of the drugs, which a therapy of this type combines; one substance of each class is
+    /// given
+    pub fn get_drug_classes(&self) -> &'static [DrugClass] {
+        use DrugClass as D;
+
+        match self {
+            SystTherapyType::CH => &[D::Chemotherapy],
+            SystTherapyType::HO => &[D::HormoneTherapy],
+            SystTherapyType::IM => &[D::Immunotherapy],
+            SystTherapyType::TS => &[D::TargetedTherapy],
+            SystTherapyType::CI => &[D::Chemotherapy, D::Immunotherapy],
+            SystTherapyType::CT => &[D::Chemotherapy, D::TargetedTherapy],
+            SystTherapyType::CIT => &[D::Chemotherapy, D::Immunotherapy, D::TargetedTherapy],
+            SystTherapyType::IT => &[D::Immunotherapy, D::TargetedTherapy],
+            _ => &[],
         }
     }
+
+    /// The tumour is only monitored (instead of being treated), i.e. no medication is taken
+    pub fn is_surveillance(&self) -> bool {
+        matches!(
+            self,
+            SystTherapyType::WS | SystTherapyType::AS | SystTherapyType::WW
+        )
+    }
+}
+
+impl CodeSystemAdapter for SystTherapyType {
+    fn get_name() -> String {
+        "SYSTTherapyTypeCS".to_string()
+    }
+
+    fn get_title() -> String {
+        "SYST Therapy Type CS".to_string()
+    }
+
+    fn get_description() -> String {
+        "SYSTTherapyType".to_string()
+    }
+
+    fn get_html_description() -> String {
+        "SYSTTherapyType CodeSystem".to_string()
+    }
+
+    fn get_url() -> String {
+        Self::get_code_system_url(Self::get_name().as_str())
+    }
+}
+
+impl CodeSystemConceptAdapter for SystTherapyType {
+    fn get_concepts() -> Vec<CodeSystemConceptHelper> {
+        SystTherapyType::iter()
+            .map(|therapy_type| CodeSystemConceptHelper {
+                code: therapy_type.to_string(),
+                display: therapy_type.as_str().to_string(),
+            })
+            .collect()
+    }
+
+    fn get_narrative() -> Narrative {
+        get_generated_narrative(
+            Self::get_html_description().as_str(),
+            Self::get_description().as_str(),
+        )
+    }
 }
 
 impl CategoryConverter for SystTherapyType {
     fn get_category() -> Category {
-        let op = Criteria::new(
-            SystTherapyType::OP.to_string().as_str(),
-            SystTherapyType::OP.as_str(),
-        );
-        let rt = Criteria::new(
-            SystTherapyType::RT.to_string().as_str(),
-            SystTherapyType::RT.as_str(),
-        );
-
-        let operation_radiotherapy_category =
-            SingleSelectCategory::new("procedure", "Operation / Radiotherapy", "", vec![op, rt]);
+        let get_criteria = |filter: fn(&SystTherapyType) -> bool| {
+            SystTherapyType::iter()
+                .filter(filter)
+                .map(|therapy_type| {
+                    Criteria::new(therapy_type.to_string().as_str(), therapy_type.as_str())
+                })
+                .collect()
+        };
 
-        let ch = Criteria::new(
-            SystTherapyType::CH.to_string().as_str(),
-            SystTherapyType::CH.as_str(),
-        );
-        let ho = Criteria::new(
-            SystTherapyType::HO.to_string().as_str(),
-            SystTherapyType::HO.as_str(),
-        );
-        let im = Criteria::new(
-            SystTherapyType::IM.to_string().as_str(),
-            SystTherapyType::IM.as_str(),
-        );
-        let sc = Criteria::new(
-            SystTherapyType::SC.to_string().as_str(),
-            SystTherapyType::SC.as_str(),
+        let operation_radiotherapy_category = SingleSelectCategory::new(
+            "procedure",
+            "Operation / Radiotherapy",
+            "",
+            get_criteria(SystTherapyType::is_procedure),
         );
 
-        let other_therapy_category = SingleSelectCategory::new(
+        // the drug therapies and the surveillance are both medication statements, hence they share
+        // the key (and the chip in the search bar)
+        let mut drug_therapy_category = SingleSelectCategory::new(
             "medicationStatement",
             "Other Therapies",
             "",
-            vec![ch, ho, im, sc],
+            get_criteria(|t| !t.is_procedure() && !t.is_surveillance()),
         );
+        drug_therapy_category.sub_category_name = Some("Drug Therapies".to_string());
+        let mut surveillance_category = SingleSelectCategory::new(
+            "medicationStatement",
+            "Other Therapies",
+            "",
+            get_criteria(SystTherapyType::is_surveillance),
+        );
+        surveillance_category.sub_category_name =
+            Some("Watchful Waiting / Surveillance".to_string());
+
         let child_categories = vec![
             Category::SingleSelect(operation_radiotherapy_category),
-            Category::SingleSelect(other_therapy_category),
+            Category::SingleSelect(drug_therapy_category),
+            Category::SingleSelect(surveillance_category),
         ];
         let category_group =
             CategoryGroup::new("therapy_of_tumor", "Therapy of tumor", child_categories);
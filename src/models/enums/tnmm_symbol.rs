@@ -0,0 +1,81 @@
+use fake::Dummy;
+use fhirbolt::model::r4b::types::Narrative;
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::{
+    fhir::{
+        globals::get_generated_narrative,
+        traits::{CodeSystemAdapter, CodeSystemConceptAdapter, CodeSystemConceptHelper},
+    },
+    lens::{catalogue::Criteria, traits::CriteriaConverter},
+};
+
+#[derive(Clone, Copy, Debug, Dummy, EnumIter, PartialEq)]
+pub enum TnmmSymbol {
+    M,
+}
+
+const M_TUPLE: (&str, &str) = ("m", "Multiple primary tumours at a single site");
+
+impl TnmmSymbol {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TnmmSymbol::M => M_TUPLE.0,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            TnmmSymbol::M => M_TUPLE.1,
+        }
+    }
+}
+
+impl CodeSystemAdapter for TnmmSymbol {
+    fn get_name() -> String {
+        "TNMmSymbolCS".to_string()
+    }
+
+    fn get_title() -> String {
+        "TNM m-Symbol CS".to_string()
+    }
+
+    fn get_description() -> String {
+        "The m-symbol of a TNM classification of multiple primary tumours at a single site"
+            .to_string()
+    }
+
+    fn get_html_description() -> String {
+        "TNM m-Symbol CodeSystem".to_string()
+    }
+
+    fn get_url() -> String {
+        Self::get_code_system_url(Self::get_name().as_str())
+    }
+}
+
+impl CodeSystemConceptAdapter for TnmmSymbol {
+    fn get_concepts() -> Vec<CodeSystemConceptHelper> {
+        TnmmSymbol::iter()
+            .map(|symbol| CodeSystemConceptHelper {
+                code: symbol.code().to_string(),
+                display: symbol.description().to_string(),
+            })
+            .collect()
+    }
+
+    fn get_narrative() -> Narrative {
+        get_generated_narrative(
+            Self::get_html_description().as_str(),
+            Self::get_description().as_str(),
+        )
+    }
+}
+
+impl CriteriaConverter for TnmmSymbol {
+    fn get_criteria() -> Vec<Criteria> {
+        TnmmSymbol::iter()
+            .map(|symbol| Criteria::new(symbol.code(), symbol.description()))
+            .collect()
+    }
+}
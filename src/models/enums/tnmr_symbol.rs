@@ -1,26 +1,84 @@
 use fake::Dummy;
+use fhirbolt::model::r4b::types::Narrative;
+use strum::{EnumIter, IntoEnumIterator};
 
-#[derive(Debug, Dummy)]
+use crate::{
+    fhir::{
+        globals::get_generated_narrative,
+        traits::{CodeSystemAdapter, CodeSystemConceptAdapter, CodeSystemConceptHelper},
+    },
+    lens::{catalogue::Criteria, traits::CriteriaConverter},
+};
+
+#[derive(Clone, Copy, Debug, Dummy, EnumIter, PartialEq)]
 pub enum TnmrSymbol {
     R,
     Nine,
 }
 
-// const R_TUPLE: (&str, &str) = ("r", "Classification was used to assess a recurrence");
-// const NINE_TUPLE: (&str, &str) = ("9", "Native classification before a recurrence");
+const R_TUPLE: (&str, &str) = ("r", "Classification was used to assess a recurrence");
+const NINE_TUPLE: (&str, &str) = ("9", "Native classification before a recurrence");
 
 impl TnmrSymbol {
-    // pub fn code(&self) -> &'static str {
-    //     match self {
-    //         TnmrSymbol::R => R_TUPLE.0,
-    //         TnmrSymbol::Nine => NINE_TUPLE.0,
-    //     }
-    // }
-
-    // pub fn description(&self) -> &'static str {
-    //     match self {
-    //         TnmrSymbol::R => R_TUPLE.1,
-    //         TnmrSymbol::Nine => NINE_TUPLE.1,
-    //     }
-    // }
+    pub fn code(&self) -> &'static str {
+        match self {
+            TnmrSymbol::R => R_TUPLE.0,
+            TnmrSymbol::Nine => NINE_TUPLE.0,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            TnmrSymbol::R => R_TUPLE.1,
+            TnmrSymbol::Nine => NINE_TUPLE.1,
+        }
+    }
+}
+
+impl CodeSystemAdapter for TnmrSymbol {
+    fn get_name() -> String {
+        "TNMrSymbolCS".to_string()
+    }
+
+    fn get_title() -> String {
+        "TNM r-Symbol CS".to_string()
+    }
+
+    fn get_description() -> String {
+        "The r-symbol of a TNM classification of a recurrence".to_string()
+    }
+
+    fn get_html_description() -> String {
+        "TNM r-Symbol CodeSystem".to_string()
+    }
+
+    fn get_url() -> String {
+        Self::get_code_system_url(Self::get_name().as_str())
+    }
+}
+
+impl CodeSystemConceptAdapter for TnmrSymbol {
+    fn get_concepts() -> Vec<CodeSystemConceptHelper> {
+        TnmrSymbol::iter()
+            .map(|symbol| CodeSystemConceptHelper {
+                code: symbol.code().to_string(),
+                display: symbol.description().to_string(),
+            })
+            .collect()
+    }
+
+    fn get_narrative() -> Narrative {
+        get_generated_narrative(
+            Self::get_html_description().as_str(),
+            Self::get_description().as_str(),
+        )
+    }
+}
+
+impl CriteriaConverter for TnmrSymbol {
+    fn get_criteria() -> Vec<Criteria> {
+        TnmrSymbol::iter()
+            .map(|symbol| Criteria::new(symbol.code(), symbol.description()))
+            .collect()
+    }
 }
@@ -3,7 +3,7 @@ use strum::Display;
 
 use crate::lens::{catalogue::Criteria, traits::CriteriaConverter};
 
-#[derive(Debug, Display, Dummy)]
+#[derive(Clone, Copy, Debug, Display, Dummy, PartialEq)]
 pub enum TnmtCategory {
     Zero,
     One,
@@ -1,26 +1,87 @@
 use fake::Dummy;
+use fhirbolt::model::r4b::types::Narrative;
+use strum::{EnumIter, IntoEnumIterator};
 
-#[derive(Debug, Dummy)]
+use crate::{
+    fhir::{
+        globals::get_generated_narrative,
+        traits::{CodeSystemAdapter, CodeSystemConceptAdapter, CodeSystemConceptHelper},
+    },
+    lens::{catalogue::Criteria, traits::CriteriaConverter},
+};
+
+#[derive(Clone, Copy, Debug, Dummy, EnumIter, PartialEq)]
 pub enum TnmySymbol {
     Y,
     Nine,
 }
 
-// const Y_TUPLE: (&str, &str) = ("y", "Classification occurred during or after initial multimodal therapy");
-// const NINE_TUPLE: (&str, &str) = ("9", "Native classification");
+const Y_TUPLE: (&str, &str) = (
+    "y",
+    "Classification occurred during or after initial multimodal therapy",
+);
+const NINE_TUPLE: (&str, &str) = ("9", "Native classification");
 
 impl TnmySymbol {
-    // pub fn code(&self) -> &'static str {
-    //     match self {
-    //         TnmySymbol::Y => Y_TUPLE.0,
-    //         TnmySymbol::Nine => NINE_TUPLE.0,
-    //     }
-    // }
-
-    // pub fn description(&self) -> &'static str {
-    //     match self {
-    //         TnmySymbol::Y => Y_TUPLE.1,
-    //         TnmySymbol::Nine => NINE_TUPLE.1,
-    //     }
-    // }
+    pub fn code(&self) -> &'static str {
+        match self {
+            TnmySymbol::Y => Y_TUPLE.0,
+            TnmySymbol::Nine => NINE_TUPLE.0,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            TnmySymbol::Y => Y_TUPLE.1,
+            TnmySymbol::Nine => NINE_TUPLE.1,
+        }
+    }
+}
+
+impl CodeSystemAdapter for TnmySymbol {
+    fn get_name() -> String {
+        "TNMySymbolCS".to_string()
+    }
+
+    fn get_title() -> String {
+        "TNM y-Symbol CS".to_string()
+    }
+
+    fn get_description() -> String {
+        "The y-symbol of a TNM classification, determined during or after a (neoadjuvant) multimodal therapy".to_string()
+    }
+
+    fn get_html_description() -> String {
+        "TNM y-Symbol CodeSystem".to_string()
+    }
+
+    fn get_url() -> String {
+        Self::get_code_system_url(Self::get_name().as_str())
+    }
+}
+
+impl CodeSystemConceptAdapter for TnmySymbol {
+    fn get_concepts() -> Vec<CodeSystemConceptHelper> {
+        TnmySymbol::iter()
+            .map(|symbol| CodeSystemConceptHelper {
+                code: symbol.code().to_string(),
+                display: symbol.description().to_string(),
+            })
+            .collect()
+    }
+
+    fn get_narrative() -> Narrative {
+        get_generated_narrative(
+            Self::get_html_description().as_str(),
+            Self::get_description().as_str(),
+        )
+    }
+}
+
+impl CriteriaConverter for TnmySymbol {
+    fn get_criteria() -> Vec<Criteria> {
+        TnmySymbol::iter()
+            .map(|symbol| Criteria::new(symbol.code(), symbol.description()))
+            .collect()
+    }
 }
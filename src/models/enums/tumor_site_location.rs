@@ -1,9 +1,9 @@
 use fake::Dummy;
-use strum::Display;
+use strum::{Display, EnumIter};
 
 use crate::lens::{catalogue::Criteria, traits::CriteriaConverter};
 
-#[derive(Debug, Display, Dummy)]
+#[derive(Debug, Display, Dummy, EnumIter)]
 pub enum TumorSiteLocation {
     L,
     R,
@@ -1,17 +1,19 @@
 use fake::Dummy;
-use strum::Display;
+use strum::{Display, EnumIter};
 
 use crate::{
     fhir::traits::CodeSystemAdapter,
     lens::{catalogue::Criteria, traits::CriteriaConverter},
 };
 
-#[derive(Debug, Display, Dummy)]
+// NOTE: the variant names are roman numerals, not acronyms
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, Display, Dummy, EnumIter, PartialEq)]
 pub enum UiccStage {
     Zero,
     ZeroA,
     ZeroIs,
-    // I,
+    I,
     IA,
     IA1,
     IA2,
@@ -44,7 +46,7 @@ impl UiccStage {
             UiccStage::Zero => "0",
             UiccStage::ZeroA => "0a",
             UiccStage::ZeroIs => "0is",
-            // UiccStage::I => "I",
+            UiccStage::I => "I",
             UiccStage::IA => "IA",
             UiccStage::IA1 => "IA1",
             UiccStage::IA2 => "IA2",
@@ -103,7 +105,7 @@ impl CriteriaConverter for UiccStage {
         let ia2 = Criteria::new(UiccStage::IA2.as_str(), UiccStage::IA2.as_str());
         let ia1 = Criteria::new(UiccStage::IA1.as_str(), UiccStage::IA1.as_str());
         let ia = Criteria::new(UiccStage::IA.as_str(), UiccStage::IA.as_str());
-        // let i = Criteria::new(UiccStage::I.as_str(), UiccStage::I.as_str());
+        let i = Criteria::new(UiccStage::I.as_str(), UiccStage::I.as_str());
         let ib2 = Criteria::new(UiccStage::IB2.as_str(), UiccStage::IB2.as_str());
         let ib1 = Criteria::new(UiccStage::IB1.as_str(), UiccStage::IB1.as_str());
         let ib = Criteria::new(UiccStage::IB.as_str(), UiccStage::IB.as_str());
@@ -125,7 +127,7 @@ impl CriteriaConverter for UiccStage {
         let iv = Criteria::new(UiccStage::IV.as_str(), UiccStage::IV.as_str());
         let is = Criteria::new(UiccStage::IS.as_str(), UiccStage::IS.as_str());
         vec![
-            ois, oa, o, ia2, ia1, ia, ib2, ib1, ib, iic, iib, iia2, iia1, iia, ii, iiic2, iiic1,
+            ois, oa, o, ia2, ia1, ia, i, ib2, ib1, ib, iic, iib, iia2, iia1, iia, ii, iiic2, iiic1,
             iiic, iiib, iiia, iii, ivc, ivb, iva, iv, is,
         ]
     }
@@ -1,6 +1,6 @@
 use fake::Dummy;
 use fhirbolt::model::r4b::types::Narrative;
-use strum::Display;
+use strum::{Display, EnumIter};
 
 use crate::{
     fhir::{
@@ -10,7 +10,7 @@ use crate::{
     lens::{catalogue::Criteria, traits::CriteriaConverter},
 };
 
-#[derive(Debug, Display, Dummy)]
+#[derive(Clone, Copy, Debug, Display, Dummy, EnumIter, PartialEq)]
 pub enum VitalStatus {
     Alive,
     Deceased,
@@ -0,0 +1,133 @@
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+
+use crate::models::cli::IdStrategy;
+use crate::utils;
+
+const MAX_NAMESPACE_LEN: usize = 8;
+// decorrelates the allocator's rng from the one generating the data
+const ID_RNG_SALT: u64 = 0x1d5a_110c_a70e_5eed;
+
+/// Allocates the unique values, which the ids of the generated resources are built from (see
+/// [crate::utils::get_ids]). The values are unique within a run; with a namespace, they are also unique
+/// across runs (that use different namespaces).
+///
+/// The allocator draws from its own rng, hence the strategy does not change the generated data.
+#[derive(Debug)]
+pub struct IdAllocator {
+    strategy: IdStrategy,
+    namespace: Option<String>,
+    seed: u64,
+    counter: u64,
+    rng: StdRng,
+}
+
+impl IdAllocator {
+    pub fn new(strategy: IdStrategy, namespace: Option<String>, seed: u64) -> Result<Self, String> {
+        if let Some(ns) = &namespace {
+            if ns.is_empty()
+                || ns.len() > MAX_NAMESPACE_LEN
+                || !ns.chars().all(|c| c.is_ascii_alphanumeric())
+            {
+                return Err(format!(
+                    "id namespace '{ns}' must have 1 - {MAX_NAMESPACE_LEN} alphanumeric characters"
+                ));
+            }
+        }
+
+        Ok(Self {
+            strategy,
+            namespace,
+            seed,
+            counter: 0,
+            rng: StdRng::seed_from_u64(seed ^ ID_RNG_SALT),
+        })
+    }
+
+    /// Returns the next unique value
+    pub fn next_id(&mut self) -> String {
+        self.counter += 1;
+        let value = match self.strategy {
+            IdStrategy::Sequential => self.counter.to_string(),
+            IdStrategy::Uuid => self.get_uuid(),
+            // splitmix64 is a bijection, hence every counter value gives a different hash
+            IdStrategy::SeedHash => {
+                format!("{:016x}", splitmix64(self.seed.wrapping_add(self.counter)))
+            }
+        };
+
+        match &self.namespace {
+            Some(ns) => format!("{ns}-{value}"),
+            None => value,
+        }
+    }
+
+    /// Draws a random (version 4) UUID; with 122 random bits, a collision is not to be expected
+    fn get_uuid(&mut self) -> String {
+        let bits: u128 = self.rng.random();
+        let uuid = (bits & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+        utils::format_uuid(uuid)
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn get_values(strategy: IdStrategy, namespace: Option<&str>, n: usize) -> Vec<String> {
+        let mut ids =
+            IdAllocator::new(strategy, namespace.map(String::from), 7).expect("allocator is valid");
+        (0..n).map(|_| ids.next_id()).collect()
+    }
+
+    #[test]
+    fn test_next_id_is_unique() {
+        for strategy in [
+            IdStrategy::Sequential,
+            IdStrategy::Uuid,
+            IdStrategy::SeedHash,
+        ] {
+            let values = get_values(strategy, None, 10_000);
+            let unique: HashSet<&String> = values.iter().collect();
+            assert_eq!(unique.len(), values.len(), "{strategy:?} ids collide");
+        }
+    }
+
+    #[test]
+    fn test_next_id_formats() {
+        assert_eq!(
+            get_values(IdStrategy::Sequential, Some("run1"), 2),
+            vec!["run1-1", "run1-2"],
+            "sequential ids do not match"
+        );
+
+        let uuid = &get_values(IdStrategy::Uuid, None, 1)[0];
+        assert_eq!(uuid.len(), 36, "uuid has the wrong length");
+        assert_eq!(&uuid[14..15], "4", "uuid is not a version 4 uuid");
+
+        assert_eq!(
+            get_values(IdStrategy::SeedHash, None, 3),
+            get_values(IdStrategy::SeedHash, None, 3),
+            "seed hash ids are not reproducible"
+        );
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_namespaces() {
+        for ns in ["", "a-b", "toolongnamespace"] {
+            assert!(
+                IdAllocator::new(IdStrategy::Sequential, Some(ns.to_string()), 1).is_err(),
+                "namespace is not rejected: {ns}"
+            );
+        }
+    }
+}
@@ -1,3 +1,9 @@
 pub mod cli;
 pub mod converters;
 pub mod enums;
+pub mod id_allocator;
+pub mod patient_attributes;
+pub mod scenario;
+pub mod staging;
+pub mod timeline;
+pub mod tumor_entity;
@@ -0,0 +1,27 @@
+use rand::rngs::StdRng;
+
+use crate::models::enums::gender::Gender;
+use crate::models::scenario::Scenario;
+use crate::models::timeline::Timeline;
+
+/// The attributes of a patient, which the other resources of the patient (e.g. the diagnosis) depend on
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatientAttributes {
+    pub gender: Gender,
+    pub age_at_diagnosis: u32,
+}
+
+impl PatientAttributes {
+    pub fn new(timeline: &Timeline, scenario: &Scenario, rng: &mut StdRng) -> Self {
+        let gender = scenario.get_gender(rng);
+        let age_at_diagnosis = timeline
+            .diagnosis_date
+            .years_since(timeline.birth_date)
+            .unwrap_or_default();
+
+        Self {
+            gender,
+            age_at_diagnosis,
+        }
+    }
+}
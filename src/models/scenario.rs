@@ -0,0 +1,444 @@
+//! A scenario sets the distributions the synthetic data is drawn from. It is read from a TOML file (see
+//! `scenario.example.toml`), everything that is not set in the file keeps its default.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+use chrono::{Days, NaiveDate};
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::RngExt;
+use serde::{de, Deserialize, Deserializer};
+use strum::IntoEnumIterator;
+
+use crate::models::enums::gender::Gender;
+use crate::models::enums::sample_material_type::SampleMaterialType;
+use crate::models::enums::syst_therapy_type::SystTherapyType;
+use crate::models::enums::tumor_site_location::TumorSiteLocation;
+use crate::models::enums::uicc_stage::UiccStage;
+use crate::models::enums::vital_status::VitalStatus;
+use crate::models::staging::StagingScheme;
+use crate::utils::{get_max_date_time, get_min_date_time_millenial};
+
+const MIN_AGE_AT_DIAGNOSIS: u32 = 18;
+const MAX_AGE_AT_DIAGNOSIS: u32 = 90;
+const MAX_AGE: u32 = 120;
+// the max date of canonical data (unless the scenario sets one), which must not depend on the day
+const CANONICAL_MAX_DATE: NaiveDate = NaiveDate::from_ymd_opt(2025, 12, 31).expect("date is valid");
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    /// Share of the patients who are deceased (0.0 - 1.0)
+    pub deceased_rate: f64,
+    /// Share of the patients who are male (0.0 - 1.0)
+    pub male_ratio: f64,
+    /// Share of the operated patients, whose (radio or systemic) therapy is a neoadjuvant one before the
+    /// resection, otherwise it is an adjuvant one after the resection (0.0 - 1.0)
+    pub neoadjuvant_rate: f64,
+    /// Share of the patients whose tumour recurs after the therapy (0.0 - 1.0)
+    pub recurrence_rate: f64,
+    /// Share of the patients with multiple primary tumours at a single site (0.0 - 1.0)
+    pub multiple_tumors_rate: f64,
+    /// The latest date of the generated data (e.g. `2025-12-31`), today if none is given; pin it to
+    /// generate the same data with the same seed on any day
+    #[serde(deserialize_with = "deserialize_date")]
+    pub max_date: Option<NaiveDate>,
+    /// Age groups (age at diagnosis) and their relative frequencies
+    pub age_at_diagnosis: Vec<AgeGroup>,
+    pub resources_per_patient: ResourcesPerPatient,
+    pub weights: Weights,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            deceased_rate: 0.5,
+            male_ratio: 0.5,
+            neoadjuvant_rate: 0.2,
+            recurrence_rate: 0.1,
+            multiple_tumors_rate: 0.05,
+            max_date: None,
+            age_at_diagnosis: vec![AgeGroup {
+                min: MIN_AGE_AT_DIAGNOSIS,
+                max: MAX_AGE_AT_DIAGNOSIS,
+                weight: 1,
+            }],
+            resources_per_patient: ResourcesPerPatient::default(),
+            weights: Weights::default(),
+        }
+    }
+}
+
+/// Ages (at diagnosis) from min to max (both inclusive), with their relative frequency
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AgeGroup {
+    pub min: u32,
+    pub max: u32,
+    pub weight: u32,
+}
+
+/// Number of resources of each type generated for a patient in a bundle
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourcesPerPatient {
+    pub specimens: u8,
+    pub tnmcs: u8,
+    pub radiotherapies: u8,
+    pub operations: u8,
+    /// The number of systemic therapies, a combined therapy has a medication statement per substance
+    pub medication_statements: u8,
+}
+
+impl Default for ResourcesPerPatient {
+    fn default() -> Self {
+        Self {
+            specimens: 1,
+            tnmcs: 1,
+            radiotherapies: 1,
+            operations: 1,
+            medication_statements: 1,
+        }
+    }
+}
+
+/// Relative frequencies of the values of an enum, keyed by the name of the enum variant (e.g. `IVC` or
+/// `TumorTissueFfpe`). Values which are not listed are not generated; if no weights are given for an
+/// enum, all of its values are equally likely.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
+    pub vital_status: HashMap<String, u32>,
+    pub sample_material_type: HashMap<String, u32>,
+    pub uicc_stage: HashMap<String, u32>,
+    pub syst_therapy_type: HashMap<String, u32>,
+    pub tumor_site_location: HashMap<String, u32>,
+}
+
+impl Scenario {
+    /// Reads (and validates) the scenario from the given TOML file
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let toml = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read scenario {}: {e}", path.display()))?;
+        Self::from_toml(&toml).map_err(|e| format!("invalid scenario {}: {e}", path.display()))
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, String> {
+        let scenario: Self = toml::from_str(toml).map_err(|e| e.to_string())?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (name, rate) in [
+            ("deceased_rate", self.deceased_rate),
+            ("male_ratio", self.male_ratio),
+            ("neoadjuvant_rate", self.neoadjuvant_rate),
+            ("recurrence_rate", self.recurrence_rate),
+            ("multiple_tumors_rate", self.multiple_tumors_rate),
+        ] {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(format!("{name} must be between 0.0 and 1.0"));
+            }
+        }
+
+        if let Some(max_date) = self.max_date {
+            // the diagnosis lies between get_min_date_time_millenial() and a year before the max date
+            let min_max_date = get_min_date_time_millenial().date_naive() + Days::new(365);
+            if max_date < min_max_date || max_date > get_max_date_time().date_naive() {
+                return Err(format!(
+                    "max_date must lie between {min_max_date} and today"
+                ));
+            }
+        }
+
+        if self.age_at_diagnosis.iter().all(|group| group.weight == 0) {
+            return Err("age_at_diagnosis needs at least one group with a positive weight".into());
+        }
+        for group in &self.age_at_diagnosis {
+            if group.min < 1 || group.min > group.max || group.max > MAX_AGE {
+                return Err(format!(
+                    "age group {}-{} must satisfy 1 <= min <= max <= {MAX_AGE}",
+                    group.min, group.max
+                ));
+            }
+        }
+
+        if self.resources_per_patient.specimens == 0 {
+            return Err("a patient needs at least one specimen (for the histology)".into());
+        }
+
+        validate_weights::<VitalStatus>("vital_status", &self.weights.vital_status)?;
+        validate_weights::<SampleMaterialType>(
+            "sample_material_type",
+            &self.weights.sample_material_type,
+        )?;
+        validate_weights::<UiccStage>("uicc_stage", &self.weights.uicc_stage)?;
+        validate_weights::<SystTherapyType>("syst_therapy_type", &self.weights.syst_therapy_type)?;
+        if let Some(procedure) = SystTherapyType::iter().find(|t| {
+            t.is_procedure() && self.weights.syst_therapy_type.contains_key(&t.to_string())
+        }) {
+            return Err(format!(
+                "syst_therapy_type {procedure} is a procedure, its number is set by resources_per_patient"
+            ));
+        }
+        validate_weights::<TumorSiteLocation>(
+            "tumor_site_location",
+            &self.weights.tumor_site_location,
+        )
+    }
+
+    /// Pins the max date to the given day (unless the scenario sets one), so that all dates of a run
+    /// are capped at the same day; canonical data gets a fixed max date instead, so that it is the
+    /// same on any day
+    pub fn pin_max_date(&mut self, today: NaiveDate, canonical: bool) {
+        if self.max_date.is_none() {
+            self.max_date = Some(if canonical { CANONICAL_MAX_DATE } else { today });
+        }
+    }
+
+    pub fn get_max_date(&self) -> NaiveDate {
+        self.max_date
+            .unwrap_or_else(|| get_max_date_time().date_naive())
+    }
+
+    pub fn get_gender(&self, rng: &mut StdRng) -> Gender {
+        if rng.random_bool(self.male_ratio) {
+            Gender::Male
+        } else {
+            Gender::Female
+        }
+    }
+
+    pub fn get_deceased(&self, rng: &mut StdRng) -> bool {
+        rng.random_bool(self.deceased_rate)
+    }
+
+    pub fn get_neoadjuvant(&self, rng: &mut StdRng) -> bool {
+        rng.random_bool(self.neoadjuvant_rate)
+    }
+
+    pub fn get_recurrence(&self, rng: &mut StdRng) -> bool {
+        rng.random_bool(self.recurrence_rate)
+    }
+
+    pub fn get_multiple_tumors(&self, rng: &mut StdRng) -> bool {
+        rng.random_bool(self.multiple_tumors_rate)
+    }
+
+    pub fn get_age_at_diagnosis(&self, rng: &mut StdRng) -> u32 {
+        let group = self
+            .age_at_diagnosis
+            .choose_weighted(rng, |group| group.weight)
+            .expect("a validated scenario has an age group with a positive weight");
+        rng.random_range(group.min..=group.max)
+    }
+
+    /// Draws the vital status of a patient, who is not deceased
+    pub fn get_vital_status_of_living(&self, rng: &mut StdRng) -> VitalStatus {
+        let variants = VitalStatus::iter()
+            .filter(|vs| *vs != VitalStatus::Deceased)
+            .collect();
+        choose_weighted(variants, &self.weights.vital_status, rng)
+    }
+
+    pub fn get_sample_material_type(&self, rng: &mut StdRng) -> SampleMaterialType {
+        choose_variant(&self.weights.sample_material_type, rng)
+    }
+
+    /// Draws the therapy type of a medication statement, i.e. any type but an operation or a
+    /// radiotherapy (which are procedures)
+    pub fn get_syst_therapy_type(&self, rng: &mut StdRng) -> SystTherapyType {
+        let variants = SystTherapyType::iter()
+            .filter(|t| !t.is_procedure())
+            .collect();
+        choose_weighted(variants, &self.weights.syst_therapy_type, rng)
+    }
+
+    pub fn get_tumor_site_location(&self, rng: &mut StdRng) -> TumorSiteLocation {
+        choose_variant(&self.weights.tumor_site_location, rng)
+    }
+
+    /// Draws one of the UICC stages of the staging scheme, `None` if no weights are given for the stages
+    /// (of the scheme)
+    pub fn get_uicc_stage(
+        &self,
+        staging_scheme: StagingScheme,
+        rng: &mut StdRng,
+    ) -> Option<UiccStage> {
+        let stages = staging_scheme.get_uicc_stages();
+        let weights = &self.weights.uicc_stage;
+        if stages
+            .iter()
+            .all(|stage| weights.get(&stage.to_string()).copied().unwrap_or(0) == 0)
+        {
+            return None;
+        }
+        Some(choose_weighted(stages, weights, rng))
+    }
+}
+
+/// Draws one of the variants of the enum according to the weights (keyed by the variant name)
+fn choose_variant<T: IntoEnumIterator + Display>(
+    weights: &HashMap<String, u32>,
+    rng: &mut StdRng,
+) -> T {
+    choose_weighted(T::iter().collect(), weights, rng)
+}
+
+/// Draws one of the variants according to the weights (keyed by the variant name). All the variants are
+/// equally likely, if none of them has a positive weight.
+fn choose_weighted<T: Display>(
+    mut variants: Vec<T>,
+    weights: &HashMap<String, u32>,
+    rng: &mut StdRng,
+) -> T {
+    let variant_weights: Vec<u32> = variants
+        .iter()
+        .map(|v| weights.get(&v.to_string()).copied().unwrap_or(0))
+        .collect();
+    let i = if variant_weights.iter().any(|w| *w > 0) {
+        let indices: Vec<usize> = (0..variants.len()).collect();
+        *indices
+            .choose_weighted(rng, |i| variant_weights[*i])
+            .expect("one of the variants has a positive weight")
+    } else {
+        rng.random_range(0..variants.len())
+    };
+    variants.swap_remove(i)
+}
+
+/// Reads a TOML date (e.g. `2025-12-31`)
+fn deserialize_date<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveDate>, D::Error> {
+    let date_time = toml::value::Datetime::deserialize(deserializer)?;
+    date_time
+        .date
+        .filter(|_| date_time.time.is_none())
+        .and_then(|date| {
+            NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())
+        })
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("{date_time} is no date")))
+}
+
+fn validate_weights<T: IntoEnumIterator + Display>(
+    name: &str,
+    weights: &HashMap<String, u32>,
+) -> Result<(), String> {
+    let variants: Vec<String> = T::iter().map(|v| v.to_string()).collect();
+    for key in weights.keys() {
+        if !variants.contains(key) {
+            return Err(format!(
+                "unknown {name} '{key}', expected one of: {}",
+                variants.join(", ")
+            ));
+        }
+    }
+    if !weights.is_empty() && weights.values().all(|w| *w == 0) {
+        return Err(format!("{name} needs at least one positive weight"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_from_toml_keeps_the_defaults() {
+        let scenario = Scenario::from_toml("deceased_rate = 0.1").expect("scenario is valid");
+        assert_eq!(scenario.deceased_rate, 0.1, "deceased rate does not match");
+        assert_eq!(
+            scenario.resources_per_patient,
+            ResourcesPerPatient::default(),
+            "resources per patient are not the defaults"
+        );
+    }
+
+    #[test]
+    fn test_from_toml_rejects_invalid_scenarios() {
+        for toml in [
+            "deceased_rate = 1.5",
+            "recurrence_rate = -0.1",
+            "max_date = 1970-01-01",
+            "max_date = 9999-12-31",
+            "unknown = 1",
+            "[weights.uicc_stage]\nV = 1",
+            "[weights.vital_status]\nAlive = 0",
+            "[weights.syst_therapy_type]\nOP = 1",
+            "[[age_at_diagnosis]]\nmin = 60\nmax = 50\nweight = 1",
+        ] {
+            assert!(
+                Scenario::from_toml(toml).is_err(),
+                "scenario is not rejected: {toml}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_weights_are_applied() {
+        let scenario = Scenario::from_toml(
+            r#"
+            male_ratio = 1.0
+
+            [[age_at_diagnosis]]
+            min = 50
+            max = 59
+            weight = 1
+
+            [weights.sample_material_type]
+            TumorTissueFfpe = 1
+
+            [weights.uicc_stage]
+            IA1 = 1
+
+            [weights.syst_therapy_type]
+            WW = 1
+            "#,
+        )
+        .expect("scenario is valid");
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            assert_eq!(scenario.get_gender(&mut rng), Gender::Male);
+            assert!((50..=59).contains(&scenario.get_age_at_diagnosis(&mut rng)));
+            assert_eq!(
+                scenario.get_sample_material_type(&mut rng),
+                SampleMaterialType::TumorTissueFfpe
+            );
+            assert_eq!(
+                scenario.get_uicc_stage(StagingScheme::Lung, &mut rng),
+                Some(UiccStage::IA1)
+            );
+            assert_eq!(
+                scenario.get_syst_therapy_type(&mut rng),
+                SystTherapyType::WW
+            );
+            // IA1 is not a stage of breast cancer
+            assert_eq!(
+                scenario.get_uicc_stage(StagingScheme::Breast, &mut rng),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_syst_therapy_type_is_no_procedure() {
+        let scenario = Scenario::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let therapy_type = scenario.get_syst_therapy_type(&mut rng);
+            assert!(
+                !therapy_type.is_procedure(),
+                "{therapy_type} is drawn for a medication statement"
+            );
+        }
+    }
+}
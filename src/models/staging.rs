@@ -0,0 +1,450 @@
+//! Rules to derive the UICC stage (8th edition) from the T, N and M categories of a tumour.
+//!
+//! The stage grouping differs from one tumour entity to the other, hence every tumour entity is staged
+//! according to a [StagingScheme]. The schemes only use the main T, N and M categories of their UICC
+//! tables, so that every generated TNM classification can be staged.
+
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::RngExt;
+
+use crate::models::enums::tnmm_category::TnmmCategory;
+use crate::models::enums::tnmn_category::TnmnCategory;
+use crate::models::enums::tnmt_category::TnmtCategory;
+use crate::models::enums::uicc_stage::UiccStage;
+
+// the probability, that the pathological T (or N) category confirms the clinical one
+const PATHOLOGIC_CONFIRMATION_RATE: f64 = 0.7;
+
+/// A TNM classification together with the UICC stage derived from it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tnm {
+    pub t: TnmtCategory,
+    pub n: TnmnCategory,
+    pub m: TnmmCategory,
+    pub uicc_stage: UiccStage,
+}
+
+/// The prefix and suffix symbols of a TNM classification, which tell the circumstances it was determined in
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TnmSymbols {
+    /// y: during or after a (neoadjuvant) multimodal therapy
+    pub y: bool,
+    /// r: of a recurrence
+    pub r: bool,
+    /// m: of multiple primary tumours at a single site
+    pub m: bool,
+}
+
+/// The UICC staging tables a tumour entity can be staged with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StagingScheme {
+    /// The generic stage grouping (0, I - IV) for entities without a specific table
+    Generic,
+    Lung,
+    Breast,
+    Colorectal,
+}
+
+impl StagingScheme {
+    /// The T categories used by the scheme
+    pub fn get_t_categories(&self) -> &'static [TnmtCategory] {
+        match self {
+            StagingScheme::Generic => &[
+                TnmtCategory::Is,
+                TnmtCategory::One,
+                TnmtCategory::Two,
+                TnmtCategory::Three,
+                TnmtCategory::Four,
+            ],
+            StagingScheme::Lung => &[
+                TnmtCategory::Is,
+                TnmtCategory::OneMi,
+                TnmtCategory::OneA,
+                TnmtCategory::OneB,
+                TnmtCategory::OneC,
+                TnmtCategory::TwoA,
+                TnmtCategory::TwoB,
+                TnmtCategory::Three,
+                TnmtCategory::Four,
+            ],
+            StagingScheme::Breast => &[
+                TnmtCategory::Is,
+                TnmtCategory::One,
+                TnmtCategory::Two,
+                TnmtCategory::Three,
+                TnmtCategory::Four,
+            ],
+            StagingScheme::Colorectal => &[
+                TnmtCategory::Is,
+                TnmtCategory::One,
+                TnmtCategory::Two,
+                TnmtCategory::Three,
+                TnmtCategory::FourA,
+                TnmtCategory::FourB,
+            ],
+        }
+    }
+
+    /// The N categories used by the scheme
+    pub fn get_n_categories(&self) -> &'static [TnmnCategory] {
+        match self {
+            StagingScheme::Generic | StagingScheme::Lung | StagingScheme::Breast => &[
+                TnmnCategory::Zero,
+                TnmnCategory::One,
+                TnmnCategory::Two,
+                TnmnCategory::Three,
+            ],
+            StagingScheme::Colorectal => &[
+                TnmnCategory::Zero,
+                TnmnCategory::One,
+                TnmnCategory::TwoA,
+                TnmnCategory::TwoB,
+            ],
+        }
+    }
+
+    /// The M categories used by the scheme
+    pub fn get_m_categories(&self) -> &'static [TnmmCategory] {
+        match self {
+            StagingScheme::Generic | StagingScheme::Breast => {
+                &[TnmmCategory::Zero, TnmmCategory::One]
+            }
+            StagingScheme::Lung => &[
+                TnmmCategory::Zero,
+                TnmmCategory::OneA,
+                TnmmCategory::OneB,
+                TnmmCategory::OneC,
+            ],
+            StagingScheme::Colorectal => &[
+                TnmmCategory::Zero,
+                TnmmCategory::OneA,
+                TnmmCategory::OneB,
+                TnmmCategory::OneC,
+            ],
+        }
+    }
+
+    /// Derives the UICC stage from the given categories. Returns `None` if the categories are not
+    /// used by the scheme.
+    pub fn get_uicc_stage(
+        &self,
+        t: TnmtCategory,
+        n: TnmnCategory,
+        m: TnmmCategory,
+    ) -> Option<UiccStage> {
+        if !self.get_t_categories().contains(&t)
+            || !self.get_n_categories().contains(&n)
+            || !self.get_m_categories().contains(&m)
+        {
+            return None;
+        }
+
+        let stage = match self {
+            StagingScheme::Generic => get_generic_stage(t, n, m),
+            StagingScheme::Lung => get_lung_stage(t, n, m),
+            StagingScheme::Breast => get_breast_stage(t, n, m),
+            StagingScheme::Colorectal => get_colorectal_stage(t, n, m),
+        };
+        Some(stage)
+    }
+
+    /// Draws a TNM classification (and derives its UICC stage)
+    pub fn get_tnm(&self, rng: &mut StdRng) -> Tnm {
+        let tnms = self.get_all_tnms();
+        *tnms
+            .choose(rng)
+            .expect("a staging scheme has at least one TNM")
+    }
+
+    /// Draws a TNM classification that results in the given UICC stage. Returns `None` if the stage
+    /// cannot be reached with this scheme.
+    pub fn get_tnm_for_stage(&self, uicc_stage: UiccStage, rng: &mut StdRng) -> Option<Tnm> {
+        let tnms: Vec<Tnm> = self
+            .get_all_tnms()
+            .into_iter()
+            .filter(|tnm| tnm.uicc_stage == uicc_stage)
+            .collect();
+        tnms.choose(rng).copied()
+    }
+
+    /// Derives the pathological TNM classification (after the resection of the tumour) from the clinical
+    /// one. The T and the N category are mostly confirmed, otherwise they are up- or downstaged by one
+    /// step; the M category is taken over, as distant metastases are not resected. A carcinoma in situ
+    /// stays one.
+    pub fn get_pathologic_tnm(&self, clinical: &Tnm, rng: &mut StdRng) -> Tnm {
+        if clinical.t == TnmtCategory::Is {
+            return *clinical;
+        }
+
+        let t_categories: Vec<TnmtCategory> = self
+            .get_t_categories()
+            .iter()
+            .copied()
+            .filter(|&t| t != TnmtCategory::Is)
+            .collect();
+        let t = get_adjacent_category(&t_categories, clinical.t, rng);
+        let n = get_adjacent_category(self.get_n_categories(), clinical.n, rng);
+        let uicc_stage = self
+            .get_uicc_stage(t, n, clinical.m)
+            .expect("the categories of the scheme can be staged");
+
+        Tnm {
+            t,
+            n,
+            m: clinical.m,
+            uicc_stage,
+        }
+    }
+
+    /// The UICC stages that can be reached with this scheme
+    pub fn get_uicc_stages(&self) -> Vec<UiccStage> {
+        let mut stages: Vec<UiccStage> = vec![];
+        for tnm in self.get_all_tnms() {
+            if !stages.contains(&tnm.uicc_stage) {
+                stages.push(tnm.uicc_stage);
+            }
+        }
+        stages
+    }
+
+    fn get_all_tnms(&self) -> Vec<Tnm> {
+        let mut tnms = vec![];
+        for &t in self.get_t_categories() {
+            for &n in self.get_n_categories() {
+                for &m in self.get_m_categories() {
+                    // carcinoma in situ does not spread
+                    if t == TnmtCategory::Is && (n != TnmnCategory::Zero || m != TnmmCategory::Zero)
+                    {
+                        continue;
+                    }
+                    if let Some(uicc_stage) = self.get_uicc_stage(t, n, m) {
+                        tnms.push(Tnm {
+                            t,
+                            n,
+                            m,
+                            uicc_stage,
+                        });
+                    }
+                }
+            }
+        }
+        tnms
+    }
+}
+
+/// Returns the given category or (with the complementary probability of [PATHOLOGIC_CONFIRMATION_RATE])
+/// one of its neighbours in the given (ordered) categories
+fn get_adjacent_category<C: Copy + PartialEq>(
+    categories: &[C],
+    category: C,
+    rng: &mut StdRng,
+) -> C {
+    let Some(index) = categories.iter().position(|&c| c == category) else {
+        return category;
+    };
+    if rng.random_bool(PATHOLOGIC_CONFIRMATION_RATE) {
+        return category;
+    }
+
+    let neighbours: Vec<C> = [index.checked_sub(1), Some(index + 1)]
+        .into_iter()
+        .flatten()
+        .filter_map(|i| categories.get(i).copied())
+        .collect();
+    neighbours.choose(rng).copied().unwrap_or(category)
+}
+
+fn get_generic_stage(t: TnmtCategory, n: TnmnCategory, m: TnmmCategory) -> UiccStage {
+    use TnmtCategory as T;
+
+    if m != TnmmCategory::Zero {
+        return UiccStage::IV;
+    }
+    if n != TnmnCategory::Zero {
+        return UiccStage::III;
+    }
+    match t {
+        T::Is => UiccStage::Zero,
+        T::One | T::Two => UiccStage::I,
+        _ => UiccStage::II,
+    }
+}
+
+fn get_lung_stage(t: TnmtCategory, n: TnmnCategory, m: TnmmCategory) -> UiccStage {
+    use TnmnCategory as N;
+    use TnmtCategory as T;
+
+    match m {
+        TnmmCategory::OneA | TnmmCategory::OneB => return UiccStage::IVA,
+        TnmmCategory::OneC => return UiccStage::IVB,
+        _ => {}
+    }
+
+    let t1_or_t2 = matches!(
+        t,
+        T::OneMi | T::OneA | T::OneB | T::OneC | T::TwoA | T::TwoB
+    );
+    match (t, n) {
+        (T::Is, _) => UiccStage::ZeroIs,
+        (T::OneMi | T::OneA, N::Zero) => UiccStage::IA1,
+        (T::OneB, N::Zero) => UiccStage::IA2,
+        // NOTE: the UICC stage CodeSystem has no IA3, hence T1c N0 is staged as IA
+        (T::OneC, N::Zero) => UiccStage::IA,
+        (T::TwoA, N::Zero) => UiccStage::IB,
+        (T::TwoB, N::Zero) => UiccStage::IIA,
+        (T::Three, N::Zero) => UiccStage::IIB,
+        (_, N::One) if t1_or_t2 => UiccStage::IIB,
+        (_, N::Two) if t1_or_t2 => UiccStage::IIIA,
+        (T::Three, N::One) | (T::Four, N::Zero | N::One) => UiccStage::IIIA,
+        (_, N::Three) if t1_or_t2 => UiccStage::IIIB,
+        (T::Three | T::Four, N::Two) => UiccStage::IIIB,
+        _ => UiccStage::IIIC,
+    }
+}
+
+fn get_breast_stage(t: TnmtCategory, n: TnmnCategory, m: TnmmCategory) -> UiccStage {
+    use TnmnCategory as N;
+    use TnmtCategory as T;
+
+    if m != TnmmCategory::Zero {
+        return UiccStage::IV;
+    }
+    match (t, n) {
+        (T::Is, _) => UiccStage::Zero,
+        (_, N::Three) => UiccStage::IIIC,
+        (T::Four, _) => UiccStage::IIIB,
+        (T::One, N::Zero) => UiccStage::IA,
+        (T::One, N::One) | (T::Two, N::Zero) => UiccStage::IIA,
+        (T::Two, N::One) | (T::Three, N::Zero) => UiccStage::IIB,
+        _ => UiccStage::IIIA,
+    }
+}
+
+fn get_colorectal_stage(t: TnmtCategory, n: TnmnCategory, m: TnmmCategory) -> UiccStage {
+    use TnmnCategory as N;
+    use TnmtCategory as T;
+
+    match m {
+        TnmmCategory::OneA => return UiccStage::IVA,
+        TnmmCategory::OneB => return UiccStage::IVB,
+        TnmmCategory::OneC => return UiccStage::IVC,
+        _ => {}
+    }
+    match (t, n) {
+        (T::Is, _) => UiccStage::Zero,
+        (T::One | T::Two, N::Zero) => UiccStage::I,
+        (T::Three, N::Zero) => UiccStage::IIA,
+        (T::FourA, N::Zero) => UiccStage::IIB,
+        (T::FourB, N::Zero) => UiccStage::IIC,
+        (T::One | T::Two, N::One) | (T::One, N::TwoA) => UiccStage::IIIA,
+        (T::Three | T::FourA, N::One)
+        | (T::Two | T::Three, N::TwoA)
+        | (T::One | T::Two, N::TwoB) => UiccStage::IIIB,
+        _ => UiccStage::IIIC,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_get_uicc_stage() {
+        let lung = StagingScheme::Lung;
+        let stage = lung.get_uicc_stage(TnmtCategory::OneA, TnmnCategory::Zero, TnmmCategory::Zero);
+        assert_eq!(stage, Some(UiccStage::IA1), "lung stage does not match");
+        let stage = lung.get_uicc_stage(TnmtCategory::Four, TnmnCategory::Two, TnmmCategory::Zero);
+        assert_eq!(stage, Some(UiccStage::IIIB), "lung stage does not match");
+
+        let breast = StagingScheme::Breast;
+        let stage = breast.get_uicc_stage(TnmtCategory::Two, TnmnCategory::One, TnmmCategory::Zero);
+        assert_eq!(stage, Some(UiccStage::IIB), "breast stage does not match");
+
+        let colorectal = StagingScheme::Colorectal;
+        let stage =
+            colorectal.get_uicc_stage(TnmtCategory::FourB, TnmnCategory::One, TnmmCategory::OneC);
+        assert_eq!(
+            stage,
+            Some(UiccStage::IVC),
+            "colorectal stage does not match"
+        );
+
+        let generic = StagingScheme::Generic;
+        let stage =
+            generic.get_uicc_stage(TnmtCategory::Zero, TnmnCategory::Zero, TnmmCategory::One);
+        assert_eq!(stage, None, "T0 is not used by the generic scheme");
+    }
+
+    #[test]
+    fn test_get_tnm_is_consistent() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for scheme in [
+            StagingScheme::Generic,
+            StagingScheme::Lung,
+            StagingScheme::Breast,
+            StagingScheme::Colorectal,
+        ] {
+            for _ in 0..100 {
+                let tnm = scheme.get_tnm(&mut rng);
+                assert_eq!(
+                    scheme.get_uicc_stage(tnm.t, tnm.n, tnm.m),
+                    Some(tnm.uicc_stage),
+                    "stage is not consistent with the TNM"
+                );
+                if tnm.m != TnmmCategory::Zero {
+                    assert!(
+                        tnm.uicc_stage.as_str().starts_with("IV"),
+                        "metastasized tumour is not stage IV"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_tnm_for_stage() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let tnm = StagingScheme::Breast.get_tnm_for_stage(UiccStage::IIIC, &mut rng);
+        assert_eq!(
+            tnm.map(|tnm| tnm.n),
+            Some(TnmnCategory::Three),
+            "N does not match"
+        );
+
+        let tnm = StagingScheme::Breast.get_tnm_for_stage(UiccStage::IVC, &mut rng);
+        assert_eq!(tnm, None, "breast cancer has no stage IVC");
+    }
+
+    #[test]
+    fn test_get_pathologic_tnm_is_consistent() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let scheme = StagingScheme::Colorectal;
+        let position = |t| scheme.get_t_categories().iter().position(|&c| c == t);
+        let mut restaged = false;
+        for _ in 0..100 {
+            let clinical = scheme.get_tnm(&mut rng);
+            let pathologic = scheme.get_pathologic_tnm(&clinical, &mut rng);
+            assert_eq!(
+                scheme.get_uicc_stage(pathologic.t, pathologic.n, pathologic.m),
+                Some(pathologic.uicc_stage),
+                "stage is not consistent with the pTNM"
+            );
+            assert_eq!(pathologic.m, clinical.m, "M does not match");
+            let steps = position(pathologic.t)
+                .zip(position(clinical.t))
+                .map(|(p, c)| p.abs_diff(c));
+            assert!(
+                steps.is_some_and(|s| s <= 1),
+                "T differs by more than one step"
+            );
+            if clinical.t == TnmtCategory::Is {
+                assert_eq!(pathologic, clinical, "carcinoma in situ is restaged");
+            }
+            restaged |= pathologic != clinical;
+        }
+        assert!(restaged, "pTNM never differs from the cTNM");
+    }
+}
@@ -0,0 +1,180 @@
+use chrono::{Days, Months, NaiveDate};
+use rand::rngs::StdRng;
+use rand::RngExt;
+
+use crate::models::scenario::Scenario;
+use crate::utils::{get_min_date_time, get_min_date_time_millenial};
+
+/// The dates of the clinically relevant events in a patient's history.
+///
+/// The dates are always in chronological order i.e.
+/// birth < diagnosis <= specimen collection <= histology < therapy start < therapy end <= last vital status,
+/// and the operation (i.e. the resection of the tumour) lies after the histology and strictly before
+/// a neoadjuvant therapy or strictly after an adjuvant one,
+/// and all of them lie between [get_min_date_time] and the max date of the scenario (today by default).
+/// If the patient is deceased, the date of death is the date of the last vital status.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timeline {
+    pub birth_date: NaiveDate,
+    pub diagnosis_date: NaiveDate,
+    pub specimen_collection_date: NaiveDate,
+    pub histology_date: NaiveDate,
+    pub therapy_start_date: NaiveDate,
+    pub therapy_end_date: NaiveDate,
+    pub operation_date: NaiveDate,
+    /// Whether the (radio or systemic) therapy precedes the operation, otherwise it follows it
+    pub neoadjuvant: bool,
+    pub last_vital_status_date: NaiveDate,
+    pub deceased_date: Option<NaiveDate>,
+}
+
+impl Timeline {
+    pub fn new(scenario: &Scenario, rng: &mut StdRng) -> Self {
+        let max_date = scenario.get_max_date();
+
+        // leave enough room after the diagnosis for the diagnostics and the therapy
+        let latest_diagnosis_date = max_date - Days::new(365);
+        // nobody is born before get_min_date_time(), hence the age is drawn first and the diagnosis
+        // lies late enough for it (only ages beyond the oldest possible one are capped)
+        let max_age_at_diagnosis = latest_diagnosis_date
+            .years_since(get_min_date_time().date_naive())
+            .unwrap_or_default()
+            .saturating_sub(1);
+        let age_at_diagnosis = scenario.get_age_at_diagnosis(rng).min(max_age_at_diagnosis);
+        let earliest_diagnosis_date = get_min_date_time_millenial()
+            .date_naive()
+            .max(get_min_date_time().date_naive() + Months::new((age_at_diagnosis + 1) * 12));
+        let diagnosis_date = get_date_between(earliest_diagnosis_date, latest_diagnosis_date, rng);
+        // the birthday is up to a year before the day the patient turns (age at diagnosis + 1)
+        let birth_date = diagnosis_date
+            - Months::new(age_at_diagnosis * 12)
+            - Days::new(rng.random_range(0..365));
+
+        let specimen_collection_date = diagnosis_date + Days::new(rng.random_range(0..=30));
+        let histology_date = specimen_collection_date + Days::new(rng.random_range(0..=14));
+        // the diagnostics, the therapy and the operation take up to 344 days, i.e. end before the max date
+        let neoadjuvant = scenario.get_neoadjuvant(rng);
+        let first_step_date = histology_date + Days::new(rng.random_range(1..=60));
+        let (therapy_start_date, therapy_end_date, operation_date) = if neoadjuvant {
+            let therapy_end_date = first_step_date + Days::new(rng.random_range(1..=180));
+            let operation_date = therapy_end_date + Days::new(rng.random_range(1..=60));
+            (first_step_date, therapy_end_date, operation_date)
+        } else {
+            let therapy_start_date = first_step_date + Days::new(rng.random_range(1..=60));
+            let therapy_end_date = therapy_start_date + Days::new(rng.random_range(1..=180));
+            (therapy_start_date, therapy_end_date, first_step_date)
+        };
+        let treatment_end_date = therapy_end_date.max(operation_date);
+
+        let last_vital_status_date = get_date_between(treatment_end_date, max_date, rng);
+        let deceased_date = scenario.get_deceased(rng).then_some(last_vital_status_date);
+
+        Self {
+            birth_date,
+            diagnosis_date,
+            specimen_collection_date,
+            histology_date,
+            therapy_start_date,
+            therapy_end_date,
+            operation_date,
+            neoadjuvant,
+            last_vital_status_date,
+            deceased_date,
+        }
+    }
+
+    /// The end of the treatment, i.e. of the therapy or of the operation, whichever is later
+    pub fn get_treatment_end_date(&self) -> NaiveDate {
+        self.therapy_end_date.max(self.operation_date)
+    }
+
+    /// Draws the date of a recurrence of the tumour, which lies after the end of the treatment (and not
+    /// after the last vital status). Returns `None` if there is no time for a recurrence.
+    pub fn get_recurrence_date(&self, rng: &mut StdRng) -> Option<NaiveDate> {
+        let start = self.get_treatment_end_date() + Days::new(1);
+        (start <= self.last_vital_status_date)
+            .then(|| get_date_between(start, self.last_vital_status_date, rng))
+    }
+}
+
+fn get_date_between(start: NaiveDate, end: NaiveDate, rng: &mut StdRng) -> NaiveDate {
+    let days = (end - start).num_days() as u64;
+    start + Days::new(rng.random_range(0..=days))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_timeline_dates_are_in_chronological_order() {
+        let max_date = Scenario::default().get_max_date();
+
+        for seed in 0..1000 {
+            let t = Timeline::new(&Scenario::default(), &mut StdRng::seed_from_u64(seed));
+
+            assert!(t.birth_date >= get_min_date_time().date_naive());
+            assert!(t.birth_date < t.diagnosis_date, "diagnosis before birth");
+            assert!(t.diagnosis_date <= t.specimen_collection_date);
+            assert!(t.specimen_collection_date <= t.histology_date);
+            assert!(t.histology_date < t.therapy_start_date);
+            assert!(t.therapy_start_date < t.therapy_end_date);
+            assert!(t.histology_date < t.operation_date);
+            if t.neoadjuvant {
+                assert!(
+                    t.therapy_end_date < t.operation_date,
+                    "therapy after operation"
+                );
+            } else {
+                assert!(
+                    t.operation_date < t.therapy_start_date,
+                    "therapy before operation"
+                );
+            }
+            assert!(t.therapy_end_date <= t.last_vital_status_date);
+            assert!(t.operation_date <= t.last_vital_status_date);
+            assert!(t.last_vital_status_date <= max_date, "date in the future");
+            if let Some(deceased_date) = t.deceased_date {
+                assert!(t.therapy_end_date <= deceased_date, "therapy after death");
+            }
+        }
+    }
+
+    #[test]
+    fn test_timeline_age_at_diagnosis_is_within_the_age_groups() {
+        for (min, max) in [(50, 50), (18, 90), (70, 90)] {
+            let scenario = Scenario::from_toml(&format!(
+                "[[age_at_diagnosis]]\nmin = {min}\nmax = {max}\nweight = 1"
+            ))
+            .expect("scenario is valid");
+
+            for seed in 0..1000 {
+                let t = Timeline::new(&scenario, &mut StdRng::seed_from_u64(seed));
+                let age = t
+                    .diagnosis_date
+                    .years_since(t.birth_date)
+                    .expect("birth before diagnosis");
+                assert!(
+                    (min..=max).contains(&age),
+                    "age at diagnosis {age} is not within {min}-{max}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_timeline_dates_do_not_exceed_the_max_date() {
+        let scenario = Scenario::from_toml("max_date = 2020-06-30").expect("scenario is valid");
+        let max_date = NaiveDate::from_ymd_opt(2020, 6, 30).expect("date is valid");
+
+        for seed in 0..100 {
+            let t = Timeline::new(&scenario, &mut StdRng::seed_from_u64(seed));
+            assert!(
+                t.last_vital_status_date <= max_date,
+                "date after the max date"
+            );
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! A built-in table of the common tumour entities, used to generate a realistic spread of diagnoses.
+//!
+//! The relative frequencies roughly follow the incidence of the entities in Germany (in thousands of new
+//! cases per year).
+
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+
+use crate::models::enums::gender::Gender;
+use crate::models::patient_attributes::PatientAttributes;
+use crate::models::staging::StagingScheme;
+
+/// A tumour entity, coupling the ICD-10-GM codes with the matching ICD-O-3 topographies and the plausible
+/// ICD-O-3 morphologies
+#[derive(Debug)]
+pub struct TumorEntity {
+    /// Pairs of an ICD-10-GM code and its ICD-O-3 topography
+    pub sites: &'static [(&'static str, &'static str)],
+    /// Pairs of an ICD-O-3 morphology and its relative frequency within the entity
+    pub morphologies: &'static [(&'static str, u32)],
+    /// The only sex the entity can occur in, if any
+    pub sex: Option<Gender>,
+    /// The minimum age (at diagnosis) the entity plausibly occurs at
+    pub min_age: u32,
+    pub staging_scheme: StagingScheme,
+    /// Relative frequency of the entity
+    pub weight: u32,
+}
+
+pub const TUMOR_ENTITIES: &[TumorEntity] = &[
+    // breast
+    TumorEntity {
+        sites: &[
+            ("C50.1", "C50.1"),
+            ("C50.2", "C50.2"),
+            ("C50.3", "C50.3"),
+            ("C50.4", "C50.4"),
+            ("C50.5", "C50.5"),
+            ("C50.8", "C50.8"),
+            ("C50.9", "C50.9"),
+        ],
+        morphologies: &[("8500/3", 75), ("8520/3", 15), ("8522/3", 5), ("8480/3", 2)],
+        // NOTE: breast cancer in men is rare (about 1% of the cases), hence it is not generated
+        sex: Some(Gender::Female),
+        min_age: 0,
+        staging_scheme: StagingScheme::Breast,
+        weight: 70,
+    },
+    // prostate
+    TumorEntity {
+        sites: &[("C61", "C61.9")],
+        morphologies: &[("8140/3", 95), ("8500/3", 2)],
+        sex: Some(Gender::Male),
+        min_age: 40,
+        staging_scheme: StagingScheme::Generic,
+        weight: 65,
+    },
+    // lung
+    TumorEntity {
+        sites: &[
+            ("C34.0", "C34.0"),
+            ("C34.1", "C34.1"),
+            ("C34.2", "C34.2"),
+            ("C34.3", "C34.3"),
+            ("C34.8", "C34.8"),
+            ("C34.9", "C34.9"),
+        ],
+        morphologies: &[
+            ("8140/3", 40),
+            ("8070/3", 25),
+            ("8041/3", 15),
+            ("8046/3", 10),
+            ("8012/3", 3),
+        ],
+        sex: None,
+        min_age: 0,
+        staging_scheme: StagingScheme::Lung,
+        weight: 57,
+    },
+    // colon
+    TumorEntity {
+        sites: &[
+            ("C18.0", "C18.0"),
+            ("C18.2", "C18.2"),
+            ("C18.4", "C18.4"),
+            ("C18.6", "C18.6"),
+            ("C18.7", "C18.7"),
+            ("C18.9", "C18.9"),
+        ],
+        morphologies: &[("8140/3", 80), ("8480/3", 10), ("8490/3", 2)],
+        sex: None,
+        min_age: 0,
+        staging_scheme: StagingScheme::Colorectal,
+        weight: 35,
+    },
+    // rectum
+    TumorEntity {
+        sites: &[("C20", "C20.9")],
+        morphologies: &[("8140/3", 80), ("8480/3", 10), ("8490/3", 2)],
+        sex: None,
+        min_age: 0,
+        staging_scheme: StagingScheme::Colorectal,
+        weight: 20,
+    },
+    // melanoma
+    TumorEntity {
+        // melanomas of the skin are coded with the topographies of the skin (C44)
+        sites: &[
+            ("C43.3", "C44.3"),
+            ("C43.4", "C44.4"),
+            ("C43.5", "C44.5"),
+            ("C43.6", "C44.6"),
+            ("C43.7", "C44.7"),
+        ],
+        morphologies: &[
+            ("8720/3", 50),
+            ("8743/3", 30),
+            ("8721/3", 15),
+            ("8742/3", 5),
+        ],
+        sex: None,
+        min_age: 0,
+        staging_scheme: StagingScheme::Generic,
+        weight: 23,
+    },
+    // pancreas
+    TumorEntity {
+        sites: &[
+            ("C25.0", "C25.0"),
+            ("C25.1", "C25.1"),
+            ("C25.2", "C25.2"),
+            ("C25.9", "C25.9"),
+        ],
+        morphologies: &[("8500/3", 85), ("8140/3", 10)],
+        sex: None,
+        min_age: 0,
+        staging_scheme: StagingScheme::Generic,
+        weight: 20,
+    },
+    // bladder
+    TumorEntity {
+        sites: &[
+            ("C67.0", "C67.0"),
+            ("C67.2", "C67.2"),
+            ("C67.4", "C67.4"),
+            ("C67.9", "C67.9"),
+        ],
+        morphologies: &[("8120/3", 90), ("8070/3", 5)],
+        sex: None,
+        min_age: 0,
+        staging_scheme: StagingScheme::Generic,
+        weight: 17,
+    },
+    // kidney
+    TumorEntity {
+        sites: &[("C64", "C64.9")],
+        morphologies: &[("8312/3", 70), ("8310/3", 20), ("8260/3", 10)],
+        sex: None,
+        min_age: 0,
+        staging_scheme: StagingScheme::Generic,
+        weight: 15,
+    },
+    // stomach
+    TumorEntity {
+        sites: &[
+            ("C16.0", "C16.0"),
+            ("C16.2", "C16.2"),
+            ("C16.3", "C16.3"),
+            ("C16.9", "C16.9"),
+        ],
+        morphologies: &[("8140/3", 60), ("8490/3", 20), ("8144/3", 20)],
+        sex: None,
+        min_age: 0,
+        staging_scheme: StagingScheme::Generic,
+        weight: 14,
+    },
+    // corpus uteri
+    TumorEntity {
+        sites: &[("C54.1", "C54.1"), ("C54.9", "C54.9")],
+        morphologies: &[("8380/3", 80), ("8441/3", 10)],
+        sex: Some(Gender::Female),
+        min_age: 30,
+        staging_scheme: StagingScheme::Generic,
+        weight: 11,
+    },
+    // ovary
+    TumorEntity {
+        sites: &[("C56", "C56.9")],
+        morphologies: &[("8441/3", 70), ("8380/3", 15), ("8310/3", 10)],
+        sex: Some(Gender::Female),
+        min_age: 0,
+        staging_scheme: StagingScheme::Generic,
+        weight: 7,
+    },
+    // cervix uteri
+    TumorEntity {
+        sites: &[("C53.0", "C53.0"), ("C53.1", "C53.1"), ("C53.9", "C53.9")],
+        morphologies: &[("8070/3", 75), ("8140/3", 20)],
+        sex: Some(Gender::Female),
+        min_age: 0,
+        staging_scheme: StagingScheme::Generic,
+        weight: 4,
+    },
+];
+
+impl TumorEntity {
+    /// Whether the entity can occur in the patient, i.e. it is not excluded by the sex or the age of the
+    /// patient
+    pub fn is_possible_for(&self, patient: &PatientAttributes) -> bool {
+        self.sex.is_none_or(|sex| sex == patient.gender) && patient.age_at_diagnosis >= self.min_age
+    }
+}
+
+/// The tumour diagnosis of a patient, drawn from [TUMOR_ENTITIES], so that the condition, the histology
+/// and the TNM observations of a patient describe the same tumour. Only the entities that are possible for
+/// the patient are drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnosis {
+    pub icd10_code: &'static str,
+    pub topography: &'static str,
+    pub morphology: &'static str,
+    pub staging_scheme: StagingScheme,
+}
+
+impl Diagnosis {
+    pub fn new(patient: &PatientAttributes, rng: &mut StdRng) -> Self {
+        let entities: Vec<&TumorEntity> = TUMOR_ENTITIES
+            .iter()
+            .filter(|e| e.is_possible_for(patient))
+            .collect();
+        let entity = entities
+            .choose_weighted(rng, |e| e.weight)
+            .expect("at least one tumour entity is possible for every patient");
+        let (icd10_code, topography) = *entity
+            .sites
+            .choose(rng)
+            .expect("a tumour entity has at least one site");
+        let (morphology, _) = *entity
+            .morphologies
+            .choose_weighted(rng, |(_, weight)| *weight)
+            .expect("a tumour entity has at least one morphology");
+
+        Self {
+            icd10_code,
+            topography,
+            morphology,
+            staging_scheme: entity.staging_scheme,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::models::scenario::Scenario;
+    use crate::models::timeline::Timeline;
+
+    #[test]
+    fn test_tumor_entities_are_consistent() {
+        for entity in TUMOR_ENTITIES {
+            assert!(entity.weight > 0, "{entity:?} has no weight");
+            assert!(!entity.sites.is_empty(), "{entity:?} has no sites");
+            assert!(
+                !entity.morphologies.is_empty(),
+                "{entity:?} has no morphologies"
+            );
+            for (icd10_code, topography) in entity.sites {
+                assert!(
+                    topography.starts_with('C') && topography.len() == 5,
+                    "{icd10_code} has an invalid topography {topography}"
+                );
+            }
+            for (morphology, _) in entity.morphologies {
+                assert!(morphology.ends_with("/3"), "{morphology} is not malignant");
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagnoses_are_spread() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let scenario = Scenario::default();
+        let diagnoses: Vec<Diagnosis> = (0..200)
+            .map(|_| {
+                let timeline = Timeline::new(&scenario, &mut rng);
+                let patient = PatientAttributes::new(&timeline, &scenario, &mut rng);
+                Diagnosis::new(&patient, &mut rng)
+            })
+            .collect();
+
+        let icd10_codes: std::collections::HashSet<&str> =
+            diagnoses.iter().map(|d| d.icd10_code).collect();
+        assert!(icd10_codes.len() > 10, "diagnoses are not spread");
+
+        for d in diagnoses {
+            let entity = TUMOR_ENTITIES
+                .iter()
+                .find(|e| e.sites.contains(&(d.icd10_code, d.topography)))
+                .expect("site is not in the table");
+            assert!(
+                entity.morphologies.iter().any(|(m, _)| *m == d.morphology),
+                "morphology {} does not match {}",
+                d.morphology,
+                d.icd10_code
+            );
+        }
+    }
+
+    #[test]
+    fn test_diagnoses_respect_sex_and_age() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for gender in [Gender::Male, Gender::Female] {
+            for age_at_diagnosis in [18, 35, 70] {
+                let patient = PatientAttributes {
+                    gender,
+                    age_at_diagnosis,
+                };
+                for _ in 0..100 {
+                    let d = Diagnosis::new(&patient, &mut rng);
+                    let entity = TUMOR_ENTITIES
+                        .iter()
+                        .find(|e| e.sites.contains(&(d.icd10_code, d.topography)))
+                        .expect("site is not in the table");
+                    assert!(
+                        entity.is_possible_for(&patient),
+                        "{} is not possible for {patient:?}",
+                        d.icd10_code
+                    );
+                }
+            }
+        }
+
+        let male = PatientAttributes {
+            gender: Gender::Male,
+            age_at_diagnosis: 60,
+        };
+        let ovary = TUMOR_ENTITIES
+            .iter()
+            .find(|e| e.sites.contains(&("C56", "C56.9")))
+            .expect("ovary is in the table");
+        assert!(
+            !ovary.is_possible_for(&male),
+            "ovary tumour in a male patient"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{self, Path};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use fhirbolt::model::r4b::resources::BundleEntry;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Serialize;
+
+use crate::models::cli::{Format, Layout};
+use crate::output_dir::{OutputDir, OutputFile};
+use crate::resource_writer::RecordSink;
+use crate::utils;
+
+const NDJSON_EXTENSION: &str = "ndjson";
+pub const EXPORT_MANIFEST: &str = "export-manifest.json";
+
+// the characters of a file path, that are URL-encoded in a `file://` URL
+const FILE_PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Writes the resources of the generated bundle entries as FHIR Bulk Data NDJSON, i.e. one file per
+/// resource type (e.g. `Patient.ndjson`) with one resource per line. The resources are written as
+/// they come, hence the memory use does not grow with the number of resources.
+pub struct NdjsonWriter<'a> {
+    out_dir: &'a mut OutputDir,
+    // the open files and their number of resources, by resource type
+    files: BTreeMap<String, (OutputFile, u64)>,
+}
+
+/// The `$export`-style manifest listing the written NDJSON files (see
+/// https://hl7.org/fhir/uv/bulkdata/export.html#response---complete-status)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportManifest {
+    pub transaction_time: String,
+    pub request: String,
+    pub requires_access_token: bool,
+    pub output: Vec<ExportFile>,
+    pub error: Vec<ExportFile>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportFile {
+    pub r#type: String,
+    pub url: String,
+    pub count: u64,
+}
+
+impl<'a> NdjsonWriter<'a> {
+    pub fn new(out_dir: &'a mut OutputDir) -> Self {
+        Self {
+            out_dir,
+            files: BTreeMap::new(),
+        }
+    }
+
+    pub fn write_entry(&mut self, entry: BundleEntry) -> io::Result<()> {
+        let Some(resource) = entry.resource else {
+            return Ok(());
+        };
+        let resource_type = utils::get_resource_type(&resource).to_string();
+        let line =
+            utils::try_serialize_resource(&resource, Format::Json, Layout::Plain).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Cannot serialize resource to JSON. Reason: {e}"),
+                )
+            })?;
+
+        if !self.files.contains_key(&resource_type) {
+            let file = self
+                .out_dir
+                .create(&format!("{resource_type}.{NDJSON_EXTENSION}"))?;
+            self.files.insert(resource_type.clone(), (file, 0));
+        }
+        let (file, count) = self
+            .files
+            .get_mut(&resource_type)
+            .expect("file was opened above");
+        file.write_all(line.as_bytes())?;
+        file.write_all(b"\n")?;
+        *count += 1;
+        Ok(())
+    }
+
+    pub fn write_entries<I>(&mut self, entries: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = BundleEntry>,
+    {
+        entries
+            .into_iter()
+            .try_for_each(|entry| self.write_entry(entry))
+    }
+
+    /// Flushes the NDJSON files and writes the manifest (listing them by their absolute URL at the given
+    /// base) next to them, with the URL of the `$export` (kick-off) request and the time of the export.
+    pub fn finish(
+        self,
+        request: &str,
+        base_url: &str,
+        transaction_time: DateTime<Utc>,
+    ) -> io::Result<ExportManifest> {
+        let base_url = base_url.trim_end_matches('/');
+        let mut output = Vec::new();
+        for (resource_type, (file, count)) in self.files {
+            self.out_dir.close(file, Some(count))?;
+            output.push(ExportFile {
+                url: format!("{base_url}/{resource_type}.{NDJSON_EXTENSION}"),
+                r#type: resource_type,
+                count,
+            });
+        }
+
+        let manifest = ExportManifest {
+            transaction_time: transaction_time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            request: request.to_string(),
+            requires_access_token: false,
+            output,
+            error: vec![],
+        };
+        let json = serde_json::to_string_pretty(&manifest)
+            .expect("Failed to serialize the export manifest to JSON");
+        self.out_dir
+            .write(EXPORT_MANIFEST, format!("{json}\n").as_bytes(), None)?;
+
+        Ok(manifest)
+    }
+}
+
+/// Returns the `file://` URL of the (absolute) directory, e.g. `file:///tmp/generated%20data`
+pub fn get_file_url(dir: &Path) -> io::Result<String> {
+    let dir = path::absolute(dir)?;
+    let path = dir.to_string_lossy().replace('\\', "/");
+    let path = utf8_percent_encode(&path, FILE_PATH);
+    match path.to_string().starts_with('/') {
+        true => Ok(format!("file://{path}")),
+        // a Windows path starts with the drive letter
+        false => Ok(format!("file:///{path}")),
+    }
+}
+
+impl RecordSink for NdjsonWriter<'_> {
+    fn write_record(&mut self, record: Vec<BundleEntry>) -> io::Result<()> {
+        self.write_entries(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use fhirbolt::model::r4b::resources::Patient;
+    use fhirbolt::model::r4b::Resource;
+
+    use super::*;
+    use crate::models::cli::OverwritePolicy;
+
+    fn get_entry(resource: Resource) -> BundleEntry {
+        BundleEntry {
+            resource: Some(resource),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_write_entries_groups_the_resources_by_type() {
+        let dir = env::temp_dir().join(format!("cce-fhir-gen-ndjson-{}", std::process::id()));
+        let mut out_dir = OutputDir::new(dir.clone(), OverwritePolicy::Warn, None);
+
+        let mut writer = NdjsonWriter::new(&mut out_dir);
+        writer
+            .write_entries([
+                get_entry(Resource::Patient(Box::new(Patient {
+                    id: Some("Patient-id-1".into()),
+                    ..Default::default()
+                }))),
+                get_entry(Resource::Specimen(Box::default())),
+                get_entry(Resource::Patient(Box::new(Patient {
+                    id: Some("Patient-id-2".into()),
+                    ..Default::default()
+                }))),
+            ])
+            .expect("entries are written");
+        let transaction_time = DateTime::from_timestamp(0, 0).expect("time is valid");
+        let manifest = writer
+            .finish(
+                "https://example.org/fhir/$export",
+                "https://example.org/files/",
+                transaction_time,
+            )
+            .expect("manifest is written");
+
+        let counts: Vec<(&str, u64)> = manifest
+            .output
+            .iter()
+            .map(|f| (f.r#type.as_str(), f.count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![("Patient", 2), ("Specimen", 1)],
+            "counts do not match"
+        );
+        assert_eq!(
+            manifest.output[0].url, "https://example.org/files/Patient.ndjson",
+            "url does not match"
+        );
+
+        assert_eq!(
+            manifest.transaction_time, "1970-01-01T00:00:00Z",
+            "transaction time does not match"
+        );
+
+        let patients = fs::read_to_string(dir.join("Patient.ndjson")).expect("file is written");
+        assert_eq!(
+            patients,
+            "{\"resourceType\":\"Patient\",\"id\":\"Patient-id-1\"}\n{\"resourceType\":\"Patient\",\"id\":\"Patient-id-2\"}\n",
+            "NDJSON does not match"
+        );
+        assert!(
+            dir.join(EXPORT_MANIFEST).exists(),
+            "manifest is not written"
+        );
+
+        fs::remove_dir_all(dir).expect("temp dir is removed");
+    }
+
+    #[test]
+    fn test_get_file_url_is_absolute() {
+        let url = get_file_url(Path::new("generated data")).expect("path is made absolute");
+        assert!(url.starts_with("file:///"), "url is not absolute: {url}");
+        assert!(
+            url.ends_with("/generated%20data"),
+            "url is not encoded: {url}"
+        );
+    }
+}
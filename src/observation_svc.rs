@@ -1,32 +1,36 @@
 //! We have multiple FHIR resources for an Observation. This module has functions to generate XML for these
 //! different Observation resources.
 
-use std::ops::Range;
-
 use crate::extensions::option_ext::OptionExt;
+use crate::fhir::traits::CodeSystemAdapter;
 use crate::models::cli::ResourceType;
 use crate::models::enums::id_type::IdType;
 use crate::models::enums::loinc_codes::{
     TnmClassification, TnmmClassification, TnmnClassification, TnmtClassification,
 };
-use crate::models::enums::tnmm_category::TnmmCategory;
-use crate::models::enums::tnmn_category::TnmnCategory;
-use crate::models::enums::tnmt_category::TnmtCategory;
-use crate::models::enums::uicc_stage::UiccStage;
+use crate::models::enums::tnmm_symbol::TnmmSymbol;
+use crate::models::enums::tnmr_symbol::TnmrSymbol;
+use crate::models::enums::tnmy_symbol::TnmySymbol;
 use crate::models::enums::vital_status::VitalStatus;
+use crate::models::id_allocator::IdAllocator;
+use crate::models::scenario::Scenario;
+use crate::models::staging::{StagingScheme, Tnm, TnmSymbols};
+use crate::models::tumor_entity::Diagnosis;
+use crate::resource_generator::{GeneratorContext, ResourceGenerator};
 use crate::utils::{
-    get_bundle_entry_request, get_full_url, get_ids, get_loinc_url, get_tnmm_url, get_tnmn_url,
-    get_tnmt_url, get_uicc_stage_url, get_vital_status_url, HISTOLOGY_BEHAVIOR_CANCER_LOINC_CODE,
-    OBSERVATION_STATUS, VITAL_STATUS_LOINC_CODE,
+    get_bundle_entry_request, get_ids, get_loinc_url, get_tnmm_url, get_tnmn_url, get_tnmt_url,
+    get_uicc_stage_url, get_vital_status_url, HISTOLOGY_BEHAVIOR_CANCER_LOINC_CODE,
+    OBSERVATION_STATUS, TNM_M_SYMBOL_LOINC_CODE, TNM_R_SYMBOL_LOINC_CODE, TNM_Y_SYMBOL_LOINC_CODE,
+    VITAL_STATUS_LOINC_CODE,
 };
 use chrono::NaiveDate;
-use fake::{Fake, Faker};
 use fhirbolt::model::r4b::resources::{
     BundleEntry, Observation, ObservationComponent, ObservationComponentValue,
     ObservationEffective, ObservationValue,
 };
 use fhirbolt::model::r4b::types::{Code, CodeableConcept, Coding, DateTime, Id, Reference, Uri};
 use fhirbolt::model::r4b::Resource;
+use rand::rngs::StdRng;
 
 /// Generates observation histology
 pub fn get_histology(
@@ -35,7 +39,7 @@ pub fn get_histology(
     focus_ref: &str,
     specimen_ref: &str,
     effective_date: NaiveDate,
-    code_value: &str,
+    diagnosis: &Diagnosis,
 ) -> Observation {
     // TODO: check date, code etc.
     let oid = Id {
@@ -61,7 +65,7 @@ pub fn get_histology(
     let coding = Coding {
         system: Some(Uri::from("urn:oid:2.16.840.1.113883.6.43.1")),
         version: Some("32".into()),
-        code: Some(Code::from(code_value)),
+        code: Some(Code::from(diagnosis.morphology)),
         ..Default::default()
     };
     let cod_concept = CodeableConcept {
@@ -93,10 +97,21 @@ pub fn get_histology(
 }
 
 /// Generates observation vitalstatus
-pub fn get_vital_status(id: &str, subject_ref: &str, effective_date: NaiveDate) -> Observation {
+pub fn get_vital_status(
+    id: &str,
+    subject_ref: &str,
+    effective_date: NaiveDate,
+    deceased: bool,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) -> Observation {
     // NOTE: VitalStatus is also an Observation
-    // TODO: check date, code etc.
-    let code_value: VitalStatus = Faker.fake();
+    // the vital status of a deceased patient can only be deceased, that of the others is either alive or unknown
+    let code_value = if deceased {
+        VitalStatus::Deceased
+    } else {
+        scenario.get_vital_status_of_living(rng)
+    };
 
     let oid = Id {
         value: Some(id.to_string()),
@@ -142,12 +157,82 @@ pub fn get_vital_status(id: &str, subject_ref: &str, effective_date: NaiveDate)
     }
 }
 
+/// Draws the clinical TNM classification of a tumour, the UICC stage is derived from the TNM according to
+/// the staging scheme
+pub fn get_clinical_tnm(
+    staging_scheme: StagingScheme,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) -> Tnm {
+    scenario
+        .get_uicc_stage(staging_scheme, rng)
+        .and_then(|uicc_stage| staging_scheme.get_tnm_for_stage(uicc_stage, rng))
+        .unwrap_or_else(|| staging_scheme.get_tnm(rng))
+}
+
 /// Generates observation TNMc
-pub fn get_tnmc(id: &str, subject_ref: &str, effective_date: NaiveDate) -> Observation {
-    let uicc_code_value: UiccStage = Faker.fake();
-    let tnmm: TnmmCategory = Faker.fake();
-    let tnmn: TnmnCategory = Faker.fake();
-    let tnmt: TnmtCategory = Faker.fake();
+pub fn get_tnmc(
+    id: &str,
+    subject_ref: &str,
+    effective_date: NaiveDate,
+    tnm: &Tnm,
+    symbols: TnmSymbols,
+) -> Observation {
+    get_tnm(
+        id,
+        subject_ref,
+        effective_date,
+        tnm,
+        symbols,
+        TnmClassification::Clinical,
+    )
+}
+
+/// Generates observation TNMp, i.e. the TNM classification after the resection of the tumour
+pub fn get_tnmp(
+    id: &str,
+    subject_ref: &str,
+    effective_date: NaiveDate,
+    tnm: &Tnm,
+    symbols: TnmSymbols,
+) -> Observation {
+    get_tnm(
+        id,
+        subject_ref,
+        effective_date,
+        tnm,
+        symbols,
+        TnmClassification::Pathologic,
+    )
+}
+
+/// Generates the TNM observation, the symbols are only added as components if they apply
+fn get_tnm(
+    id: &str,
+    subject_ref: &str,
+    effective_date: NaiveDate,
+    tnm: &Tnm,
+    symbols: TnmSymbols,
+    classification: TnmClassification,
+) -> Observation {
+    let Tnm {
+        t: tnmt,
+        n: tnmn,
+        m: tnmm,
+        uicc_stage: uicc_code_value,
+    } = tnm;
+    let (tnmt_code, tnmn_code, tnmm_code) = match classification {
+        TnmClassification::Clinical => (
+            TnmtClassification::Clinical.as_str(),
+            TnmnClassification::Clinical.as_str(),
+            TnmmClassification::Clinical.as_str(),
+        ),
+        TnmClassification::Pathologic => (
+            TnmtClassification::Pathologic.as_str(),
+            TnmnClassification::Pathologic.as_str(),
+            TnmmClassification::Pathologic.as_str(),
+        ),
+    };
     // TODO: check date, code etc.
     let oid = Id {
         value: Some(id.to_string()),
@@ -183,7 +268,7 @@ pub fn get_tnmc(id: &str, subject_ref: &str, effective_date: NaiveDate) -> Obser
         ..Default::default()
     };
     let tnmm_comp = ObservationComponent {
-        code: Box::new(get_loinc_code(TnmmClassification::Clinical.as_str())),
+        code: Box::new(get_loinc_code(tnmm_code)),
         value: Some(ObservationComponentValue::CodeableConcept(Box::new(
             tnmm_concept,
         ))),
@@ -200,7 +285,7 @@ pub fn get_tnmc(id: &str, subject_ref: &str, effective_date: NaiveDate) -> Obser
         ..Default::default()
     };
     let tnmn_comp = ObservationComponent {
-        code: Box::new(get_loinc_code(TnmnClassification::Clinical.as_str())),
+        code: Box::new(get_loinc_code(tnmn_code)),
         value: Some(ObservationComponentValue::CodeableConcept(Box::new(
             tnmn_concept,
         ))),
@@ -217,13 +302,36 @@ pub fn get_tnmc(id: &str, subject_ref: &str, effective_date: NaiveDate) -> Obser
         ..Default::default()
     };
     let tnmt_comp = ObservationComponent {
-        code: Box::new(get_loinc_code(TnmtClassification::Clinical.as_str())),
+        code: Box::new(get_loinc_code(tnmt_code)),
         value: Some(ObservationComponentValue::CodeableConcept(Box::new(
             tnmt_concept,
         ))),
         ..Default::default()
     };
 
+    let mut component = vec![tnmm_comp, tnmn_comp, tnmt_comp];
+    if symbols.y {
+        component.push(get_symbol_component(
+            TNM_Y_SYMBOL_LOINC_CODE,
+            TnmySymbol::get_url(),
+            TnmySymbol::Y.code(),
+        ));
+    }
+    if symbols.r {
+        component.push(get_symbol_component(
+            TNM_R_SYMBOL_LOINC_CODE,
+            TnmrSymbol::get_url(),
+            TnmrSymbol::R.code(),
+        ));
+    }
+    if symbols.m {
+        component.push(get_symbol_component(
+            TNM_M_SYMBOL_LOINC_CODE,
+            TnmmSymbol::get_url(),
+            TnmmSymbol::M.code(),
+        ));
+    }
+
     Observation {
         r#id: Some(oid),
         subject: Some(Box::new(subject_rfrnc)),
@@ -231,85 +339,220 @@ pub fn get_tnmc(id: &str, subject_ref: &str, effective_date: NaiveDate) -> Obser
         // NOTE: status is required by the FHIR lib
         status: OBSERVATION_STATUS.into(),
         value: Some(ObservationValue::CodeableConcept(Box::new(cod_concept))),
-        code: Box::new(get_loinc_code(TnmClassification::Clinical.as_str())),
-        // component: vec![tnmm_comp, tnmn_comp, tnmr_comp, tnmy_comp],
-        component: vec![tnmm_comp, tnmn_comp, tnmt_comp],
+        code: Box::new(get_loinc_code(classification.as_str())),
+        component,
+        ..Default::default()
+    }
+}
+
+fn get_symbol_component(loinc_code: &str, system: String, code: &str) -> ObservationComponent {
+    let coding = Coding {
+        system: Some(Uri::from(system)),
+        code: Some(Code::from(code)),
+        ..Default::default()
+    };
+    let concept = CodeableConcept {
+        coding: vec![coding],
+        ..Default::default()
+    };
+
+    ObservationComponent {
+        code: Box::new(get_loinc_code(loinc_code)),
+        value: Some(ObservationComponentValue::CodeableConcept(Box::new(
+            concept,
+        ))),
         ..Default::default()
     }
 }
 
 pub fn get_bundle_entry(observation: Observation, observation_ref_id: &str) -> BundleEntry {
     BundleEntry {
-        full_url: Some(get_full_url(
-            observation.clone().id.unwrap().value.unwrap().as_str(),
-        )),
         resource: Some(Resource::Observation(Box::new(observation.clone()))),
         request: get_bundle_entry_request("PUT", observation_ref_id).into_some(),
         ..Default::default()
     }
 }
 
-pub fn get_histologies(
-    subject_ref: &str,
-    focus_ref: &str,
-    specimen_ref: &str,
-    effective_date: NaiveDate,
-    code_value: &str,
-    range: Range<u8>,
-) -> Vec<(Observation, String)> {
-    range
-        .map(|_| {
-            let i: u16 = Faker.fake();
-            let (obs_hist_id, obs_hist_ref_id) =
-                get_ids(IdType::Id, ResourceType::ObservationHistology, i);
-            (
-                get_histology(
-                    obs_hist_id.as_str(),
-                    subject_ref,
-                    focus_ref,
-                    specimen_ref,
-                    effective_date,
-                    code_value,
-                ),
-                obs_hist_ref_id,
-            )
-        })
-        .collect()
+/// Generates a histology of the patient's tumour, done on a specimen
+pub struct HistologyGenerator;
+
+impl ResourceGenerator for HistologyGenerator {
+    fn resource_type(&self) -> ResourceType {
+        ResourceType::ObservationHistology
+    }
+
+    fn dependencies(&self) -> &'static [ResourceType] {
+        &[
+            ResourceType::Patient,
+            ResourceType::Condition,
+            ResourceType::Specimen,
+        ]
+    }
+
+    fn generate(
+        &self,
+        i: &str,
+        context: &mut GeneratorContext,
+        _ids: &mut IdAllocator,
+        _scenario: &Scenario,
+        _rng: &mut StdRng,
+    ) -> Vec<BundleEntry> {
+        let (obs_hist_id, obs_hist_ref_id) =
+            get_ids(IdType::Id, ResourceType::ObservationHistology, i);
+        let histology = get_histology(
+            obs_hist_id.as_str(),
+            context.get_ref_id(ResourceType::Patient).as_str(),
+            context.get_ref_id(ResourceType::Condition).as_str(),
+            context.get_ref_id(ResourceType::Specimen).as_str(),
+            context.timeline.histology_date,
+            &context.diagnosis,
+        );
+        vec![get_bundle_entry(histology, obs_hist_ref_id.as_str())]
+    }
 }
 
-pub fn get_vital_statuses(
-    subject_ref: &str,
-    effective_date: NaiveDate,
-    range: Range<u8>,
-) -> Vec<(Observation, String)> {
-    range
-        .map(|_| {
-            let i: u16 = Faker.fake();
-            let (ovs_id, ovs_ref_id) = get_ids(IdType::Id, ResourceType::ObservationVitalStatus, i);
-            (
-                get_vital_status(ovs_id.as_str(), subject_ref, effective_date),
-                ovs_ref_id,
-            )
-        })
-        .collect()
+/// Generates a vital status of the patient
+pub struct VitalStatusGenerator;
+
+impl ResourceGenerator for VitalStatusGenerator {
+    fn resource_type(&self) -> ResourceType {
+        ResourceType::ObservationVitalStatus
+    }
+
+    fn dependencies(&self) -> &'static [ResourceType] {
+        &[ResourceType::Patient]
+    }
+
+    fn generate(
+        &self,
+        i: &str,
+        context: &mut GeneratorContext,
+        _ids: &mut IdAllocator,
+        scenario: &Scenario,
+        rng: &mut StdRng,
+    ) -> Vec<BundleEntry> {
+        let (ovs_id, ovs_ref_id) = get_ids(IdType::Id, ResourceType::ObservationVitalStatus, i);
+        let vital_status = get_vital_status(
+            ovs_id.as_str(),
+            context.get_ref_id(ResourceType::Patient).as_str(),
+            context.timeline.last_vital_status_date,
+            context.timeline.deceased_date.is_some(),
+            scenario,
+            rng,
+        );
+        vec![get_bundle_entry(vital_status, ovs_ref_id.as_str())]
+    }
 }
 
-pub fn get_tnmcs(
-    subject_ref: &str,
-    effective_date: NaiveDate,
-    range: Range<u8>,
-) -> Vec<(Observation, String)> {
-    range
-        .map(|_| {
-            let i: u16 = Faker.fake();
-            let (obs_tnmc_id, obs_tnmc_ref_id) =
-                get_ids(IdType::Id, ResourceType::ObservationTNMc, i);
-            (
-                get_tnmc(obs_tnmc_id.as_str(), subject_ref, effective_date),
-                obs_tnmc_ref_id,
-            )
-        })
-        .collect()
+/// Generates a clinical TNM classification of the patient's tumour
+pub struct TnmcGenerator;
+
+impl ResourceGenerator for TnmcGenerator {
+    fn resource_type(&self) -> ResourceType {
+        ResourceType::ObservationTNMc
+    }
+
+    fn dependencies(&self) -> &'static [ResourceType] {
+        &[ResourceType::Patient]
+    }
+
+    fn count_per_patient(&self, scenario: &Scenario) -> u8 {
+        scenario.resources_per_patient.tnmcs
+    }
+
+    fn generate(
+        &self,
+        i: &str,
+        context: &mut GeneratorContext,
+        _ids: &mut IdAllocator,
+        scenario: &Scenario,
+        rng: &mut StdRng,
+    ) -> Vec<BundleEntry> {
+        let (obs_tnmc_id, obs_tnmc_ref_id) = get_ids(IdType::Id, ResourceType::ObservationTNMc, i);
+        let tnm = get_clinical_tnm(context.diagnosis.staging_scheme, scenario, rng);
+        context.clinical_tnms.push(tnm);
+        let tnmc = get_tnmc(
+            obs_tnmc_id.as_str(),
+            context.get_ref_id(ResourceType::Patient).as_str(),
+            context.timeline.diagnosis_date,
+            &tnm,
+            context.get_tnm_symbols(false),
+        );
+        vec![get_bundle_entry(tnmc, obs_tnmc_ref_id.as_str())]
+    }
+}
+
+/// Generates a pathological TNM classification of the patient's tumour at its resection, derived from
+/// the (first) clinical one
+pub struct TnmpGenerator;
+
+impl ResourceGenerator for TnmpGenerator {
+    fn resource_type(&self) -> ResourceType {
+        ResourceType::ObservationTNMp
+    }
+
+    fn dependencies(&self) -> &'static [ResourceType] {
+        &[ResourceType::Patient, ResourceType::ObservationTNMc]
+    }
+
+    /// Only an operated patient gets a pTNM
+    fn count_per_patient(&self, scenario: &Scenario) -> u8 {
+        u8::from(scenario.resources_per_patient.operations > 0)
+    }
+
+    fn generate(
+        &self,
+        i: &str,
+        context: &mut GeneratorContext,
+        _ids: &mut IdAllocator,
+        scenario: &Scenario,
+        rng: &mut StdRng,
+    ) -> Vec<BundleEntry> {
+        let (obs_tnmp_id, obs_tnmp_ref_id) = get_ids(IdType::Id, ResourceType::ObservationTNMp, i);
+        let staging_scheme = context.diagnosis.staging_scheme;
+        let clinical_tnm = context
+            .clinical_tnms
+            .first()
+            .copied()
+            .unwrap_or_else(|| get_clinical_tnm(staging_scheme, scenario, rng));
+        let tnm = staging_scheme.get_pathologic_tnm(&clinical_tnm, rng);
+        let tnmp = get_tnmp(
+            obs_tnmp_id.as_str(),
+            context.get_ref_id(ResourceType::Patient).as_str(),
+            context.timeline.operation_date,
+            &tnm,
+            context.get_tnm_symbols(true),
+        );
+        vec![get_bundle_entry(tnmp, obs_tnmp_ref_id.as_str())]
+    }
+}
+
+/// Generates the clinical TNM classification of a recurrence of the patient's tumour (after the
+/// therapy), if the patient has one
+pub fn get_recurrence_tnmc(
+    context: &GeneratorContext,
+    ids: &mut IdAllocator,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) -> Option<BundleEntry> {
+    let recurrence_date = scenario
+        .get_recurrence(rng)
+        .then(|| context.timeline.get_recurrence_date(rng))
+        .flatten()?;
+    let (obs_tnmc_id, obs_tnmc_ref_id) =
+        get_ids(IdType::Id, ResourceType::ObservationTNMc, &ids.next_id());
+    let tnm = get_clinical_tnm(context.diagnosis.staging_scheme, scenario, rng);
+    let tnmc = get_tnmc(
+        obs_tnmc_id.as_str(),
+        context.get_ref_id(ResourceType::Patient).as_str(),
+        recurrence_date,
+        &tnm,
+        TnmSymbols {
+            r: true,
+            ..context.get_tnm_symbols(false)
+        },
+    );
+    Some(get_bundle_entry(tnmc, obs_tnmc_ref_id.as_str()))
 }
 
 fn get_loinc_code(code_val: &str) -> CodeableConcept {
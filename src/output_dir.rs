@@ -0,0 +1,628 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::models::cli::{ArchiveFormat, OverwritePolicy};
+use crate::utils::DATA_FOLDER;
+
+pub const MANIFEST: &str = "manifest.json";
+
+/// The directory the generated files are written to, or the archive they are packed into. Every
+/// written file is hashed (SHA-256) and counted while it is written, so that the manifest listing
+/// them can be written at the end without reading the files again.
+pub struct OutputDir {
+    dir: PathBuf,
+    overwrite_policy: OverwritePolicy,
+    archive: Option<Archive>,
+    files: Vec<ManifestFile>,
+}
+
+/// A file in the output directory (or in the archive); it is hashed while it is written
+pub struct OutputFile {
+    path: String,
+    writer: BufWriter<FileWriter>,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+enum FileWriter {
+    /// A file in the output directory
+    File(File),
+    /// A file in the staging directory of the archive, which is packed once it is complete
+    Staged(File, PathBuf),
+    /// The current entry of the zip archive
+    Zip(Rc<RefCell<ZipWriter<BufWriter<File>>>>),
+}
+
+/// The archive, which the files are packed into instead of the output directory. A zip archive is
+/// written straight through, one file after the other. A tar entry starts with its size (and the
+/// gzip stream cannot be rewound to set it afterwards), and several files can be written at the
+/// same time (e.g. the NDJSON files), hence the other files are staged in a temporary directory
+/// and packed, once they are complete. An archive, that is not finished (e.g. after an error), is
+/// removed along with the staged files.
+struct Archive {
+    format: ArchiveFormat,
+    path: PathBuf,
+    staging_dir: PathBuf,
+    // created along with the first file
+    writer: Option<ArchiveWriter>,
+    // whether a file is written straight into the (zip) archive right now
+    streaming: bool,
+    // the staged files, that are complete, but wait for the streamed file
+    pending: Vec<(String, PathBuf)>,
+}
+
+enum ArchiveWriter {
+    TarGz(Box<tar::Builder<GzEncoder<File>>>),
+    Zip(Rc<RefCell<ZipWriter<BufWriter<File>>>>),
+}
+
+/// The manifest of a run, listing the written files along with the arguments they were generated
+/// with
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub generator: String,
+    pub arguments: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    pub resources: u64,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestFile {
+    /// The path of the file, relative to the output directory
+    pub path: String,
+    pub sha256: String,
+    pub bytes: u64,
+    /// The number of FHIR resources in the file (none for a file that has no resources)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<u64>,
+}
+
+impl OutputDir {
+    /// Creates the output directory (and its parents) lazily, i.e. when the first file is written.
+    /// With an archive format, the files are packed into an archive named after the directory (e.g.
+    /// `generated-data.tar.gz`) instead.
+    pub fn new(
+        dir: PathBuf,
+        overwrite_policy: OverwritePolicy,
+        archive_format: Option<ArchiveFormat>,
+    ) -> Self {
+        let archive = archive_format.map(|format| Archive::new(&dir, format));
+        Self {
+            dir,
+            overwrite_policy,
+            archive,
+            files: Vec::new(),
+        }
+    }
+
+    /// Returns the path of the output directory or of the archive
+    pub fn path(&self) -> &Path {
+        match &self.archive {
+            Some(archive) => &archive.path,
+            None => &self.dir,
+        }
+    }
+
+    /// Creates the file at the given path (relative to the output directory, with `/` separators),
+    /// according to the overwrite policy
+    pub fn create(&mut self, path: &str) -> io::Result<OutputFile> {
+        let writer = match &mut self.archive {
+            Some(archive) => archive.create(path, self.overwrite_policy)?,
+            None => FileWriter::File(create_file(&self.dir.join(path), self.overwrite_policy)?),
+        };
+
+        Ok(OutputFile {
+            path: path.to_string(),
+            writer: BufWriter::new(writer),
+            hasher: Sha256::new(),
+            bytes: 0,
+        })
+    }
+
+    /// Flushes the file (and packs it into the archive) and adds it to the manifest, along with the
+    /// number of resources in it
+    pub fn close(&mut self, file: OutputFile, resources: Option<u64>) -> io::Result<()> {
+        let OutputFile {
+            path,
+            writer,
+            hasher,
+            bytes,
+        } = file;
+        match writer.into_inner().map_err(|e| e.into_error())? {
+            FileWriter::File(_) => {}
+            FileWriter::Staged(file, staged_path) => {
+                drop(file);
+                if let Some(archive) = &mut self.archive {
+                    archive.append(path.clone(), staged_path)?;
+                }
+            }
+            FileWriter::Zip(_) => {
+                if let Some(archive) = &mut self.archive {
+                    archive.close_streamed()?;
+                }
+            }
+        }
+
+        self.files.push(ManifestFile {
+            path,
+            sha256: to_hex(&hasher.finalize()),
+            bytes,
+            resources,
+        });
+        Ok(())
+    }
+
+    /// Writes the whole file at once
+    pub fn write(&mut self, path: &str, data: &[u8], resources: Option<u64>) -> io::Result<()> {
+        let mut file = self.create(path)?;
+        file.write_all(data)?;
+        self.close(file, resources)
+    }
+
+    /// Removes the unfinished archive and the staged files (if any), e.g. before exiting after an
+    /// error; the files written to the output directory are kept
+    pub fn discard(&mut self) {
+        self.archive = None;
+    }
+
+    /// Writes the manifest listing the written files; nothing is written, if no file was written
+    pub fn finish(
+        mut self,
+        arguments: Vec<String>,
+        seed: Option<u64>,
+    ) -> io::Result<Option<Manifest>> {
+        if self.files.is_empty() {
+            return Ok(None);
+        }
+
+        let manifest = Manifest {
+            generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            arguments,
+            seed,
+            resources: self.files.iter().filter_map(|f| f.resources).sum(),
+            files: std::mem::take(&mut self.files),
+        };
+        let json = serde_json::to_string_pretty(&manifest)
+            .expect("Failed to serialize the manifest to JSON");
+        let mut manifest_file = self.create(MANIFEST)?;
+        manifest_file.write_all(json.as_bytes())?;
+        manifest_file.write_all(b"\n")?;
+        self.close(manifest_file, None)?;
+
+        if let Some(archive) = &mut self.archive {
+            archive.finish()?;
+        }
+
+        Ok(Some(manifest))
+    }
+}
+
+impl Archive {
+    fn new(dir: &Path, format: ArchiveFormat) -> Self {
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or(DATA_FOLDER.to_string());
+        let file_name = format!("{name}.{}", format.extension());
+        Self {
+            format,
+            path: dir.with_file_name(&file_name),
+            staging_dir: env::temp_dir()
+                .join(format!("cce-fhir-gen-{}-{file_name}", process::id())),
+            writer: None,
+            streaming: false,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Creates the file in the archive: a zip entry is written straight through, unless another
+    /// file is written into the archive right now, otherwise the file is staged
+    fn create(&mut self, path: &str, overwrite_policy: OverwritePolicy) -> io::Result<FileWriter> {
+        // the archive is created before anything is staged, so that a refused overwrite leaves no
+        // staged files behind
+        self.open(overwrite_policy)?;
+        if let Some(ArchiveWriter::Zip(zip)) = &self.writer {
+            if !self.streaming {
+                // the size is not known yet
+                let options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Deflated)
+                    .large_file(true);
+                zip.borrow_mut()
+                    .start_file(path, options)
+                    .map_err(io::Error::other)?;
+                self.streaming = true;
+                return Ok(FileWriter::Zip(Rc::clone(zip)));
+            }
+        }
+
+        let staged_path = self.staging_dir.join(path);
+        if let Some(parent) = staged_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(FileWriter::Staged(File::create(&staged_path)?, staged_path))
+    }
+
+    /// Creates the archive file according to the overwrite policy, unless it is already created
+    fn open(&mut self, overwrite_policy: OverwritePolicy) -> io::Result<()> {
+        if self.writer.is_none() {
+            let file = create_file(&self.path, overwrite_policy)?;
+            self.writer = Some(match self.format {
+                ArchiveFormat::TarGz => ArchiveWriter::TarGz(Box::new(tar::Builder::new(
+                    GzEncoder::new(file, Compression::default()),
+                ))),
+                ArchiveFormat::Zip => {
+                    ArchiveWriter::Zip(Rc::new(RefCell::new(ZipWriter::new(BufWriter::new(file)))))
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Packs the complete staged file into the archive, once no file is streamed into it
+    fn append(&mut self, path: String, staged_path: PathBuf) -> io::Result<()> {
+        self.pending.push((path, staged_path));
+        if self.streaming {
+            return Ok(());
+        }
+        self.append_pending()
+    }
+
+    /// Ends the streamed file and packs the staged files, that were completed in the meantime
+    fn close_streamed(&mut self) -> io::Result<()> {
+        self.streaming = false;
+        self.append_pending()
+    }
+
+    /// Packs the pending staged files into the archive and removes them
+    fn append_pending(&mut self) -> io::Result<()> {
+        let Some(writer) = &mut self.writer else {
+            return Err(io::Error::other("the archive is not created"));
+        };
+
+        for (path, staged_path) in self.pending.drain(..) {
+            let mut staged_file = File::open(&staged_path)?;
+            let size = staged_file.metadata()?.len();
+            match writer {
+                ArchiveWriter::TarGz(builder) => {
+                    // the entries get no modification time, so that the same files give the same
+                    // archive
+                    let mut header = tar::Header::new_gnu();
+                    header.set_size(size);
+                    header.set_mode(0o644);
+                    header.set_mtime(0);
+                    builder.append_data(&mut header, &path, &mut staged_file)?;
+                }
+                ArchiveWriter::Zip(zip) => {
+                    let options = SimpleFileOptions::default()
+                        .compression_method(CompressionMethod::Deflated)
+                        .large_file(size > u64::from(u32::MAX));
+                    let mut zip = zip.borrow_mut();
+                    zip.start_file(path, options).map_err(io::Error::other)?;
+                    io::copy(&mut staged_file, &mut *zip)?;
+                }
+            }
+            fs::remove_file(staged_path)?;
+        }
+        Ok(())
+    }
+
+    /// Packs the remaining staged files, writes the end of the archive and removes the staging
+    /// directory
+    fn finish(&mut self) -> io::Result<()> {
+        self.append_pending()?;
+        match self.writer.take() {
+            Some(ArchiveWriter::TarGz(builder)) => {
+                builder.into_inner()?.finish()?;
+            }
+            Some(ArchiveWriter::Zip(zip)) => {
+                let zip = Rc::try_unwrap(zip)
+                    .map_err(|_| io::Error::other("a file of the archive is not closed"))?;
+                zip.into_inner()
+                    .finish()
+                    .map_err(io::Error::other)?
+                    .flush()?;
+            }
+            None => {}
+        }
+        if self.staging_dir.exists() {
+            fs::remove_dir_all(&self.staging_dir)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Archive {
+    fn drop(&mut self) {
+        // the archive is still written, if it is not finished, i.e. it is incomplete
+        if let Some(writer) = self.writer.take() {
+            drop(writer);
+            let _ = fs::remove_file(&self.path);
+        }
+        let _ = fs::remove_dir_all(&self.staging_dir);
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FileWriter::File(file) | FileWriter::Staged(file, _) => file.write(buf),
+            FileWriter::Zip(zip) => zip.borrow_mut().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FileWriter::File(file) | FileWriter::Staged(file, _) => file.flush(),
+            FileWriter::Zip(zip) => zip.borrow_mut().flush(),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Creates the file (and its directory) according to the overwrite policy
+fn create_file(file_path: &Path, overwrite_policy: OverwritePolicy) -> io::Result<File> {
+    if let Some(parent) = file_path.parent() {
+        if !parent.as_os_str().is_empty() && !parent.exists() {
+            info!("creating {}", parent.display());
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    match overwrite_policy {
+        OverwritePolicy::NoClobber => File::create_new(file_path).map_err(|e| {
+            if e.kind() == io::ErrorKind::AlreadyExists {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "{} already exists (remove --no-clobber to replace it)",
+                        file_path.display()
+                    ),
+                )
+            } else {
+                e
+            }
+        }),
+        OverwritePolicy::Warn if file_path.exists() => {
+            warn!("replacing {}", file_path.display());
+            File::create(file_path)
+        }
+        OverwritePolicy::Warn | OverwritePolicy::Overwrite => File::create(file_path),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn get_temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("cce-fhir-gen-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_manifest_lists_the_hashed_files() {
+        let dir = get_temp_dir("output-dir");
+        let mut out_dir = OutputDir::new(dir.clone(), OverwritePolicy::Warn, None);
+        out_dir
+            .write("Patient/Patient-id-1.xml", b"abc", Some(1))
+            .expect("file is written");
+        let manifest = out_dir
+            .finish(vec!["synthetic-data".into()], Some(42))
+            .expect("manifest is written")
+            .expect("manifest has files");
+
+        assert_eq!(manifest.resources, 1, "resources do not match");
+        let file = &manifest.files[0];
+        assert_eq!(file.path, "Patient/Patient-id-1.xml", "path does not match");
+        assert_eq!(
+            file.sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            "hash does not match"
+        );
+        assert_eq!(file.bytes, 3, "size does not match");
+        assert!(dir.join(MANIFEST).exists(), "manifest is not written");
+
+        fs::remove_dir_all(dir).expect("temp dir is removed");
+    }
+
+    #[test]
+    fn test_archive_has_the_files_and_the_manifest() {
+        let dir = get_temp_dir("archive");
+        let mut out_dir = OutputDir::new(
+            dir.join("cohort"),
+            OverwritePolicy::Warn,
+            Some(ArchiveFormat::TarGz),
+        );
+        // the files are written at the same time, like the NDJSON files
+        let mut patients = out_dir.create("Patient.ndjson").expect("file is created");
+        let mut conditions = out_dir.create("Condition.ndjson").expect("file is created");
+        patients.write_all(b"patient").expect("file is written");
+        conditions.write_all(b"condition").expect("file is written");
+        out_dir.close(patients, Some(1)).expect("file is closed");
+        out_dir.close(conditions, Some(1)).expect("file is closed");
+        out_dir
+            .finish(vec![], None)
+            .expect("manifest is written")
+            .expect("manifest has files");
+
+        let archive = File::open(dir.join("cohort.tar.gz")).expect("archive is written");
+        let mut entries: Vec<(String, String)> =
+            tar::Archive::new(flate2::read::GzDecoder::new(archive))
+                .entries()
+                .expect("archive is readable")
+                .map(|entry| {
+                    let mut entry = entry.expect("entry is readable");
+                    let path = entry
+                        .path()
+                        .expect("entry has a path")
+                        .display()
+                        .to_string();
+                    let mut data = String::new();
+                    io::Read::read_to_string(&mut entry, &mut data).expect("entry is readable");
+                    (path, data)
+                })
+                .collect();
+        entries.sort();
+
+        let paths: Vec<&str> = entries.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["Condition.ndjson", "Patient.ndjson", MANIFEST],
+            "paths do not match"
+        );
+        assert_eq!(entries[1].1, "patient", "file does not match");
+        assert!(
+            !dir.join("cohort").exists(),
+            "files are written to the directory"
+        );
+
+        fs::remove_dir_all(dir).expect("temp dir is removed");
+    }
+
+    #[test]
+    fn test_zip_archive_has_the_files_and_the_manifest() {
+        let dir = get_temp_dir("zip");
+        let mut out_dir = OutputDir::new(
+            dir.join("cohort"),
+            OverwritePolicy::Warn,
+            Some(ArchiveFormat::Zip),
+        );
+        // the first file is streamed, the second one is staged, as it is written at the same time
+        let mut patients = out_dir.create("Patient.ndjson").expect("file is created");
+        let mut conditions = out_dir.create("Condition.ndjson").expect("file is created");
+        patients.write_all(b"patient").expect("file is written");
+        conditions.write_all(b"condition").expect("file is written");
+        out_dir.close(conditions, Some(1)).expect("file is closed");
+        out_dir.close(patients, Some(1)).expect("file is closed");
+        out_dir
+            .write("Specimen.ndjson", b"specimen", Some(1))
+            .expect("file is written");
+        let manifest = out_dir
+            .finish(vec![], None)
+            .expect("manifest is written")
+            .expect("manifest has files");
+
+        let archive = File::open(dir.join("cohort.zip")).expect("archive is written");
+        let mut zip = zip::ZipArchive::new(archive).expect("archive is readable");
+        let mut entries: Vec<(String, String)> = (0..zip.len())
+            .map(|i| {
+                let mut entry = zip.by_index(i).expect("entry is readable");
+                let mut data = String::new();
+                io::Read::read_to_string(&mut entry, &mut data).expect("entry is readable");
+                (entry.name().expect("entry has a name").to_string(), data)
+            })
+            .collect();
+        entries.sort();
+
+        let paths: Vec<&str> = entries.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "Condition.ndjson",
+                "Patient.ndjson",
+                "Specimen.ndjson",
+                MANIFEST
+            ],
+            "paths do not match"
+        );
+        assert_eq!(entries[0].1, "condition", "file does not match");
+        assert_eq!(entries[1].1, "patient", "file does not match");
+        assert_eq!(entries[2].1, "specimen", "file does not match");
+        let patients = manifest
+            .files
+            .iter()
+            .find(|f| f.path == "Patient.ndjson")
+            .expect("file is listed");
+        assert_eq!(patients.bytes, 7, "size does not match");
+        assert!(
+            !dir.join("cohort").exists(),
+            "files are written to the directory"
+        );
+
+        fs::remove_dir_all(dir).expect("temp dir is removed");
+    }
+
+    #[test]
+    fn test_discard_removes_the_unfinished_archive() {
+        let dir = get_temp_dir("discard");
+        let mut out_dir = OutputDir::new(
+            dir.join("cohort"),
+            OverwritePolicy::Warn,
+            Some(ArchiveFormat::TarGz),
+        );
+        let mut patients = out_dir.create("Patient.ndjson").expect("file is created");
+        patients.write_all(b"patient").expect("file is written");
+        let staging_dir = out_dir
+            .archive
+            .as_ref()
+            .expect("archive is set")
+            .staging_dir
+            .clone();
+        assert!(staging_dir.exists(), "file is not staged");
+
+        drop(patients);
+        out_dir.discard();
+        assert!(!staging_dir.exists(), "staged files are kept");
+        assert!(
+            !dir.join("cohort.tar.gz").exists(),
+            "unfinished archive is kept"
+        );
+
+        fs::remove_dir_all(dir).expect("temp dir is removed");
+    }
+
+    #[test]
+    fn test_no_clobber_keeps_existing_files() {
+        let dir = get_temp_dir("no-clobber");
+        OutputDir::new(dir.clone(), OverwritePolicy::Warn, None)
+            .write("bundle.xml", b"first", None)
+            .expect("file is written");
+
+        let result = OutputDir::new(dir.clone(), OverwritePolicy::NoClobber, None).write(
+            "bundle.xml",
+            b"second",
+            None,
+        );
+        assert!(result.is_err(), "existing file is replaced");
+        assert_eq!(
+            fs::read_to_string(dir.join("bundle.xml")).expect("file exists"),
+            "first",
+            "existing file is changed"
+        );
+
+        fs::remove_dir_all(dir).expect("temp dir is removed");
+    }
+}
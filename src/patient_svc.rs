@@ -1,27 +1,28 @@
-use std::ops::Range;
-
-use chrono::{prelude::*, Months};
-use fake::faker::chrono::en::DateTimeAfter;
-use fake::{Fake, Faker};
+use chrono::NaiveDate;
 use fhirbolt::model::r4b::resources::{BundleEntry, Patient, PatientDeceased};
 use fhirbolt::model::r4b::types::{Code, Date, DateTime, Id, Identifier, String};
 use fhirbolt::model::r4b::Resource;
 use log::debug;
+use rand::rngs::StdRng;
 
 use crate::extensions::option_ext::OptionExt;
 use crate::models::cli::ResourceType;
 use crate::models::enums::gender::Gender;
 use crate::models::enums::id_type::IdType;
-use crate::utils::{get_bundle_entry_request, get_full_url, get_ids, get_min_date_time};
+use crate::models::id_allocator::IdAllocator;
+use crate::models::scenario::Scenario;
+use crate::resource_generator::{GeneratorContext, ResourceGenerator};
+use crate::utils::{get_bundle_entry_request, get_ids, get_patient_identifier_url};
 
-pub fn get_patient(id: &str, src_id: &str) -> Patient {
+pub fn get_patient(
+    id: &str,
+    src_id: &str,
+    gender: Gender,
+    birth_date: NaiveDate,
+    deceased_date: Option<NaiveDate>,
+) -> Patient {
     debug!("get_patient - id: {}, src_id: {}", id, src_id);
 
-    let gender: Gender = Faker.fake();
-    let min_date_time: chrono::DateTime<Utc> = get_min_date_time();
-    let birthdate: chrono::DateTime<Utc> = DateTimeAfter(min_date_time).fake();
-    let deceased: bool = Faker.fake();
-
     let oid = Id {
         value: Some(id.to_string()),
         ..Default::default()
@@ -32,6 +33,7 @@ pub fn get_patient(id: &str, src_id: &str) -> Patient {
         ..Default::default()
     };
     let identifier = Identifier {
+        system: Some(get_patient_identifier_url()),
         value: Some(id_val),
         ..Default::default()
     };
@@ -44,16 +46,15 @@ pub fn get_patient(id: &str, src_id: &str) -> Patient {
             ..Default::default()
         }),
         birth_date: Some(Date {
-            value: Some(birthdate.date_naive().to_string()),
+            value: Some(birth_date.to_string()),
             ..Default::default()
         }),
         ..Default::default()
     };
 
-    if deceased {
-        let deceased_date = birthdate.checked_add_months(Months::new(600)).unwrap();
+    if let Some(deceased_date) = deceased_date {
         let deceased_date_time =
-            PatientDeceased::DateTime(DateTime::from(deceased_date.date_naive().to_string()));
+            PatientDeceased::DateTime(DateTime::from(deceased_date.to_string()));
 
         patient.deceased = Some(deceased_date_time);
         patient
@@ -64,25 +65,74 @@ pub fn get_patient(id: &str, src_id: &str) -> Patient {
 
 pub fn get_bundle_entry(patient: Patient, patient_ref_id: &str) -> BundleEntry {
     BundleEntry {
-        full_url: Some(get_full_url(
-            patient.clone().id.unwrap().value.unwrap().as_str(),
-        )),
         resource: Some(Resource::Patient(Box::new(patient.clone()))),
         request: get_bundle_entry_request("PUT", patient_ref_id).into_some(),
         ..Default::default()
     }
 }
 
-pub fn get_patients(range: Range<u8>) -> Vec<(Patient, std::string::String)> {
-    range
-        .map(|_| {
-            let i: u16 = Faker.fake();
-            let (patient_id, patient_ref_id) = get_ids(IdType::Id, ResourceType::Patient, i);
-            let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
-            (
-                get_patient(patient_id.as_str(), patient_src_id.as_str()),
-                patient_ref_id,
-            )
-        })
-        .collect()
+/// Generates a patient
+pub struct PatientGenerator;
+
+impl ResourceGenerator for PatientGenerator {
+    fn resource_type(&self) -> ResourceType {
+        ResourceType::Patient
+    }
+
+    fn dependencies(&self) -> &'static [ResourceType] {
+        &[]
+    }
+
+    fn generate(
+        &self,
+        i: &str,
+        context: &mut GeneratorContext,
+        _ids: &mut IdAllocator,
+        _scenario: &Scenario,
+        _rng: &mut StdRng,
+    ) -> Vec<BundleEntry> {
+        let (patient_id, patient_ref_id) = get_ids(IdType::Id, ResourceType::Patient, i);
+        let (patient_src_id, _) = get_ids(IdType::Identifier, ResourceType::Patient, i);
+        let patient = get_patient(
+            patient_id.as_str(),
+            patient_src_id.as_str(),
+            context.patient_attributes.gender,
+            context.timeline.birth_date,
+            context.timeline.deceased_date,
+        );
+        vec![get_bundle_entry(patient, patient_ref_id.as_str())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::models::patient_attributes::PatientAttributes;
+    use crate::models::timeline::Timeline;
+
+    fn get_seeded_patient(seed: u64) -> Patient {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let scenario = Scenario::default();
+        let timeline = Timeline::new(&scenario, &mut rng);
+        let patient_attributes = PatientAttributes::new(&timeline, &scenario, &mut rng);
+        get_patient(
+            "Patient-id-1",
+            "Patient-src-identifier-1",
+            patient_attributes.gender,
+            timeline.birth_date,
+            timeline.deceased_date,
+        )
+    }
+
+    #[test]
+    fn test_get_patient_is_reproducible_with_the_same_seed() {
+        let pt1 = get_seeded_patient(7);
+        let pt2 = get_seeded_patient(7);
+        assert_eq!(
+            pt1, pt2,
+            "patients generated with the same seed do not match"
+        );
+    }
 }
@@ -1,19 +1,20 @@
 //! We have multiple FHIR resources for a Procedure. This module has functions to generate XML for these
 //! different Procedure resources.
 
-use std::ops::Range;
-
 use chrono::NaiveDate;
-use fake::{Fake, Faker};
 use fhirbolt::model::r4b::resources::{BundleEntry, Procedure, ProcedurePerformed};
 use fhirbolt::model::r4b::types::{Code, CodeableConcept, Coding, DateTime, Id, Period, Reference};
 use fhirbolt::model::r4b::Resource;
+use rand::rngs::StdRng;
 
 use crate::extensions::option_ext::OptionExt;
 use crate::models::cli::ResourceType;
 use crate::models::enums::id_type::IdType;
 use crate::models::enums::syst_therapy_type::SystTherapyType;
-use crate::utils::{get_bundle_entry_request, get_full_url, get_ids, get_syst_therapy_type_url};
+use crate::models::id_allocator::IdAllocator;
+use crate::models::scenario::Scenario;
+use crate::resource_generator::{GeneratorContext, ResourceGenerator};
+use crate::utils::{get_bundle_entry_request, get_ids, get_syst_therapy_type_url};
 
 pub fn get_procedure(
     id: &str,
@@ -78,77 +79,60 @@ pub fn get_procedure(
 
 pub fn get_bundle_entry(procedure: Procedure, procedure_ref_id: &str) -> BundleEntry {
     BundleEntry {
-        full_url: Some(get_full_url(
-            procedure.clone().id.unwrap().value.unwrap().as_str(),
-        )),
         resource: Some(Resource::Procedure(Box::new(procedure.clone()))),
         request: get_bundle_entry_request("PUT", procedure_ref_id).into_some(),
         ..Default::default()
     }
 }
 
-pub fn get_proc_operations(
-    src_id: &str,
-    reason_ref: &str,
-    start_date: NaiveDate,
-    end_date: NaiveDate,
-    range: Range<u8>,
-) -> Vec<(Procedure, String)> {
-    let res_type = ResourceType::ProcedureOperation;
-    get_procedures(
-        res_type,
-        src_id,
-        reason_ref,
-        start_date,
-        end_date,
-        SystTherapyType::OP,
-        range,
-    )
+/// Generates a procedure (of the given therapy type) of the patient's condition
+pub struct ProcedureGenerator {
+    pub resource_type: ResourceType,
+    pub therapy_type: SystTherapyType,
 }
 
-pub fn get_proc_radio_therapies(
-    src_id: &str,
-    reason_ref: &str,
-    start_date: NaiveDate,
-    end_date: NaiveDate,
-    range: Range<u8>,
-) -> Vec<(Procedure, String)> {
-    let res_type = ResourceType::ProcedureRadiotherapy;
-    get_procedures(
-        res_type,
-        src_id,
-        reason_ref,
-        start_date,
-        end_date,
-        SystTherapyType::RT,
-        range,
-    )
-}
+impl ResourceGenerator for ProcedureGenerator {
+    fn resource_type(&self) -> ResourceType {
+        self.resource_type
+    }
 
-fn get_procedures(
-    res_type: ResourceType,
-    src_id: &str,
-    reason_ref: &str,
-    start_date: NaiveDate,
-    end_date: NaiveDate,
-    therapy_type: SystTherapyType,
-    range: Range<u8>,
-) -> Vec<(Procedure, String)> {
-    range
-        .map(|_| {
-            let i: u16 = Faker.fake();
-            let (id, ref_id) = get_ids(IdType::Id, res_type, i);
-            (
-                get_procedure(
-                    id.as_str(),
-                    src_id,
-                    reason_ref,
-                    start_date,
-                    end_date,
-                    therapy_type.clone(),
-                ),
-                ref_id.to_string(),
-            )
-        })
-        .collect()
+    fn dependencies(&self) -> &'static [ResourceType] {
+        &[ResourceType::Patient, ResourceType::Condition]
+    }
+
+    fn count_per_patient(&self, scenario: &Scenario) -> u8 {
+        match self.therapy_type {
+            SystTherapyType::OP => scenario.resources_per_patient.operations,
+            _ => scenario.resources_per_patient.radiotherapies,
+        }
+    }
+
+    fn generate(
+        &self,
+        i: &str,
+        context: &mut GeneratorContext,
+        _ids: &mut IdAllocator,
+        _scenario: &Scenario,
+        _rng: &mut StdRng,
+    ) -> Vec<BundleEntry> {
+        let (id, ref_id) = get_ids(IdType::Id, self.resource_type, i);
+        let timeline = &context.timeline;
+        // an operation takes place on a single day
+        let (start_date, end_date) = match self.therapy_type {
+            SystTherapyType::OP => (timeline.operation_date, timeline.operation_date),
+            _ => (timeline.therapy_start_date, timeline.therapy_end_date),
+        };
+        if self.therapy_type != SystTherapyType::OP {
+            context.therapy_types.push(self.therapy_type);
+        }
+        let procedure = get_procedure(
+            id.as_str(),
+            context.get_ref_id(ResourceType::Patient).as_str(),
+            context.get_ref_id(ResourceType::Condition).as_str(),
+            start_date,
+            end_date,
+            self.therapy_type,
+        );
+        vec![get_bundle_entry(procedure, ref_id.as_str())]
+    }
 }
@@ -0,0 +1,297 @@
+//! The generators of the single resource types (`-r patient`, `-r condition`, ...). Every resource type
+//! declares the resources it depends on and how to build itself, hence a bundle of a single resource
+//! type (or of many of them) and the complete record of a patient are put together the same way for
+//! all resource types.
+
+use fhirbolt::model::r4b::resources::{Bundle, BundleEntry};
+use rand::rngs::StdRng;
+
+use crate::bundle_svc;
+use crate::condition_svc::ConditionGenerator;
+use crate::medication_svc::MedStatementGenerator;
+use crate::models::cli::ResourceType;
+use crate::models::enums::id_type::IdType;
+use crate::models::enums::syst_therapy_type::SystTherapyType;
+use crate::models::id_allocator::IdAllocator;
+use crate::models::patient_attributes::PatientAttributes;
+use crate::models::scenario::Scenario;
+use crate::models::staging::{Tnm, TnmSymbols};
+use crate::models::timeline::Timeline;
+use crate::models::tumor_entity::Diagnosis;
+use crate::observation_svc::{
+    self, HistologyGenerator, TnmcGenerator, TnmpGenerator, VitalStatusGenerator,
+};
+use crate::patient_svc::PatientGenerator;
+use crate::procedure_svc::ProcedureGenerator;
+use crate::specimen_svc::SpecimenGenerator;
+use crate::utils::get_ids;
+
+/// All the generators of the single resource types, a new resource type only has to be added here.
+/// A patient record is generated in this order, hence every generator follows its dependencies (and the
+/// pTNM follows the therapies, which decide about its y-symbol).
+static GENERATORS: &[&dyn ResourceGenerator] = &[
+    &PatientGenerator,
+    &SpecimenGenerator,
+    &ConditionGenerator,
+    &HistologyGenerator,
+    &VitalStatusGenerator,
+    &TnmcGenerator,
+    &ProcedureGenerator {
+        resource_type: ResourceType::ProcedureRadiotherapy,
+        therapy_type: SystTherapyType::RT,
+    },
+    &ProcedureGenerator {
+        resource_type: ResourceType::ProcedureOperation,
+        therapy_type: SystTherapyType::OP,
+    },
+    &MedStatementGenerator,
+    &TnmpGenerator,
+];
+
+/// Generates the resources of a single resource type
+pub trait ResourceGenerator: Sync {
+    /// The resource type of the generated resources, which their ids are built from
+    fn resource_type(&self) -> ResourceType;
+
+    /// The resource types, which the generated resources reference or are derived from. A resource of
+    /// each of them is generated once per bundle (in the given order), and precedes the generated
+    /// resources.
+    fn dependencies(&self) -> &'static [ResourceType];
+
+    /// The number of resources of a patient's record (see
+    /// [ResourcesPerPatient](crate::models::scenario::ResourcesPerPatient))
+    fn count_per_patient(&self, _scenario: &Scenario) -> u8 {
+        1
+    }
+
+    /// Generates the resource with the id built from the given (allocated) value as bundle entries; the
+    /// context is the one of the patient, which the resource belongs to, and keeps what the resource
+    /// adds to the patient's history. The resource is preceded by the resources it is derived from (if
+    /// any, e.g. the parent of a specimen), their ids are allocated from the given ids.
+    fn generate(
+        &self,
+        i: &str,
+        context: &mut GeneratorContext,
+        ids: &mut IdAllocator,
+        scenario: &Scenario,
+        rng: &mut StdRng,
+    ) -> Vec<BundleEntry>;
+}
+
+/// The patient, which the generated resources (and their dependencies) belong to
+pub struct GeneratorContext {
+    // the value, which the ids of the patient and the dependencies are built from
+    i: String,
+    pub timeline: Timeline,
+    pub patient_attributes: PatientAttributes,
+    pub diagnosis: Diagnosis,
+    /// Whether the patient has multiple primary tumours, which all TNM classifications are marked with
+    pub multiple_tumors: bool,
+    /// The clinical TNM classifications generated so far, a pTNM is derived from the first one
+    pub clinical_tnms: Vec<Tnm>,
+    /// The types of the (radio or systemic) therapies generated so far
+    pub therapy_types: Vec<SystTherapyType>,
+}
+
+impl GeneratorContext {
+    pub fn new(i: &str, scenario: &Scenario, rng: &mut StdRng) -> Self {
+        let timeline = Timeline::new(scenario, rng);
+        let patient_attributes = PatientAttributes::new(&timeline, scenario, rng);
+        let diagnosis = Diagnosis::new(&patient_attributes, rng);
+        let multiple_tumors = scenario.get_multiple_tumors(rng);
+
+        Self {
+            i: i.to_string(),
+            timeline,
+            patient_attributes,
+            diagnosis,
+            multiple_tumors,
+            clinical_tnms: vec![],
+            therapy_types: vec![],
+        }
+    }
+
+    /// The reference to the patient or to a dependency of the given type, e.g. `Patient/Patient-id-1`
+    pub fn get_ref_id(&self, resource_type: ResourceType) -> String {
+        get_ids(IdType::Id, resource_type, &self.i).1
+    }
+
+    /// The symbols of the patient's TNM classifications, a pTNM after a neoadjuvant (radio or systemic)
+    /// therapy also gets the y-symbol (but not after a surveillance, as the tumour was not treated)
+    pub fn get_tnm_symbols(&self, pathologic: bool) -> TnmSymbols {
+        let has_therapy = self
+            .therapy_types
+            .iter()
+            .any(|therapy_type| !therapy_type.is_surveillance());
+        TnmSymbols {
+            y: pathologic && has_therapy && self.timeline.neoadjuvant,
+            m: self.multiple_tumors,
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns the generator of the given resource type; a complete bundle has none, as it is not a
+/// single resource type
+pub fn get_generator(resource_type: ResourceType) -> Option<&'static dyn ResourceGenerator> {
+    GENERATORS
+        .iter()
+        .find(|generator| generator.resource_type() == resource_type)
+        .copied()
+}
+
+/// Generates a bundle with the given number of resources of the generator's type, preceded by the
+/// resources they depend on. A single resource gets the same id value as the bundle and its
+/// dependencies. Resources without dependencies (i.e. patients) get a patient context of their own.
+///
+/// Returns the bundle along with the file name (the id of the first resource).
+pub fn get_bundle(
+    generator: &dyn ResourceGenerator,
+    number: u64,
+    ids: &mut IdAllocator,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) -> (Bundle, String) {
+    let i = ids.next_id();
+    let (bundle_id, _) = get_ids(IdType::Id, ResourceType::Bundle, &i);
+    let (file_name, _) = get_ids(IdType::Id, generator.resource_type(), &i);
+    let mut context = GeneratorContext::new(&i, scenario, rng);
+
+    let mut entries: Vec<BundleEntry> = vec![];
+    for &dependency in generator.dependencies() {
+        let dependency =
+            get_generator(dependency).unwrap_or_else(|| panic!("{dependency:?} has no generator"));
+        entries.extend(dependency.generate(&i, &mut context, ids, scenario, rng));
+    }
+
+    if number == 1 {
+        entries.extend(generator.generate(&i, &mut context, ids, scenario, rng));
+    } else {
+        for _ in 0..number {
+            let i = ids.next_id();
+            let resource_entries = if generator.dependencies().is_empty() {
+                let mut context = GeneratorContext::new(&i, scenario, rng);
+                generator.generate(&i, &mut context, ids, scenario, rng)
+            } else {
+                generator.generate(&i, &mut context, ids, scenario, rng)
+            };
+            entries.extend(resource_entries);
+        }
+    }
+
+    (
+        bundle_svc::get_transaction_bundle(&bundle_id, entries),
+        file_name,
+    )
+}
+
+/// Generates the bundle entries for a single patient and all the resources that belong to the
+/// patient: the resources of every generator (in the order of [GENERATORS]), as many of each type as
+/// the scenario sets per patient. The first resource of each type gets the patient's id value, so that
+/// the references to the dependencies resolve; a recurrence is classified by another clinical TNM.
+pub fn get_patient_record(
+    ids: &mut IdAllocator,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) -> Vec<BundleEntry> {
+    let i = ids.next_id();
+    let mut context = GeneratorContext::new(&i, scenario, rng);
+
+    let mut entries: Vec<BundleEntry> = vec![];
+    for generator in GENERATORS {
+        for n in 0..generator.count_per_patient(scenario) {
+            let i = if n == 0 { i.clone() } else { ids.next_id() };
+            entries.extend(generator.generate(&i, &mut context, ids, scenario, rng));
+        }
+    }
+    entries.extend(observation_svc::get_recurrence_tnmc(
+        &context, ids, scenario, rng,
+    ));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::models::cli::IdStrategy;
+
+    fn get_entry_types(resource_type: ResourceType, number: u64) -> Vec<&'static str> {
+        let generator = get_generator(resource_type).expect("resource type has a generator");
+        let mut ids = IdAllocator::new(IdStrategy::Sequential, None, 1).expect("ids are allocated");
+        let mut rng = StdRng::seed_from_u64(1);
+        let (bundle, file_name) =
+            get_bundle(generator, number, &mut ids, &Scenario::default(), &mut rng);
+        assert!(
+            file_name.ends_with("-id-1"),
+            "file name does not match: {file_name}"
+        );
+
+        bundle
+            .entry
+            .iter()
+            .filter_map(|e| e.resource.as_ref())
+            .map(crate::utils::get_resource_type)
+            .collect()
+    }
+
+    #[test]
+    fn test_every_dependency_has_a_generator() {
+        for generator in GENERATORS {
+            for dependency in generator.dependencies() {
+                assert!(
+                    get_generator(*dependency).is_some(),
+                    "{dependency:?} of {:?} has no generator",
+                    generator.resource_type()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_generators_follow_their_dependencies() {
+        for (n, generator) in GENERATORS.iter().enumerate() {
+            for dependency in generator.dependencies() {
+                assert!(
+                    GENERATORS[..n]
+                        .iter()
+                        .any(|g| g.resource_type() == *dependency),
+                    "{:?} precedes its dependency {dependency:?}",
+                    generator.resource_type()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_bundle_adds_the_dependencies_once() {
+        assert_eq!(
+            get_entry_types(ResourceType::ObservationHistology, 1),
+            vec!["Patient", "Condition", "Specimen", "Observation"],
+            "entries of a single histology do not match"
+        );
+        assert_eq!(
+            get_entry_types(ResourceType::ProcedureOperation, 3),
+            vec![
+                "Patient",
+                "Condition",
+                "Procedure",
+                "Procedure",
+                "Procedure"
+            ],
+            "entries of multiple operations do not match"
+        );
+        // a pTNM is preceded by the cTNM it is derived from
+        assert_eq!(
+            get_entry_types(ResourceType::ObservationTNMp, 1),
+            vec!["Patient", "Observation", "Observation"],
+            "entries of a single pTNM do not match"
+        );
+        assert_eq!(
+            get_entry_types(ResourceType::Patient, 2),
+            vec!["Patient", "Patient"],
+            "entries of multiple patients do not match"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::io;
+
+use fhirbolt::model::r4b::resources::BundleEntry;
+use fhirbolt::model::r4b::Resource;
+
+use crate::models::cli::{FileLayout, Format, Layout};
+use crate::output_dir::OutputDir;
+use crate::utils;
+
+/// The destination of the patient records written by [crate::bundle_svc::write_cohort_records]
+pub trait RecordSink {
+    /// Writes the bundle entries of a single patient and all the resources that belong to the patient
+    fn write_record(&mut self, record: Vec<BundleEntry>) -> io::Result<()>;
+}
+
+/// Writes every resource of the patient records to its own file (named after the resource id), in a
+/// directory per patient or per resource type
+pub struct ResourceWriter<'a> {
+    out_dir: &'a mut OutputDir,
+    file_layout: FileLayout,
+    format: Format,
+    layout: Layout,
+    files: u64,
+}
+
+impl<'a> ResourceWriter<'a> {
+    pub fn new(
+        out_dir: &'a mut OutputDir,
+        file_layout: FileLayout,
+        format: Format,
+        layout: Layout,
+    ) -> Self {
+        Self {
+            out_dir,
+            file_layout,
+            format,
+            layout,
+            files: 0,
+        }
+    }
+
+    /// The number of files written so far
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    fn get_path(&self, resource: &Resource, patient_id: &str) -> io::Result<String> {
+        let id = get_id(resource).ok_or_else(|| invalid_data("resource has no id"))?;
+        let file_name = format!("{id}.{}", self.format.extension());
+        Ok(match self.file_layout {
+            FileLayout::Flat => file_name,
+            FileLayout::PerPatient => format!("{patient_id}/{file_name}"),
+            FileLayout::PerResourceType => {
+                format!("{}/{file_name}", utils::get_resource_type(resource))
+            }
+        })
+    }
+}
+
+impl RecordSink for ResourceWriter<'_> {
+    fn write_record(&mut self, record: Vec<BundleEntry>) -> io::Result<()> {
+        let resources: Vec<Resource> = record.into_iter().filter_map(|e| e.resource).collect();
+        let patient_id = resources
+            .iter()
+            .find(|r| matches!(r, Resource::Patient(_)))
+            .and_then(get_id)
+            .ok_or_else(|| invalid_data("record has no patient"))?;
+
+        for resource in resources {
+            let path = self.get_path(&resource, &patient_id)?;
+            let data = utils::try_serialize_resource(&resource, self.format, self.layout)
+                .map_err(|e| invalid_data(&e))?;
+            self.out_dir.write(&path, data.as_bytes(), Some(1))?;
+            self.files += 1;
+        }
+        Ok(())
+    }
+}
+
+fn get_id(resource: &Resource) -> Option<String> {
+    resource.id().and_then(|id| id.value.clone())
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Cannot write resource. Reason: {msg}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::bundle_svc;
+    use crate::models::cli::{IdStrategy, OverwritePolicy};
+    use crate::models::id_allocator::IdAllocator;
+    use crate::models::scenario::Scenario;
+
+    #[test]
+    fn test_write_record_lays_out_the_resources() {
+        let dir = env::temp_dir().join(format!("cce-fhir-gen-resources-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut out_dir = OutputDir::new(dir.clone(), OverwritePolicy::Warn, None);
+        let mut ids = IdAllocator::new(IdStrategy::Sequential, None, 1).expect("ids are allocated");
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for file_layout in [FileLayout::PerPatient, FileLayout::PerResourceType] {
+            let mut writer =
+                ResourceWriter::new(&mut out_dir, file_layout, Format::Xml, Layout::Plain);
+            bundle_svc::write_cohort_records(
+                &mut writer,
+                0..1,
+                &mut ids,
+                &Scenario::default(),
+                &mut rng,
+            )
+            .expect("record is written");
+            assert!(writer.files() > 1, "not every resource is written");
+        }
+
+        // the patient of the first record is written to its own directory
+        let patient = fs::read_to_string(dir.join("Patient-id-1/Patient-id-1.xml"))
+            .expect("patient file is written");
+        assert!(
+            patient.contains("<Patient xmlns"),
+            "patient file does not match: {patient}"
+        );
+        assert!(
+            dir.join("Condition")
+                .read_dir()
+                .is_ok_and(|mut d| d.next().is_some()),
+            "condition directory is empty"
+        );
+
+        fs::remove_dir_all(dir).expect("temp dir is removed");
+    }
+}
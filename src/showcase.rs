@@ -1,20 +1,48 @@
-use std::fs;
+use std::io::{self, Write};
+use std::process;
 
-use crate::models::cli::{Commands, OutputMode, ResourceType};
-use crate::utils::DATA_FOLDER;
+use log::{error, info};
 
-pub fn showcase_data(data: String, file_name: Option<String>, commands: Commands) {
+use crate::bundle_writer::BundleSink;
+use crate::fhir_client::{FhirClient, UploadSummary};
+use crate::models::cli::{
+    Commands, EntryRequest, FhirServerArgs, Format, Layout, OutputMode, ResourceType,
+};
+use crate::output_dir::{OutputDir, OutputFile};
+
+/// Shows, writes or uploads the generated data; a file is written to the output directory, along with
+/// the number of resources in it (if it has any)
+pub fn showcase_data(
+    data: String,
+    file_name: Option<String>,
+    format: Format,
+    resources: Option<u64>,
+    commands: Commands,
+    out_dir: &mut OutputDir,
+) {
     match commands {
         Commands::SyntheticData {
             resource_type,
             output_mode,
+            entry_request,
+            fhir_server,
             ..
         } => {
-            synthetic_data(data, file_name, resource_type, output_mode);
+            synthetic_data(
+                data,
+                file_name,
+                format,
+                resources,
+                resource_type,
+                output_mode,
+                entry_request,
+                &fhir_server,
+                out_dir,
+            );
         }
 
         Commands::Catalogue { output_mode } => {
-            catalogue(data, output_mode);
+            catalogue(data, output_mode, out_dir);
         }
 
         Commands::FhirProfiles => {
@@ -24,11 +52,171 @@ pub fn showcase_data(data: String, file_name: Option<String>, commands: Commands
     }
 }
 
+/// The output, which a (possibly split) cohort is streamed to, so that large data does not have to be
+/// held in memory. The bundles are shown on the screen, written to a file each (named after the bundle
+/// id, or numbered, if the cohort is split) or uploaded to the FHIR server.
+pub struct DataSink<'a> {
+    resource_type: ResourceType,
+    output_mode: OutputMode,
+    format: Format,
+    layout: Layout,
+    numbered_files: bool,
+    fhir_client: Option<FhirClient>,
+    bundle_id: String,
+    summary: UploadSummary,
+    failed_bundle_ids: Vec<String>,
+    out_dir: &'a mut OutputDir,
+}
+
+pub enum DataWriter {
+    Screen(io::Stdout),
+    File(OutputFile),
+    Upload(Vec<u8>),
+}
+
+impl<'a> DataSink<'a> {
+    pub fn new(
+        commands: &Commands,
+        format: Format,
+        layout: Layout,
+        numbered_files: bool,
+        out_dir: &'a mut OutputDir,
+    ) -> Self {
+        let Commands::SyntheticData {
+            resource_type,
+            output_mode,
+            entry_request,
+            fhir_server,
+            ..
+        } = commands
+        else {
+            unreachable!("only synthetic data is streamed");
+        };
+
+        let fhir_client = match output_mode {
+            OutputMode::ApiCall => Some(get_fhir_client(fhir_server, format, *entry_request)),
+            _ => None,
+        };
+
+        Self {
+            resource_type: *resource_type,
+            output_mode: *output_mode,
+            format,
+            layout,
+            numbered_files,
+            fhir_client,
+            bundle_id: String::new(),
+            summary: UploadSummary::default(),
+            failed_bundle_ids: Vec::new(),
+            out_dir,
+        }
+    }
+
+    /// Logs the total number of created and updated resources, if the bundles were uploaded, and
+    /// fails if any of the bundles could not be uploaded
+    pub fn finish(&self) -> Result<(), String> {
+        if self.fhir_client.is_none() {
+            return Ok(());
+        }
+
+        log_upload_summary("all bundles", self.summary);
+        if self.failed_bundle_ids.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} bundle(s) could not be uploaded: {}",
+                self.failed_bundle_ids.len(),
+                self.failed_bundle_ids.join(", ")
+            ))
+        }
+    }
+}
+
+impl BundleSink for DataSink<'_> {
+    type Writer = DataWriter;
+
+    fn format(&self) -> Format {
+        self.format
+    }
+
+    fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    fn open(&mut self, bundle_id: &str, bundle_no: u64) -> io::Result<DataWriter> {
+        self.bundle_id = bundle_id.to_string();
+        match self.output_mode {
+            OutputMode::Screen => {
+                println!("{}:", self.resource_type.as_str());
+                Ok(DataWriter::Screen(io::stdout()))
+            }
+
+            OutputMode::File => {
+                // split bundles are numbered, so that they can be loaded in order
+                let extension = self.format.extension();
+                let file_name = if self.numbered_files {
+                    format!("bundle-{bundle_no:04}.{extension}")
+                } else {
+                    format!("{bundle_id}.{extension}")
+                };
+                Ok(DataWriter::File(self.out_dir.create(&file_name)?))
+            }
+
+            OutputMode::ApiCall => Ok(DataWriter::Upload(Vec::new())),
+        }
+    }
+
+    fn close(&mut self, writer: DataWriter, entries: u64) -> io::Result<()> {
+        if let DataWriter::File(file) = writer {
+            return self.out_dir.close(file, Some(entries));
+        }
+        if let (DataWriter::Upload(data), Some(client)) = (writer, &self.fhir_client) {
+            // a failed upload does not stop the remaining bundles, the failures are summarized at
+            // the end
+            match client.upload_bundle(data) {
+                Ok(summary) => {
+                    log_upload_summary(&self.bundle_id, summary);
+                    self.summary.add(summary);
+                }
+                Err(e) => {
+                    error!("failed to upload {}: {e}", self.bundle_id);
+                    self.failed_bundle_ids.push(self.bundle_id.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for DataWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            DataWriter::Screen(w) => w.write(buf),
+            DataWriter::File(w) => w.write(buf),
+            DataWriter::Upload(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            DataWriter::Screen(w) => w.flush(),
+            DataWriter::File(w) => w.flush(),
+            DataWriter::Upload(w) => w.flush(),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn synthetic_data(
     data: String,
     file_name: Option<String>,
+    format: Format,
+    resources: Option<u64>,
     resource_type: ResourceType,
     output_mode: OutputMode,
+    entry_request: EntryRequest,
+    fhir_server: &FhirServerArgs,
+    out_dir: &mut OutputDir,
 ) {
     match output_mode {
         OutputMode::Screen => {
@@ -38,24 +226,29 @@ fn synthetic_data(
         }
 
         OutputMode::File => {
-            let dir_path = format!("./{DATA_FOLDER}");
-            if fs::exists(&dir_path).expect("dir exists error") {
-                println!("{} already exists.", dir_path);
-            } else {
-                println!("creating {}.", &dir_path);
-                fs::create_dir(&dir_path).expect("failed to create dir");
-            }
-
-            let with_extn = format!("{}.xml", file_name.unwrap_or("unknown_name".to_string()));
-            let file_path = format!("{}/{}", &dir_path, with_extn);
-            fs::write(file_path, data).expect("Unable to create XML file");
+            let with_extn = format!(
+                "{}.{}",
+                file_name.unwrap_or("unknown_name".to_string()),
+                format.extension()
+            );
+            write_file(out_dir, &with_extn, data, resources);
         }
 
-        OutputMode::ApiCall => todo!(),
+        OutputMode::ApiCall => {
+            let name = file_name.unwrap_or("unknown_name".to_string());
+            let client = get_fhir_client(fhir_server, format, entry_request);
+            match client.upload_bundle(data.into_bytes()) {
+                Ok(summary) => log_upload_summary(&name, summary),
+                Err(e) => {
+                    error!("failed to upload {name}: {e}");
+                    process::exit(1);
+                }
+            }
+        }
     }
 }
 
-fn catalogue(data: String, output_mode: OutputMode) {
+fn catalogue(data: String, output_mode: OutputMode, out_dir: &mut OutputDir) {
     match output_mode {
         OutputMode::Screen => {
             println!("Catalogue JSON:");
@@ -64,19 +257,38 @@ fn catalogue(data: String, output_mode: OutputMode) {
         }
 
         OutputMode::File => {
-            let dir_path = format!("./{DATA_FOLDER}");
-            if fs::exists(&dir_path).expect("dir exists error") {
-                println!("{} already exists.", dir_path);
-            } else {
-                println!("creating {}.", &dir_path);
-                fs::create_dir(&dir_path).expect("failed to create dir");
-            }
+            write_file(out_dir, "catalogue.json", data, None);
+        }
 
-            let with_extn = format!("catalogue.json");
-            let file_path = format!("{}/{}", &dir_path, with_extn);
-            fs::write(file_path, data).expect("Unable to create the catalogue.json file");
+        OutputMode::ApiCall => {
+            error!("the catalogue is not a FHIR resource and cannot be uploaded, use -o screen or -o file");
+            process::exit(1);
         }
+    }
+}
 
-        OutputMode::ApiCall => todo!(),
+fn write_file(out_dir: &mut OutputDir, file_name: &str, data: String, resources: Option<u64>) {
+    if let Err(e) = out_dir.write(file_name, data.as_bytes(), resources) {
+        error!("failed to write {file_name}: {e}");
+        out_dir.discard();
+        process::exit(1);
     }
 }
+
+fn get_fhir_client(
+    fhir_server: &FhirServerArgs,
+    format: Format,
+    entry_request: EntryRequest,
+) -> FhirClient {
+    FhirClient::new(fhir_server, format, entry_request).unwrap_or_else(|e| {
+        error!("{e}");
+        process::exit(1);
+    })
+}
+
+fn log_upload_summary(name: &str, summary: UploadSummary) {
+    info!(
+        "uploaded {name}: {} resource(s) created, {} updated",
+        summary.created, summary.updated
+    );
+}
@@ -1,23 +1,32 @@
-use std::ops::Range;
-
-use fake::{Fake, Faker};
+use chrono::NaiveDate;
 use fhirbolt::model::r4b::resources::{
     BundleEntry, Specimen, SpecimenCollection, SpecimenCollectionCollected,
 };
 use fhirbolt::model::r4b::types::{Code, CodeableConcept, Coding, DateTime, Id, Reference};
 use fhirbolt::model::r4b::Resource;
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
 
 use crate::extensions::option_ext::OptionExt;
 use crate::models::cli::ResourceType;
 use crate::models::enums::id_type::IdType;
 use crate::models::enums::sample_material_type::SampleMaterialType;
-use crate::utils::{
-    get_body_site_url, get_bundle_entry_request, get_full_url, get_ids, get_sample_mat_type_url,
-};
-
-pub fn get_specimen(id: &str, subject_ref: &str) -> Specimen {
-    let sample_material_type: SampleMaterialType = Faker.fake();
+use crate::models::id_allocator::IdAllocator;
+use crate::models::scenario::Scenario;
+use crate::models::tumor_entity::Diagnosis;
+use crate::resource_generator::{GeneratorContext, ResourceGenerator};
+use crate::utils::{get_body_site_url, get_bundle_entry_request, get_ids, get_sample_mat_type_url};
 
+/// Generates a specimen of the given sample material type; a derivative references the specimen it is
+/// extracted from as its parent. Only a tissue sample is taken from the body site of the tumour.
+pub fn get_specimen(
+    id: &str,
+    subject_ref: &str,
+    sample_material_type: SampleMaterialType,
+    parent_ref: Option<&str>,
+    diagnosis: &Diagnosis,
+    collected_date: NaiveDate,
+) -> Specimen {
     let oid = Id {
         value: Some(id.to_string()),
         ..Default::default()
@@ -27,23 +36,25 @@ pub fn get_specimen(id: &str, subject_ref: &str) -> Specimen {
         ..Default::default()
     };
     let collected = DateTime {
-        value: Some("2021-02-02".to_string()),
+        value: Some(collected_date.to_string()),
         ..Default::default()
     };
 
-    let bs_coding = Coding {
-        system: Some(get_body_site_url()),
-        version: None,
-        code: Some(Code::from("C26.8")),
-        ..Default::default()
-    };
-    let bs_cod_concept = CodeableConcept {
-        coding: vec![bs_coding],
-        ..Default::default()
-    };
+    let body_site = sample_material_type.is_tissue().then(|| {
+        let bs_coding = Coding {
+            system: Some(get_body_site_url()),
+            version: None,
+            code: Some(Code::from(diagnosis.topography)),
+            ..Default::default()
+        };
+        Box::new(CodeableConcept {
+            coding: vec![bs_coding],
+            ..Default::default()
+        })
+    });
     let specimen_collection = SpecimenCollection {
         collected: Some(SpecimenCollectionCollected::DateTime(collected)),
-        body_site: Some(Box::new(bs_cod_concept)),
+        body_site,
         ..Default::default()
     };
     let coding = Coding {
@@ -56,36 +67,209 @@ pub fn get_specimen(id: &str, subject_ref: &str) -> Specimen {
         coding: vec![coding],
         ..Default::default()
     };
+    let parent_rfrncs = parent_ref
+        .map(|parent_ref| Reference {
+            reference: Some(parent_ref.into()),
+            ..Default::default()
+        })
+        .into_iter()
+        .collect();
 
     Specimen {
         r#id: Some(oid),
         subject: Some(Box::new(subject_rfrnc)),
         collection: Some(specimen_collection),
         r#type: Some(Box::new(cod_concept)),
+        parent: parent_rfrncs,
         ..Default::default()
     }
 }
 
+/// Draws the sample material type of the specimen with the id built from the given value and generates
+/// it. A derivative is preceded by the (tissue or blood) specimen it is extracted from, which gets an id
+/// of its own.
+///
+/// Returns the specimens along with their reference ids.
+pub fn get_specimen_with_parent(
+    i: &str,
+    subject_ref: &str,
+    diagnosis: &Diagnosis,
+    collected_date: NaiveDate,
+    ids: &mut IdAllocator,
+    scenario: &Scenario,
+    rng: &mut StdRng,
+) -> Vec<(Specimen, String)> {
+    let sample_material_type = scenario.get_sample_material_type(rng);
+    let mut specimens = vec![];
+
+    let parent_ref_id = sample_material_type
+        .get_source_types()
+        .choose(rng)
+        .map(|&source_type| {
+            let (parent_id, parent_ref_id) =
+                get_ids(IdType::Id, ResourceType::Specimen, &ids.next_id());
+            let parent = get_specimen(
+                parent_id.as_str(),
+                subject_ref,
+                source_type,
+                None,
+                diagnosis,
+                collected_date,
+            );
+            specimens.push((parent, parent_ref_id.clone()));
+            parent_ref_id
+        });
+
+    let (specimen_id, specimen_ref_id) = get_ids(IdType::Id, ResourceType::Specimen, i);
+    let specimen = get_specimen(
+        specimen_id.as_str(),
+        subject_ref,
+        sample_material_type,
+        parent_ref_id.as_deref(),
+        diagnosis,
+        collected_date,
+    );
+    specimens.push((specimen, specimen_ref_id));
+    specimens
+}
+
 pub fn get_bundle_entry(specimen: Specimen, specimen_ref_id: &str) -> BundleEntry {
     BundleEntry {
-        full_url: Some(get_full_url(
-            specimen.clone().id.unwrap().value.unwrap().as_str(),
-        )),
         resource: Some(Resource::Specimen(Box::new(specimen.clone()))),
         request: get_bundle_entry_request("PUT", specimen_ref_id).into_some(),
         ..Default::default()
     }
 }
 
-pub fn get_specimens(subject_ref: &str, range: Range<u8>) -> Vec<(Specimen, String)> {
-    range
-        .map(|_| {
-            let i: u16 = Faker.fake();
-            let (specimen_id, specimen_ref_id) = get_ids(IdType::Id, ResourceType::Specimen, i);
-            (
-                get_specimen(specimen_id.as_str(), subject_ref),
-                specimen_ref_id,
-            )
-        })
+/// Generates a specimen of the patient
+pub struct SpecimenGenerator;
+
+impl ResourceGenerator for SpecimenGenerator {
+    fn resource_type(&self) -> ResourceType {
+        ResourceType::Specimen
+    }
+
+    fn dependencies(&self) -> &'static [ResourceType] {
+        &[ResourceType::Patient]
+    }
+
+    fn count_per_patient(&self, scenario: &Scenario) -> u8 {
+        scenario.resources_per_patient.specimens
+    }
+
+    fn generate(
+        &self,
+        i: &str,
+        context: &mut GeneratorContext,
+        ids: &mut IdAllocator,
+        scenario: &Scenario,
+        rng: &mut StdRng,
+    ) -> Vec<BundleEntry> {
+        get_specimen_with_parent(
+            i,
+            context.get_ref_id(ResourceType::Patient).as_str(),
+            &context.diagnosis,
+            context.timeline.specimen_collection_date,
+            ids,
+            scenario,
+            rng,
+        )
+        .into_iter()
+        .map(|(specimen, specimen_ref_id)| get_bundle_entry(specimen, specimen_ref_id.as_str()))
         .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::models::cli::IdStrategy;
+    use crate::models::patient_attributes::PatientAttributes;
+    use crate::models::timeline::Timeline;
+
+    #[test]
+    fn test_get_specimen_with_parent_extracts_a_derivative_from_its_source() {
+        let scenario = Scenario::from_toml("[weights.sample_material_type]\nCfDna = 1")
+            .expect("scenario is valid");
+        let mut ids = IdAllocator::new(IdStrategy::Sequential, None, 1).expect("ids are allocated");
+        let mut rng = StdRng::seed_from_u64(1);
+        let timeline = Timeline::new(&scenario, &mut rng);
+        let diagnosis = Diagnosis::new(
+            &PatientAttributes::new(&timeline, &scenario, &mut rng),
+            &mut rng,
+        );
+
+        let specimens = get_specimen_with_parent(
+            "1",
+            "Patient/Patient-id-1",
+            &diagnosis,
+            timeline.specimen_collection_date,
+            &mut ids,
+            &scenario,
+            &mut rng,
+        );
+        let get_type = |s: &Specimen| {
+            s.r#type
+                .as_ref()
+                .and_then(|t| t.coding[0].code.clone())
+                .and_then(|c| c.value)
+        };
+
+        let [(parent, parent_ref_id), (specimen, _)] = specimens.as_slice() else {
+            panic!("derivative is not preceded by its parent");
+        };
+        assert!(parent.parent.is_empty(), "source specimen has a parent");
+        assert!(
+            get_type(parent).is_some_and(|t| t.starts_with("plasma")),
+            "cfDNA is not extracted from plasma"
+        );
+        assert_eq!(
+            get_type(specimen).as_deref(),
+            Some("cf-dna"),
+            "type does not match"
+        );
+        assert_eq!(
+            specimen.parent[0].reference.clone().and_then(|r| r.value),
+            Some(parent_ref_id.clone()),
+            "parent does not match"
+        );
+        assert!(
+            get_body_site(parent).is_none() && get_body_site(specimen).is_none(),
+            "plasma or cfDNA has a body site"
+        );
+    }
+
+    fn get_body_site(specimen: &Specimen) -> Option<&CodeableConcept> {
+        specimen.collection.as_ref()?.body_site.as_deref()
+    }
+
+    #[test]
+    fn test_get_specimen_has_a_body_site_only_for_tissue() {
+        let scenario = Scenario::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        let timeline = Timeline::new(&scenario, &mut rng);
+        let diagnosis = Diagnosis::new(
+            &PatientAttributes::new(&timeline, &scenario, &mut rng),
+            &mut rng,
+        );
+
+        for sample_material_type in SampleMaterialType::iter() {
+            let specimen = get_specimen(
+                "Specimen-id-1",
+                "Patient/Patient-id-1",
+                sample_material_type,
+                None,
+                &diagnosis,
+                timeline.specimen_collection_date,
+            );
+            assert_eq!(
+                get_body_site(&specimen).is_some(),
+                sample_material_type.is_tissue(),
+                "body site of {sample_material_type} does not match"
+            );
+        }
+    }
 }
@@ -1,16 +1,22 @@
 use chrono::prelude::*;
 use fhirbolt::{
+    json,
     model::r4b::{
         resources::BundleEntryRequest,
         types::{Code, Uri},
+        Resource,
     },
     serde::SerializeResource,
     xml,
 };
+use sha2::{Digest, Sha256};
 
 use crate::{
     extensions::option_ext::OptionExt,
-    models::{cli::ResourceType, enums::id_type::IdType},
+    models::{
+        cli::{Format, Layout, ResourceType},
+        enums::id_type::IdType,
+    },
 };
 
 pub const CCE_URL: &str = "https://www.cancercoreeurope.eu";
@@ -24,6 +30,7 @@ pub const DATA_FOLDER: &str = "generated-data";
 pub const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
 
 const LOINC_URL: &str = "https://loinc.org";
+const ATC_URL: &str = "http://www.whocc.no/atc";
 // const FHIR_ENDPOINT: &str = "cce-localdatamanagement/fhir/";
 
 const UICC_STAGE_CS: &str = "UICCStageCS";
@@ -35,8 +42,6 @@ const SAMPLE_MATERIAL_TYPE_CS: &str = "SampleMaterialType";
 const TNMMCS_CS: &str = "TNMMCS";
 const TNMNCS_CS: &str = "TNMNCS";
 const TNMTCS_CS: &str = "TNMTCS";
-// const TNMR_SYMBOL_CS: &str = "TNMrSymbolCS";
-// const TNMY_SYMBOL_CS: &str = "TNMySymbolCS";
 
 pub const OBSERVATION_STATUS: &str = "final";
 
@@ -46,6 +51,13 @@ pub const CLINICAL_STAGE_GROUP_LOINC_CODE: &str = "21908-9";
 pub const CLINICAL_TUMOR_LOINC_CODE: &str = "21905-5";
 pub const CLINICAL_NODES_LOINC_CODE: &str = "21906-3";
 pub const CLINICAL_METASTASES_LOINC_CODE: &str = "21907-1";
+pub const PATHOLOGIC_STAGE_GROUP_LOINC_CODE: &str = "21902-2";
+pub const PATHOLOGIC_TUMOR_LOINC_CODE: &str = "21899-0";
+pub const PATHOLOGIC_NODES_LOINC_CODE: &str = "21900-6";
+pub const PATHOLOGIC_METASTASES_LOINC_CODE: &str = "21901-4";
+pub const TNM_Y_SYMBOL_LOINC_CODE: &str = "59479-6";
+pub const TNM_R_SYMBOL_LOINC_CODE: &str = "21983-2";
+pub const TNM_M_SYMBOL_LOINC_CODE: &str = "42030-7";
 pub const HISTOLOGY_BEHAVIOR_CANCER_LOINC_CODE: &str = "59847-4";
 
 pub fn get_fhir_url() -> String {
@@ -56,6 +68,10 @@ pub fn get_loinc_url() -> Uri {
     Uri::from(LOINC_URL)
 }
 
+pub fn get_atc_url() -> Uri {
+    Uri::from(ATC_URL)
+}
+
 // pub fn get_bh_fhir_api_url(server_name: &str) -> String {
 //     format!("https://{}/{FHIR_ENDPOINT}", server_name)
 // }
@@ -97,20 +113,53 @@ pub fn get_tnmt_url() -> Uri {
     Uri::from(get_code_system_url(TNMTCS_CS))
 }
 
-// pub fn get_tnmr_symbol_url() -> Uri {
-//     Uri::from(get_code_system_url(TNMR_SYMBOL_CS))
-// }
-
-// pub fn get_tnmy_symbol_url() -> Uri {
-//     Uri::from(get_code_system_url(TNMY_SYMBOL_CS))
-// }
-
 pub fn get_body_site_url() -> Uri {
     Uri::from("urn:oid:1.3.6.1.4.1.19376.1.3.11.36")
 }
 
-pub fn get_full_url(id: &str) -> Uri {
-    Uri::from(format!("{CCE_URL}/fhir-xml/examples/{}", id))
+/// Returns the URL of the resource with the given reference (e.g. `Patient/Patient-id-1`) at the
+/// server base, e.g. `http://localhost:8080/fhir/Patient/Patient-id-1`
+pub fn get_full_url(base: &str, ref_id: &str) -> Uri {
+    Uri::from(format!("{}/{ref_id}", base.trim_end_matches('/')))
+}
+
+/// The system of the patient identifiers, that conditional requests search the patients by
+pub fn get_patient_identifier_url() -> Uri {
+    get_identifier_url("Patient")
+}
+
+/// The system of the identifiers of the given resource type (e.g. `.../sid/condition-id`), that
+/// conditional requests search the resources by
+pub fn get_identifier_url(resource_type: &str) -> Uri {
+    Uri::from(format!(
+        "{}/sid/{}-id",
+        get_fhir_url(),
+        resource_type.to_lowercase()
+    ))
+}
+
+/// Returns the `urn:uuid:` fullUrl for the resource with the given reference (e.g.
+/// `Patient/Patient-id-1`); the uuid is derived from the reference, hence the same resource always
+/// gets the same fullUrl
+pub fn get_urn_uuid(ref_id: &str) -> String {
+    let hash = Sha256::digest(ref_id.as_bytes());
+    let bits = u128::from_be_bytes(hash[..16].try_into().expect("sha-256 has 32 bytes"));
+    // a (version 8) custom uuid, as it is neither random nor a name-based uuid with SHA-1
+    let uuid = (bits & !(0xf << 76) & !(0x3 << 62)) | (0x8 << 76) | (0x2 << 62);
+    format!("urn:uuid:{}", format_uuid(uuid))
+}
+
+/// Formats the uuid in its hyphenated form, e.g. `0e5c5d2a-9c3b-4e6f-8a1d-2b7f4c9e0d13`
+pub fn format_uuid(uuid: u128) -> String {
+    let hex = format!("{uuid:032x}");
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
 }
 
 pub fn get_min_date_time() -> DateTime<Utc> {
@@ -121,6 +170,13 @@ pub fn get_min_date_time_millenial() -> DateTime<Utc> {
     Utc.with_ymd_and_hms(1980, 1, 1, 0, 0, 0).unwrap()
 }
 
+/// Today, i.e. the latest date any generated data can have; a scenario can pin an earlier date (see
+/// [Scenario::get_max_date](crate::models::scenario::Scenario::get_max_date)), so that the same seed
+/// generates the same data on any day
+pub fn get_max_date_time() -> DateTime<Utc> {
+    Utc::now()
+}
+
 pub fn get_bundle_entry_request(method: &str, url: &str) -> BundleEntryRequest {
     BundleEntryRequest {
         method: Code {
@@ -132,18 +188,82 @@ pub fn get_bundle_entry_request(method: &str, url: &str) -> BundleEntryRequest {
     }
 }
 
-pub fn get_xml<T>(t: T, error_infix: &str) -> String
+/// Serializes the resource to XML or JSON in the given layout, an error is returned as the data
+pub fn serialize<T>(t: T, format: Format, layout: Layout, error_infix: &str) -> String
 where
     T: SerializeResource,
 {
-    let error_str = format!("Cannot serialize {} to XML.", error_infix);
-    let xml_result = xml::to_string(&t, None);
-    match xml_result {
-        Ok(xml) => xml,
-        Err(e) => format!("{error_str} Reason: {}", e.to_string()),
+    try_serialize(&t, format, layout).unwrap_or_else(|e| {
+        format!(
+            "Cannot serialize {error_infix} to {}. Reason: {e}",
+            format.extension().to_uppercase()
+        )
+    })
+}
+
+/// Serializes the resource to XML or JSON in the given layout
+pub fn try_serialize<T>(t: &T, format: Format, layout: Layout) -> Result<String, String>
+where
+    T: SerializeResource,
+{
+    let data = match format {
+        Format::Xml => xml::to_string(t, None).map_err(|e| e.to_string()),
+        Format::Json if layout.is_pretty() => {
+            json::to_string_pretty(t, None).map_err(|e| e.to_string())
+        }
+        Format::Json => json::to_string(t, None).map_err(|e| e.to_string()),
+    }?;
+    match layout {
+        Layout::Canonical => Ok(canonicalize(&data)),
+        Layout::Plain | Layout::Pretty => Ok(data),
+    }
+}
+
+/// Serializes a single resource of the types the generator creates (serializing the resource enum as
+/// such would build the serializers of all FHIR resource types)
+pub fn try_serialize_resource(
+    resource: &Resource,
+    format: Format,
+    layout: Layout,
+) -> Result<String, String> {
+    match resource {
+        Resource::Patient(r) => try_serialize(r.as_ref(), format, layout),
+        Resource::Condition(r) => try_serialize(r.as_ref(), format, layout),
+        Resource::Specimen(r) => try_serialize(r.as_ref(), format, layout),
+        Resource::Observation(r) => try_serialize(r.as_ref(), format, layout),
+        Resource::Procedure(r) => try_serialize(r.as_ref(), format, layout),
+        Resource::MedicationStatement(r) => try_serialize(r.as_ref(), format, layout),
+        Resource::Medication(r) => try_serialize(r.as_ref(), format, layout),
+        _ => Err(format!("{} is not generated", get_resource_type(resource))),
+    }
+}
+
+/// Returns the type of the resource, as it is named in FHIR
+pub fn get_resource_type(resource: &Resource) -> &'static str {
+    match resource {
+        Resource::Patient(_) => "Patient",
+        Resource::Condition(_) => "Condition",
+        Resource::Specimen(_) => "Specimen",
+        Resource::Observation(_) => "Observation",
+        Resource::Procedure(_) => "Procedure",
+        Resource::MedicationStatement(_) => "MedicationStatement",
+        Resource::Medication(_) => "Medication",
+        Resource::Bundle(_) => "Bundle",
+        _ => "Resource",
     }
 }
 
+/// Normalizes the whitespace of the serialized data: the lines are separated by line feeds, have no
+/// trailing whitespace and the last one ends with a line break
+pub fn canonicalize(data: &str) -> String {
+    let mut canonical = String::with_capacity(data.len() + 1);
+    for line in data.trim_end().lines() {
+        canonical.push_str(line.trim_end());
+        canonical.push('\n');
+    }
+    canonical
+}
+
 // pub fn print_fhir_data<T>(t: T, name: &str)
 // where
 //     T: SerializeResource,
@@ -153,7 +273,7 @@ where
 //     println!("");
 // }
 
-pub fn get_ids(id_type: IdType, res_type: ResourceType, i: u16) -> (String, String) {
+pub fn get_ids(id_type: IdType, res_type: ResourceType, i: &str) -> (String, String) {
     let id = format!("{}-{}", res_type.as_str(), id_type.get_id(i));
     let ref_id = format!("{}/{}", res_type.get_resource_group(), id);
     (id, ref_id)
@@ -165,14 +285,14 @@ mod tests {
 
     #[test]
     fn test_get_ids_with_id_type_id() {
-        let (bundle_id, bundle_ref_id) = get_ids(IdType::Id, ResourceType::Bundle, 1);
+        let (bundle_id, bundle_ref_id) = get_ids(IdType::Id, ResourceType::Bundle, "1");
         assert_eq!(bundle_id, "Bundle-id-1", "id does not match");
         assert_eq!(bundle_ref_id, "Bundle/Bundle-id-1", "ref id does not match");
     }
 
     #[test]
     fn test_get_ids_with_id_type_identifier() {
-        let (bundle_id, bundle_ref_id) = get_ids(IdType::Identifier, ResourceType::Bundle, 1);
+        let (bundle_id, bundle_ref_id) = get_ids(IdType::Identifier, ResourceType::Bundle, "1");
         assert_eq!(bundle_id, "Bundle-src-identifier-1", "id does not match");
         assert_eq!(
             bundle_ref_id, "Bundle/Bundle-src-identifier-1",
@@ -183,7 +303,7 @@ mod tests {
     #[test]
     fn test_get_ids_with_id_type_id_and_res_group() {
         let (obs_hist_id, obs_hist_ref_id) =
-            get_ids(IdType::Id, ResourceType::ObservationHistology, 1);
+            get_ids(IdType::Id, ResourceType::ObservationHistology, "1");
 
         assert_eq!(obs_hist_id, "Histology-id-1", "id does not match");
         assert_eq!(
@@ -195,7 +315,7 @@ mod tests {
     #[test]
     fn test_get_ids_with_id_type_identifier_and_res_group() {
         let (obs_hist_id, obs_hist_ref_id) =
-            get_ids(IdType::Identifier, ResourceType::ProcedureRadiotherapy, 1);
+            get_ids(IdType::Identifier, ResourceType::ProcedureRadiotherapy, "1");
 
         assert_eq!(
             obs_hist_id, "Radiotherapy-src-identifier-1",
@@ -216,9 +336,62 @@ mod tests {
 
     #[test]
     fn test_get_full_url() {
-        let id = "1";
-        let id_url = get_full_url(id);
-        let expected = Uri::from(format!("{CCE_URL}/fhir-xml/examples/1"));
+        let ref_id = "Patient/Patient-id-1";
+        let id_url = get_full_url("http://localhost:8080/fhir/", ref_id);
+        let expected = Uri::from("http://localhost:8080/fhir/Patient/Patient-id-1");
         assert_eq!(id_url, expected, "urls do not match");
     }
+
+    #[test]
+    fn test_get_urn_uuid_is_stable() {
+        let urn_uuid = get_urn_uuid("Patient/Patient-id-1");
+        assert_eq!(
+            urn_uuid,
+            get_urn_uuid("Patient/Patient-id-1"),
+            "uuid is not stable"
+        );
+        assert_ne!(
+            urn_uuid,
+            get_urn_uuid("Patient/Patient-id-2"),
+            "uuids are not unique"
+        );
+        let uuid = urn_uuid
+            .strip_prefix("urn:uuid:")
+            .expect("urn has the prefix");
+        assert_eq!(uuid.len(), 36, "uuid does not match: {uuid}");
+        assert_eq!(&uuid[14..15], "8", "uuid has not version 8: {uuid}");
+    }
+
+    #[test]
+    fn test_serialize_in_both_formats() {
+        let patient = fhirbolt::model::r4b::resources::Patient {
+            id: Some("Patient-id-1".into()),
+            ..Default::default()
+        };
+
+        let xml = serialize(patient.clone(), Format::Xml, Layout::Plain, "patient");
+        assert!(
+            xml.contains(r#"<id value="Patient-id-1"/>"#),
+            "XML does not match: {xml}"
+        );
+        let json = serialize(patient.clone(), Format::Json, Layout::Plain, "patient");
+        assert_eq!(
+            json, r#"{"resourceType":"Patient","id":"Patient-id-1"}"#,
+            "JSON does not match"
+        );
+        let json = serialize(patient, Format::Json, Layout::Canonical, "patient");
+        assert_eq!(
+            json, "{\n  \"resourceType\": \"Patient\",\n  \"id\": \"Patient-id-1\"\n}\n",
+            "canonical JSON does not match"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_normalizes_the_whitespace() {
+        assert_eq!(
+            canonicalize("<a>  \r\n  <b/>\t\r\n</a>\n\n"),
+            "<a>\n  <b/>\n</a>\n",
+            "whitespace is not normalized"
+        );
+    }
 }